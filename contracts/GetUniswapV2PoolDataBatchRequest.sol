@@ -14,6 +14,8 @@ interface IUniswapV2Pair {
             uint112 reserve1,
             uint32 blockTimestampLast
         );
+
+    function totalSupply() external view returns (uint256);
 }
 
 interface IERC20 {
@@ -33,6 +35,7 @@ contract GetUniswapV2PoolDataBatchRequest {
         uint112 reserve0;
         uint112 reserve1;
         uint32 blockTimestampLast;
+        uint256 totalSupply;
     }
 
     constructor(address[] memory pools) {
@@ -67,7 +70,7 @@ contract GetUniswapV2PoolDataBatchRequest {
                         (uint256)
                     );
 
-                    if (tokenADecimals == 0 || tokenADecimals > 255) {
+                    if (tokenADecimals > 255) {
                         continue;
                     } else {
                         poolData.tokenADecimals = uint8(tokenADecimals);
@@ -94,7 +97,7 @@ contract GetUniswapV2PoolDataBatchRequest {
                         (uint256)
                     );
 
-                    if (tokenBDecimals == 0 || tokenBDecimals > 255) {
+                    if (tokenBDecimals > 255) {
                         continue;
                     } else {
                         poolData.tokenBDecimals = uint8(tokenBDecimals);
@@ -111,6 +114,13 @@ contract GetUniswapV2PoolDataBatchRequest {
                 poolAddress
             ).getReserves();
 
+            //Get the LP token total supply, left at zero for pairs that do not expose it
+            try IUniswapV2Pair(poolAddress).totalSupply() returns (
+                uint256 lpTotalSupply
+            ) {
+                poolData.totalSupply = lpTotalSupply;
+            } catch {}
+
             allPoolData[i] = poolData;
         }
 
@@ -0,0 +1,25 @@
+#!/usr/bin/env bash
+#Compiles the batch request contracts in `contracts` with forge (see foundry.toml) and replaces the embedded artifacts with the build output.
+#Pass contract names to only rebuild those artifacts, ie. `scripts/build-batch-contracts.sh GetUniswapV2PoolDataBatchRequest`.
+set -euo pipefail
+
+cd "$(dirname "$0")/.."
+
+forge build --force --ast
+
+if [ "$#" -eq 0 ]; then
+    set -- $(find contracts -name "*.sol" -exec basename {} .sol \;)
+fi
+
+for contract in "$@"; do
+    artifact="out/$contract.sol/$contract.json"
+    destination=$(find src -name "${contract}ABI.json")
+
+    if [ -z "$destination" ]; then
+        echo "No embedded artifact for $contract, skipping"
+        continue
+    fi
+
+    cp "$artifact" "$destination"
+    echo "Updated $destination"
+done
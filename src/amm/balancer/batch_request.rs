@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::Token,
+    contract::Multicall,
+    providers::Middleware,
+    types::{H160, H256, U256},
+};
+
+use crate::errors::AMMError;
+
+use super::IBalancerVault;
+
+const POOL_TOKENS_MULTICALL_STEP: usize = 100;
+
+/// Tokens and balances of a Balancer pool, as registered in the Vault.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BalancerPoolTokens {
+    pub pool_id: H256,
+    pub tokens: Vec<H160>,
+    pub balances: Vec<U256>,
+    pub last_change_block: u64,
+}
+
+/// Calls `getPoolTokens` on `vault` for every pool id, aggregating the calls with Multicall3.
+/// Pools can hold any number of tokens. Pool ids that are not registered in the Vault revert and are left out of the result.
+pub async fn get_pool_tokens_batch_request<M: Middleware>(
+    vault: H160,
+    pool_ids: &[H256],
+    middleware: Arc<M>,
+) -> Result<Vec<BalancerPoolTokens>, AMMError<M>> {
+    let balancer_vault = IBalancerVault::new(vault, middleware.clone());
+
+    let mut pool_tokens = vec![];
+    let mut multicall = Multicall::new(middleware.clone(), None)
+        .await
+        .map_err(AMMError::MulticallError)?;
+
+    for pool_id_chunk in pool_ids.chunks(POOL_TOKENS_MULTICALL_STEP) {
+        multicall.clear_calls();
+        for pool_id in pool_id_chunk {
+            multicall.add_call(balancer_vault.get_pool_tokens(pool_id.to_fixed_bytes()), true);
+        }
+
+        let results = multicall
+            .call_raw()
+            .await
+            .map_err(AMMError::MulticallError)?;
+
+        for (pool_id, result) in pool_id_chunk.iter().zip(results) {
+            if let Ok(return_data) = result {
+                let pool = populate_pool_tokens_from_tokens(*pool_id, return_data)
+                    .ok_or(AMMError::BatchRequestError(vault))?;
+
+                pool_tokens.push(pool);
+            }
+        }
+    }
+
+    Ok(pool_tokens)
+}
+
+//Decodes the `(address[], uint256[], uint256)` returned by `getPoolTokens`
+fn populate_pool_tokens_from_tokens(
+    pool_id: H256,
+    return_data: Token,
+) -> Option<BalancerPoolTokens> {
+    let return_data = return_data.into_tuple()?;
+
+    let tokens = return_data
+        .first()?
+        .to_owned()
+        .into_array()?
+        .into_iter()
+        .map(|token| token.into_address())
+        .collect::<Option<Vec<H160>>>()?;
+
+    let balances = return_data
+        .get(1)?
+        .to_owned()
+        .into_array()?
+        .into_iter()
+        .map(|balance| balance.into_uint())
+        .collect::<Option<Vec<U256>>>()?;
+
+    if tokens.len() != balances.len() {
+        return None;
+    }
+
+    Some(BalancerPoolTokens {
+        pool_id,
+        tokens,
+        balances,
+        last_change_block: return_data.get(2)?.to_owned().into_uint()?.as_u64(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        abi::Token,
+        providers::{Http, Provider},
+        types::{H160, H256, U256},
+    };
+
+    use crate::amm::balancer::BALANCER_VAULT;
+
+    use super::{get_pool_tokens_batch_request, populate_pool_tokens_from_tokens};
+
+    #[test]
+    fn test_populate_pool_tokens_from_tokens() {
+        let pool_id = H256::from_low_u64_be(1);
+
+        //Pools with different token counts
+        for token_count in [2, 3, 8] {
+            let return_data = Token::Tuple(vec![
+                Token::Array(
+                    (0..token_count)
+                        .map(|i| Token::Address(H160::from_low_u64_be(i)))
+                        .collect(),
+                ),
+                Token::Array(
+                    (0..token_count)
+                        .map(|i| Token::Uint(U256::from(i * 1000)))
+                        .collect(),
+                ),
+                Token::Uint(U256::from(17000000)),
+            ]);
+
+            let pool = populate_pool_tokens_from_tokens(pool_id, return_data)
+                .expect("Pool tokens should be decoded");
+
+            assert_eq!(pool.pool_id, pool_id);
+            assert_eq!(pool.tokens.len(), token_count as usize);
+            assert_eq!(pool.balances.len(), token_count as usize);
+            assert_eq!(pool.balances[1], U256::from(1000));
+            assert_eq!(pool.last_change_block, 17000000);
+        }
+
+        //Mismatched tokens and balances
+        let return_data = Token::Tuple(vec![
+            Token::Array(vec![Token::Address(H160::from_low_u64_be(1))]),
+            Token::Array(vec![]),
+            Token::Uint(U256::zero()),
+        ]);
+        assert!(populate_pool_tokens_from_tokens(pool_id, return_data).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_tokens_batch_request() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //80BAL/20WETH weighted pool
+        let pool_id =
+            H256::from_str("0x5c6ee304399dbdb9c8ef030ab642b10820db8f56000200000000000000000014")?;
+        let weth = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let pool_tokens = get_pool_tokens_batch_request(
+            BALANCER_VAULT,
+            &[pool_id, H256::zero()],
+            middleware,
+        )
+        .await?;
+
+        //The unregistered pool id is left out
+        assert_eq!(pool_tokens.len(), 1);
+        assert_eq!(pool_tokens[0].pool_id, pool_id);
+        assert_eq!(pool_tokens[0].tokens.len(), 2);
+        assert!(pool_tokens[0].tokens.contains(&weth));
+        assert!(pool_tokens[0].balances.iter().all(|balance| !balance.is_zero()));
+
+        Ok(())
+    }
+}
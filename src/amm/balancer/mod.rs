@@ -0,0 +1,15 @@
+pub mod batch_request;
+
+use ethers::{prelude::abigen, types::H160};
+
+abigen!(
+    IBalancerVault,
+    r#"[
+        function getPoolTokens(bytes32 poolId) external view returns (address[] tokens, uint256[] balances, uint256 lastChangeBlock)
+    ]"#;
+);
+
+// Balancer V2 Vault, deployed at the same address on every chain
+pub const BALANCER_VAULT: H160 = H160([
+    186, 18, 34, 34, 34, 34, 141, 139, 164, 69, 149, 138, 117, 160, 112, 77, 86, 107, 242, 200,
+]);
@@ -0,0 +1,221 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::Token,
+    providers::Middleware,
+    types::{
+        transaction::eip2718::TypedTransaction, transaction::eip2930::AccessList, BlockNumber,
+        Bytes, H160,
+    },
+};
+
+use crate::{errors::AMMError, middleware::rate_limit::RateLimiter};
+
+/// Options applied to the `eth_call` made by a batch request.
+#[derive(Debug, Clone, Default)]
+pub struct CallOptions {
+    /// Sender of the call, for nodes or middleware that require a `from` address.
+    pub from: Option<H160>,
+    /// Access list attached to the call. Ignored for legacy transactions.
+    pub access_list: Option<AccessList>,
+    /// Deployment bytecode replacing the embedded bytecode of the batch contract, ie. for chains where the default bytecode uses unsupported opcodes.
+    /// The contract must take the same constructor arguments and return data in the same layout as the contract it replaces.
+    pub bytecode: Option<Bytes>,
+    /// Bit width of the reserves returned by Uniswap V2 pool data batch requests, 112 if unset.
+    /// Forks storing reserves in wider fields, ie. uint128, also need a batch contract returning them at that width, see `bytecode`.
+    /// Reserves returned wider than this are rejected with `AMMError::ReserveOverflow`.
+    pub reserve_bits: Option<u8>,
+    /// Block the call is made at, the latest block if unset. Historical blocks require an archive node.
+    /// Batch requests that take an explicit block number use it over this option.
+    pub block: Option<BlockNumber>,
+    /// Rate limiter acquired before each batch contract call, shared across the calls of a sync to stay under the request limit of the provider.
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// Also reads the fee growth globals of Uniswap V3 pools when populating their data, with an extra Multicall3 call per batch. Off by default.
+    pub fee_growth_globals: bool,
+}
+
+impl CallOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from(mut self, from: H160) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    pub fn access_list(mut self, access_list: AccessList) -> Self {
+        self.access_list = Some(access_list);
+        self
+    }
+
+    pub fn bytecode(mut self, bytecode: Bytes) -> Self {
+        self.bytecode = Some(bytecode);
+        self
+    }
+
+    pub fn reserve_bits(mut self, reserve_bits: u8) -> Self {
+        self.reserve_bits = Some(reserve_bits);
+        self
+    }
+
+    pub fn block(mut self, block: impl Into<BlockNumber>) -> Self {
+        self.block = Some(block.into());
+        self
+    }
+
+    pub fn rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub fn fee_growth_globals(mut self, fee_growth_globals: bool) -> Self {
+        self.fee_growth_globals = fee_growth_globals;
+        self
+    }
+
+    /// Waits for the rate limiter to allow a request, returning immediately if no rate limiter is set.
+    pub async fn acquire(&self) {
+        if let Some(rate_limiter) = &self.rate_limiter {
+            rate_limiter.acquire().await;
+        }
+    }
+
+    /// Returns the number of the block a batch request is made at, `block` or the latest block if unset.
+    /// Batch requests are made at the resolved number rather than a tag, so that every read is served from the same block even when the provider balances calls across nodes.
+    pub async fn resolve_block<M: Middleware>(
+        &self,
+        middleware: Arc<M>,
+    ) -> Result<u64, AMMError<M>> {
+        match self.block {
+            Some(BlockNumber::Number(block_number)) => Ok(block_number.as_u64()),
+            Some(block) => middleware
+                .get_block(block)
+                .await
+                .map_err(AMMError::MiddlewareError)?
+                .and_then(|block| block.number)
+                .map(|block_number| block_number.as_u64())
+                .ok_or(AMMError::BlockNumberNotFound),
+            None => Ok(middleware
+                .get_block_number()
+                .await
+                .map_err(AMMError::MiddlewareError)?
+                .as_u64()),
+        }
+    }
+
+    /// Sets the configured fields on the deployment transaction of a batch contract, leaving the rest untouched.
+    /// `constructor_args` are the arguments the batch contract is deployed with, needed to rebuild the calldata when the bytecode is replaced.
+    pub fn apply(&self, tx: &mut TypedTransaction, constructor_args: Token) {
+        if let Some(from) = self.from {
+            tx.set_from(from);
+        }
+
+        if let Some(access_list) = &self.access_list {
+            tx.set_access_list(access_list.clone());
+        }
+
+        if let Some(bytecode) = &self.bytecode {
+            //Constructor arguments are encoded as a tuple of their own, following the bytecode
+            let constructor_args = match constructor_args {
+                Token::Tuple(tokens) => tokens,
+                token => vec![token],
+            };
+
+            let mut data = bytecode.to_vec();
+            data.extend(ethers::abi::encode(&constructor_args));
+            tx.set_data(data.into());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use ethers::types::{
+        transaction::eip2930::AccessListItem, Eip1559TransactionRequest, H160, H256, U256,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_apply_call_options() {
+        let from = H160::from_low_u64_be(1);
+        let access_list = AccessList(vec![AccessListItem {
+            address: H160::from_low_u64_be(2),
+            storage_keys: vec![H256::zero()],
+        }]);
+
+        let mut tx = TypedTransaction::Eip1559(Eip1559TransactionRequest::new());
+        CallOptions::new()
+            .from(from)
+            .access_list(access_list.clone())
+            .apply(&mut tx, Token::Tuple(vec![]));
+
+        assert_eq!(tx.from(), Some(&from));
+        assert_eq!(tx.access_list(), Some(&access_list));
+
+        //Default options should not modify the transaction
+        let mut tx = TypedTransaction::Eip1559(Eip1559TransactionRequest::new());
+        CallOptions::default().apply(&mut tx, Token::Tuple(vec![]));
+
+        assert_eq!(tx.from(), None);
+        assert_eq!(tx.access_list(), None);
+        assert_eq!(tx.data(), None);
+    }
+
+    #[test]
+    fn test_block() {
+        assert_eq!(CallOptions::default().block, None);
+        assert_eq!(
+            CallOptions::new().block(17000000).block,
+            Some(BlockNumber::Number(17000000.into()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let call_options = CallOptions::new().rate_limiter(Arc::new(RateLimiter::new(20)));
+
+        //The first requests are served from the full bucket
+        let start = Instant::now();
+        for _ in 0..20 {
+            call_options.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(25));
+
+        //The bucket is empty, so the next request waits for a refill
+        call_options.acquire().await;
+        assert!(start.elapsed() >= Duration::from_millis(45));
+
+        //Without a rate limiter requests are not paced
+        let start = Instant::now();
+        for _ in 0..100 {
+            CallOptions::default().acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(25));
+    }
+
+    #[test]
+    fn test_apply_bytecode() {
+        let bytecode = Bytes::from(vec![0x60, 0x80, 0x60, 0x40]);
+        let constructor_args = Token::Tuple(vec![
+            Token::Uint(U256::from(1)),
+            Token::Array(vec![Token::Address(H160::from_low_u64_be(2))]),
+        ]);
+
+        let mut tx = TypedTransaction::Eip1559(Eip1559TransactionRequest::new());
+        CallOptions::new()
+            .bytecode(bytecode.clone())
+            .apply(&mut tx, constructor_args);
+
+        let mut expected_data = bytecode.to_vec();
+        expected_data.extend(ethers::abi::encode(&[
+            Token::Uint(U256::from(1)),
+            Token::Array(vec![Token::Address(H160::from_low_u64_be(2))]),
+        ]));
+
+        assert_eq!(tx.data(), Some(&Bytes::from(expected_data)));
+    }
+}
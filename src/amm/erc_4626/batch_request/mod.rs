@@ -5,7 +5,10 @@ use ethers::{
 };
 use std::sync::Arc;
 
-use crate::{amm::AutomatedMarketMaker, errors::AMMError};
+use crate::{
+    amm::{call_options::CallOptions, AutomatedMarketMaker},
+    errors::AMMError,
+};
 
 use ethers::prelude::abigen;
 
@@ -16,6 +19,24 @@ abigen!(
         "src/amm/erc_4626/batch_request/GetERC4626VaultDataBatchRequestABI.json";
 );
 
+lazy_static::lazy_static! {
+    static ref VAULT_DATA_RETURN_TYPES: Vec<ParamType> =
+        vec![ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Address,   // vault token
+            ParamType::Uint(8),   // vault token decimals
+            ParamType::Address,   // asset token
+            ParamType::Uint(8),   // asset token decimals
+            ParamType::Uint(256), // vault token reserve
+            ParamType::Uint(256), // asset token reserve
+            ParamType::Uint(256), // deposit fee delta 1
+            ParamType::Uint(256), // deposit fee delta 2
+            ParamType::Uint(256), // deposit not fee
+            ParamType::Uint(256), // withdraw fee delta 1
+            ParamType::Uint(256), // withdraw fee delta 2
+            ParamType::Uint(256), // withdraw no fee
+        ])))];
+}
+
 fn populate_vault_data_from_tokens(
     mut vault: ERC4626Vault,
     tokens: Vec<Token>,
@@ -63,40 +84,34 @@ fn populate_vault_data_from_tokens(
     Some(vault)
 }
 
+/// Populates the data of `vault`, returning the number of the block the vault was read at.
 pub async fn get_4626_vault_data_batch_request<M: Middleware>(
     vault: &mut ERC4626Vault,
+    call_options: &CallOptions,
     middleware: Arc<M>,
-) -> Result<(), AMMError<M>> {
+) -> Result<u64, AMMError<M>> {
     let constructor_args =
         Token::Tuple(vec![Token::Array(vec![Token::Address(vault.vault_token)])]);
 
-    let deployer = IGetERC4626VaultDataBatchRequest::deploy(
+    let mut deployer = IGetERC4626VaultDataBatchRequest::deploy(
         middleware.clone(), 
-        constructor_args,
+        constructor_args.clone(),
     )
     .map_err(|e| AMMError::ContractError("get_4626_vault_data_batch_request", vault.vault_token, e))?;
+    let block_number = call_options.resolve_block(middleware.clone()).await?;
+    deployer = deployer.block(block_number);
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
 
     let return_data: Bytes = deployer
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("get_4626_vault_data_batch_request", vault.vault_token, e))?;
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Array(Box::new(ParamType::Tuple(vec![
-            ParamType::Address,   // vault token
-            ParamType::Uint(8),   // vault token decimals
-            ParamType::Address,   // asset token
-            ParamType::Uint(8),   // asset token decimals
-            ParamType::Uint(256), // vault token reserve
-            ParamType::Uint(256), // asset token reserve
-            ParamType::Uint(256), // deposit fee delta 1
-            ParamType::Uint(256), // deposit fee delta 2
-            ParamType::Uint(256), // deposit not fee
-            ParamType::Uint(256), // withdraw fee delta 1
-            ParamType::Uint(256), // withdraw fee delta 2
-            ParamType::Uint(256), // withdraw no fee
-        ])))],
-        &return_data,
-    )?;
+        .map_err(|e| AMMError::batch_call_error("get_4626_vault_data_batch_request", vault.vault_token, e))?;
+    let return_data_tokens = ethers::abi::decode(&VAULT_DATA_RETURN_TYPES, &return_data)
+        .map_err(|source| AMMError::AbiDecodeError {
+            context: "get_4626_vault_data_batch_request",
+            source,
+        })?;
 
     for tokens in return_data_tokens {
         if let Some(tokens_arr) = tokens.into_array() {
@@ -111,5 +126,5 @@ pub async fn get_4626_vault_data_batch_request<M: Middleware>(
         }
     }
 
-    Ok(())
+    Ok(block_number)
 }
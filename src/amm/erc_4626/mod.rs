@@ -1,6 +1,6 @@
 pub mod batch_request;
 
-use std::{cmp::Ordering, sync::Arc};
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use ethers::{
@@ -12,13 +12,13 @@ use ethers::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    amm::AutomatedMarketMaker,
+    amm::{call_options::CallOptions, AutomatedMarketMaker},
     errors::{AMMError, ArithmeticError, EventLogError, SwapSimulationError},
 };
 
 use ethers::prelude::abigen;
 
-use super::uniswap_v2::{div_uu, q64_to_f64, U128_0X10000000000000000};
+use super::math::{div_uu, normalize_decimals, q64_to_f64, U128_0X10000000000000000};
 
 abigen!(
     IERC4626Vault,
@@ -42,6 +42,9 @@ pub const WITHDRAW_EVENT_SIGNATURE: H256 = H256([
     74, 44, 117, 192, 31, 201, 102, 114, 50, 200, 219,
 ]);
 
+// Approximate gas used by a deposit or redeem on the vault, including the asset transfer
+pub const SWAP_GAS_ESTIMATE: u64 = 110000;
+
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ERC4626Vault {
     pub vault_token: H160, // token received from depositing, i.e. shares token
@@ -105,7 +108,12 @@ impl AutomatedMarketMaker for ERC4626Vault {
         _block_number: Option<u64>,
         middleware: Arc<M>,
     ) -> Result<(), AMMError<M>> {
-        batch_request::get_4626_vault_data_batch_request(self, middleware.clone()).await?;
+        batch_request::get_4626_vault_data_batch_request(
+            self,
+            &CallOptions::default(),
+            middleware.clone(),
+        )
+        .await?;
 
         Ok(())
     }
@@ -147,6 +155,25 @@ impl AutomatedMarketMaker for ERC4626Vault {
             self.vault_token
         }
     }
+
+    //Deposits and withdrawals are charged separately, the higher of the two fees is returned
+    fn fee_bps(&self) -> u32 {
+        self.deposit_fee.max(self.withdraw_fee)
+    }
+
+    fn liquidity(&self) -> U256 {
+        //The square root of a product of two U256 always fits in a U256
+        U256::try_from(
+            self.vault_reserve
+                .full_mul(self.asset_reserve)
+                .integer_sqrt(),
+        )
+        .unwrap_or(U256::MAX)
+    }
+
+    fn swap_gas_estimate(&self) -> u64 {
+        SWAP_GAS_ESTIMATE
+    }
 }
 
 impl ERC4626Vault {
@@ -231,19 +258,13 @@ impl ERC4626Vault {
     }
 
     pub fn calculate_price_64_x_64(&self, base_token: H160) -> Result<u128, ArithmeticError> {
-        let decimal_shift = self.vault_token_decimals as i8 - self.asset_token_decimals as i8;
-
         // Normalize reserves by decimal shift
-        let (r_v, r_a) = match decimal_shift.cmp(&0) {
-            Ordering::Less => (
-                self.vault_reserve * U256::from(10u128.pow(decimal_shift.unsigned_abs() as u32)),
-                self.asset_reserve,
-            ),
-            _ => (
-                self.vault_reserve,
-                self.asset_reserve * U256::from(10u128.pow(decimal_shift as u32)),
-            ),
-        };
+        let (r_v, r_a) = normalize_decimals(
+            self.vault_reserve,
+            self.vault_token_decimals,
+            self.asset_reserve,
+            self.asset_token_decimals,
+        )?;
 
         // Withdraw
         if base_token == self.vault_token {
@@ -444,4 +465,43 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_fee_bps() {
+        let mut vault = ERC4626Vault {
+            deposit_fee: 10,
+            withdraw_fee: 25,
+            ..Default::default()
+        };
+        assert_eq!(vault.fee_bps(), 25);
+
+        vault.deposit_fee = 50;
+        assert_eq!(vault.fee_bps(), 50);
+    }
+
+    #[test]
+    fn test_calculate_price_zero_to_eighteen_decimals() -> eyre::Result<()> {
+        let vault_token = H160::from_low_u64_be(1);
+        let asset_token = H160::from_low_u64_be(2);
+
+        for vault_token_decimals in [0_u8, 2, 6, 18] {
+            for asset_token_decimals in [0_u8, 2, 6, 18] {
+                //1000 shares backed by 1100 assets
+                let vault = ERC4626Vault {
+                    vault_token,
+                    vault_token_decimals,
+                    asset_token,
+                    asset_token_decimals,
+                    vault_reserve: U256::from(1000) * U256::exp10(vault_token_decimals as usize),
+                    asset_reserve: U256::from(1100) * U256::exp10(asset_token_decimals as usize),
+                    ..Default::default()
+                };
+
+                assert!((vault.calculate_price(vault_token)? - 1.1).abs() < 1e-12);
+                assert!((vault.calculate_price(asset_token)? - 1.0 / 1.1).abs() < 1e-12);
+            }
+        }
+
+        Ok(())
+    }
 }
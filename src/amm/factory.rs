@@ -10,11 +10,11 @@ use tokio::task::JoinHandle;
 
 use crate::errors::{AMMError, EventLogError};
 
-use super::{
-    uniswap_v2::factory::{UniswapV2Factory, PAIR_CREATED_EVENT_SIGNATURE},
-    uniswap_v3::factory::{UniswapV3Factory, POOL_CREATED_EVENT_SIGNATURE},
-    AMM,
-};
+#[cfg(feature = "uniswap-v2")]
+use super::uniswap_v2::factory::{UniswapV2Factory, PAIR_CREATED_EVENT_SIGNATURE};
+#[cfg(feature = "uniswap-v3")]
+use super::uniswap_v3::factory::{UniswapV3Factory, POOL_CREATED_EVENT_SIGNATURE};
+use super::AMM;
 
 pub const TASK_LIMIT: usize = 10;
 
@@ -51,7 +51,9 @@ pub trait AutomatedMarketMakerFactory {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Factory {
+    #[cfg(feature = "uniswap-v2")]
     UniswapV2Factory(UniswapV2Factory),
+    #[cfg(feature = "uniswap-v3")]
     UniswapV3Factory(UniswapV3Factory),
 }
 
@@ -59,14 +61,18 @@ pub enum Factory {
 impl AutomatedMarketMakerFactory for Factory {
     fn address(&self) -> H160 {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(factory) => factory.address(),
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(factory) => factory.address(),
         }
     }
 
     fn amm_created_event_signature(&self) -> H256 {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(factory) => factory.amm_created_event_signature(),
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(factory) => factory.amm_created_event_signature(),
         }
     }
@@ -77,14 +83,18 @@ impl AutomatedMarketMakerFactory for Factory {
         middleware: Arc<M>,
     ) -> Result<AMM, AMMError<M>> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(factory) => factory.new_amm_from_log(log, middleware).await,
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(factory) => factory.new_amm_from_log(log, middleware).await,
         }
     }
 
     fn new_empty_amm_from_log(&self, log: Log) -> Result<AMM, ethers::abi::Error> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(factory) => factory.new_empty_amm_from_log(log),
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(factory) => factory.new_empty_amm_from_log(log),
         }
     }
@@ -96,9 +106,11 @@ impl AutomatedMarketMakerFactory for Factory {
         step: u64,
     ) -> Result<Vec<AMM>, AMMError<M>> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(factory) => {
                 factory.get_all_amms(to_block, middleware, step).await
             }
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(factory) => {
                 factory.get_all_amms(to_block, middleware, step).await
             }
@@ -112,9 +124,13 @@ impl AutomatedMarketMakerFactory for Factory {
         middleware: Arc<M>,
     ) -> Result<(), AMMError<M>> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(factory) => {
-                factory.populate_amm_data(amms, None, middleware).await
+                factory
+                    .populate_amm_data(amms, block_number, middleware)
+                    .await
             }
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(factory) => {
                 factory
                     .populate_amm_data(amms, block_number, middleware)
@@ -125,7 +141,9 @@ impl AutomatedMarketMakerFactory for Factory {
 
     fn creation_block(&self) -> u64 {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(uniswap_v2_factory) => uniswap_v2_factory.creation_block,
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(uniswap_v3_factory) => uniswap_v3_factory.creation_block,
         }
     }
@@ -208,12 +226,16 @@ impl TryFrom<H256> for Factory {
     type Error = EventLogError;
 
     fn try_from(value: H256) -> Result<Self, Self::Error> {
+        #[cfg(feature = "uniswap-v2")]
         if value == PAIR_CREATED_EVENT_SIGNATURE {
-            Ok(Factory::UniswapV2Factory(UniswapV2Factory::default()))
-        } else if value == POOL_CREATED_EVENT_SIGNATURE {
-            Ok(Factory::UniswapV3Factory(UniswapV3Factory::default()))
-        } else {
-            return Err(EventLogError::InvalidEventSignature);
+            return Ok(Factory::UniswapV2Factory(UniswapV2Factory::default()));
         }
+
+        #[cfg(feature = "uniswap-v3")]
+        if value == POOL_CREATED_EVENT_SIGNATURE {
+            return Ok(Factory::UniswapV3Factory(UniswapV3Factory::default()));
+        }
+
+        Err(EventLogError::InvalidEventSignature)
     }
 }
@@ -1,9 +1,19 @@
+pub mod balancer;
+pub mod call_options;
 pub mod erc_4626;
 pub mod factory;
+pub mod math;
+pub mod tvl;
+#[cfg(feature = "uniswap-v2")]
 pub mod uniswap_v2;
+#[cfg(feature = "uniswap-v3")]
 pub mod uniswap_v3;
 
-use std::sync::Arc;
+use std::{
+    cmp::Reverse,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use async_trait::async_trait;
 use ethers::{
@@ -14,15 +24,58 @@ use serde::{Deserialize, Serialize};
 
 use crate::errors::{AMMError, ArithmeticError, EventLogError, SwapSimulationError};
 
-use self::{erc_4626::ERC4626Vault, uniswap_v2::UniswapV2Pool, uniswap_v3::UniswapV3Pool};
+#[cfg(feature = "uniswap-v2")]
+use self::uniswap_v2::UniswapV2Pool;
+#[cfg(feature = "uniswap-v3")]
+use self::uniswap_v3::UniswapV3Pool;
+use self::{erc_4626::ERC4626Vault, math::u256_to_f64};
 
 #[async_trait]
 pub trait AutomatedMarketMaker {
     fn address(&self) -> H160;
     async fn sync<M: Middleware>(&mut self, middleware: Arc<M>) -> Result<(), AMMError<M>>;
     fn sync_on_event_signatures(&self) -> Vec<H256>;
+    /// Returns every token of the AMM, two for pairs and vaults but any number for multi-asset pools.
     fn tokens(&self) -> Vec<H160>;
+    /// Returns the number of tokens of the AMM.
+    fn token_count(&self) -> usize {
+        self.tokens().len()
+    }
+    /// Returns true if `token` is any of the tokens of the AMM.
+    fn contains_token(&self, token: H160) -> bool {
+        self.tokens().contains(&token)
+    }
     fn calculate_price(&self, base_token: H160) -> Result<f64, ArithmeticError>;
+    /// Returns the price of the first token in the second and of the second token in the first, each computed from the pool state rather than as the reciprocal of the other.
+    fn calculate_price_both(&self) -> Result<(f64, f64), ArithmeticError> {
+        let tokens = self.tokens();
+
+        Ok((
+            self.calculate_price(tokens[0])?,
+            self.calculate_price(tokens[1])?,
+        ))
+    }
+    /// Returns the price of `base` in `numeraire`, ie. to compare pools quoted in different tokens.
+    /// The price of `base` in the other token of the AMM is converted with `reference_prices`, the price of a token in `numeraire`, unless the other token is `numeraire` itself.
+    /// Returns `None` if `base` is not a token of the AMM, the price can not be computed or the reference price of the other token is missing.
+    fn normalized_price(
+        &self,
+        base: H160,
+        numeraire: H160,
+        reference_prices: &HashMap<H160, f64>,
+    ) -> Option<f64> {
+        if !self.contains_token(base) {
+            return None;
+        }
+
+        let price = self.calculate_price(base).ok()?;
+        let quote = self.get_token_out(base);
+        if quote == numeraire {
+            return Some(price);
+        }
+
+        Some(price * reference_prices.get(&quote)?)
+    }
     fn sync_from_log(&mut self, log: Log) -> Result<(), EventLogError>;
     async fn populate_data<M: Middleware>(
         &mut self,
@@ -36,12 +89,54 @@ pub trait AutomatedMarketMaker {
         token_in: H160,
         amount_in: U256,
     ) -> Result<U256, SwapSimulationError>;
+    /// Simulates `swaps` in order against a copy of the AMM, each swap starting from the state left by the previous one, and returns the amount out of each swap.
+    fn simulate_swaps(&self, swaps: &[(H160, U256)]) -> Result<Vec<U256>, SwapSimulationError>
+    where
+        Self: Clone + Sized,
+    {
+        let mut amm = self.clone();
+
+        swaps
+            .iter()
+            .map(|(token_in, amount_in)| amm.simulate_swap_mut(*token_in, *amount_in))
+            .collect()
+    }
+    /// Returns the round trip spread of the AMM for a trade of `size` of its first token, in percent.
+    /// Sells `size` for the second token and buys back with the amount received, both from the current state, and compares the two effective prices relative to their midpoint, so fees are included.
+    /// Returns `f64::INFINITY` if either swap fails or nothing is bought back.
+    fn spread(&self, size: U256) -> f64 {
+        let tokens = self.tokens();
+
+        let Ok(amount_out) = self.simulate_swap(tokens[0], size) else {
+            return f64::INFINITY;
+        };
+        let Ok(amount_back) = self.simulate_swap(tokens[1], amount_out) else {
+            return f64::INFINITY;
+        };
+        if size.is_zero() || amount_out.is_zero() || amount_back.is_zero() {
+            return f64::INFINITY;
+        }
+
+        //The sell price is amount_out / size and the buy price amount_out / amount_back, so their ratio only depends on size and amount_back
+        let (size, amount_back) = (u256_to_f64(size), u256_to_f64(amount_back));
+        200.0 * (size - amount_back) / (size + amount_back)
+    }
     fn get_token_out(&self, token_in: H160) -> H160;
+    /// Returns the swap fee of the AMM in basis points.
+    fn fee_bps(&self) -> u32;
+    /// Returns the liquidity of the AMM as the geometric mean of its reserves, in raw token units.
+    /// Uniswap V3 pools return their active liquidity, which is the same measure for the current tick range.
+    fn liquidity(&self) -> U256;
+    /// Returns a rough estimate of the gas used by a swap against the AMM, ie. to weigh venues by execution cost when routing.
+    /// Uniswap V3 pools return the cost of a swap within the current tick range, see `UniswapV3Pool::swap_gas_estimate_for` for swaps crossing ticks.
+    fn swap_gas_estimate(&self) -> u64;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AMM {
+    #[cfg(feature = "uniswap-v2")]
     UniswapV2Pool(UniswapV2Pool),
+    #[cfg(feature = "uniswap-v3")]
     UniswapV3Pool(UniswapV3Pool),
     ERC4626Vault(ERC4626Vault),
 }
@@ -50,7 +145,9 @@ pub enum AMM {
 impl AutomatedMarketMaker for AMM {
     fn address(&self) -> H160 {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.address,
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.address,
             AMM::ERC4626Vault(vault) => vault.vault_token,
         }
@@ -58,7 +155,9 @@ impl AutomatedMarketMaker for AMM {
 
     async fn sync<M: Middleware>(&mut self, middleware: Arc<M>) -> Result<(), AMMError<M>> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.sync(middleware).await,
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.sync(middleware).await,
             AMM::ERC4626Vault(vault) => vault.sync(middleware).await,
         }
@@ -66,7 +165,9 @@ impl AutomatedMarketMaker for AMM {
 
     fn sync_on_event_signatures(&self) -> Vec<H256> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.sync_on_event_signatures(),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.sync_on_event_signatures(),
             AMM::ERC4626Vault(vault) => vault.sync_on_event_signatures(),
         }
@@ -74,7 +175,9 @@ impl AutomatedMarketMaker for AMM {
 
     fn sync_from_log(&mut self, log: Log) -> Result<(), EventLogError> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.sync_from_log(log),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.sync_from_log(log),
             AMM::ERC4626Vault(vault) => vault.sync_from_log(log),
         }
@@ -82,7 +185,9 @@ impl AutomatedMarketMaker for AMM {
 
     fn simulate_swap(&self, token_in: H160, amount_in: U256) -> Result<U256, SwapSimulationError> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.simulate_swap(token_in, amount_in),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.simulate_swap(token_in, amount_in),
             AMM::ERC4626Vault(vault) => vault.simulate_swap(token_in, amount_in),
         }
@@ -94,7 +199,9 @@ impl AutomatedMarketMaker for AMM {
         amount_in: U256,
     ) -> Result<U256, SwapSimulationError> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.simulate_swap_mut(token_in, amount_in),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.simulate_swap_mut(token_in, amount_in),
             AMM::ERC4626Vault(vault) => vault.simulate_swap_mut(token_in, amount_in),
         }
@@ -102,19 +209,25 @@ impl AutomatedMarketMaker for AMM {
 
     fn get_token_out(&self, token_in: H160) -> H160 {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.get_token_out(token_in),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.get_token_out(token_in),
             AMM::ERC4626Vault(vault) => vault.get_token_out(token_in),
         }
     }
 
+    //Only Uniswap V3 pools are populated at `block_number`
+    #[cfg_attr(not(feature = "uniswap-v3"), allow(unused_variables))]
     async fn populate_data<M: Middleware>(
         &mut self,
         block_number: Option<u64>,
         middleware: Arc<M>,
     ) -> Result<(), AMMError<M>> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.populate_data(None, middleware).await,
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.populate_data(block_number, middleware).await,
             AMM::ERC4626Vault(vault) => vault.populate_data(None, middleware).await,
         }
@@ -122,17 +235,366 @@ impl AutomatedMarketMaker for AMM {
 
     fn tokens(&self) -> Vec<H160> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.tokens(),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.tokens(),
             AMM::ERC4626Vault(vault) => vault.tokens(),
         }
     }
 
+    fn token_count(&self) -> usize {
+        match self {
+            #[cfg(feature = "uniswap-v2")]
+            AMM::UniswapV2Pool(pool) => pool.token_count(),
+            #[cfg(feature = "uniswap-v3")]
+            AMM::UniswapV3Pool(pool) => pool.token_count(),
+            AMM::ERC4626Vault(vault) => vault.token_count(),
+        }
+    }
+
     fn calculate_price(&self, base_token: H160) -> Result<f64, ArithmeticError> {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(pool) => pool.calculate_price(base_token),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(pool) => pool.calculate_price(base_token),
             AMM::ERC4626Vault(vault) => vault.calculate_price(base_token),
         }
     }
+
+    fn calculate_price_both(&self) -> Result<(f64, f64), ArithmeticError> {
+        match self {
+            #[cfg(feature = "uniswap-v2")]
+            AMM::UniswapV2Pool(pool) => pool.calculate_price_both(),
+            #[cfg(feature = "uniswap-v3")]
+            AMM::UniswapV3Pool(pool) => pool.calculate_price_both(),
+            AMM::ERC4626Vault(vault) => vault.calculate_price_both(),
+        }
+    }
+
+    fn fee_bps(&self) -> u32 {
+        match self {
+            #[cfg(feature = "uniswap-v2")]
+            AMM::UniswapV2Pool(pool) => pool.fee_bps(),
+            #[cfg(feature = "uniswap-v3")]
+            AMM::UniswapV3Pool(pool) => pool.fee_bps(),
+            AMM::ERC4626Vault(vault) => vault.fee_bps(),
+        }
+    }
+
+    fn liquidity(&self) -> U256 {
+        match self {
+            #[cfg(feature = "uniswap-v2")]
+            AMM::UniswapV2Pool(pool) => pool.liquidity(),
+            #[cfg(feature = "uniswap-v3")]
+            AMM::UniswapV3Pool(pool) => pool.liquidity(),
+            AMM::ERC4626Vault(vault) => vault.liquidity(),
+        }
+    }
+
+    fn swap_gas_estimate(&self) -> u64 {
+        match self {
+            #[cfg(feature = "uniswap-v2")]
+            AMM::UniswapV2Pool(pool) => pool.swap_gas_estimate(),
+            #[cfg(feature = "uniswap-v3")]
+            AMM::UniswapV3Pool(pool) => pool.swap_gas_estimate(),
+            AMM::ERC4626Vault(vault) => vault.swap_gas_estimate(),
+        }
+    }
+}
+
+/// Sorts `amms` by liquidity, deepest first. Liquidity is only comparable between AMMs of tokens with similar unit values, see `AutomatedMarketMaker::liquidity`.
+pub fn sort_by_liquidity(amms: &mut [AMM]) {
+    amms.sort_by_cached_key(|amm| Reverse(amm.liquidity()));
+}
+
+/// Returns the `n` deepest AMMs of `amms` by liquidity, deepest first.
+pub fn top_n_by_liquidity(amms: &[AMM], n: usize) -> Vec<&AMM> {
+    let mut sorted = amms.iter().collect::<Vec<&AMM>>();
+    sorted.sort_by_cached_key(|amm| Reverse(amm.liquidity()));
+    sorted.truncate(n);
+
+    sorted
+}
+
+/// Returns every distinct token across `amms`.
+pub fn unique_tokens(amms: &[AMM]) -> HashSet<H160> {
+    amms.iter().flat_map(|amm| amm.tokens()).collect()
+}
+
+/// Returns an approximation of the bytes used by `amms`, for capacity planning.
+/// Uniswap V3 tick data dominates the footprint, and is counted per allocated entry of the tick and bitmap maps.
+pub fn estimate_memory(amms: &[AMM]) -> usize {
+    amms.iter()
+        .map(|amm| {
+            let heap_size = match amm {
+                #[cfg(feature = "uniswap-v3")]
+                AMM::UniswapV3Pool(pool) => {
+                    //Each hashbrown bucket stores the entry plus a control byte
+                    pool.ticks.capacity() * (std::mem::size_of::<(i32, uniswap_v3::Info)>() + 1)
+                        + pool.tick_bitmap.capacity() * (std::mem::size_of::<(i16, U256)>() + 1)
+                }
+                #[cfg(feature = "uniswap-v2")]
+                AMM::UniswapV2Pool(_) => 0,
+                AMM::ERC4626Vault(_) => 0,
+            };
+
+            std::mem::size_of::<AMM>() + heap_size
+        })
+        .sum()
+}
+
+#[cfg(all(test, feature = "uniswap-v2", feature = "uniswap-v3"))]
+mod tests {
+    use std::collections::{HashMap, HashSet};
+
+    use ethers::types::{H160, U256};
+
+    use super::{
+        erc_4626::ERC4626Vault,
+        estimate_memory, sort_by_liquidity, top_n_by_liquidity, unique_tokens,
+        uniswap_v2::UniswapV2Pool,
+        uniswap_v3::{Info, UniswapV3Pool},
+        AutomatedMarketMaker, AMM,
+    };
+
+    #[test]
+    fn test_contains_token() {
+        let amm = AMM::UniswapV2Pool(UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            ..Default::default()
+        });
+
+        assert!(amm.contains_token(H160::from_low_u64_be(1)));
+        assert!(amm.contains_token(H160::from_low_u64_be(2)));
+        assert!(!amm.contains_token(H160::from_low_u64_be(3)));
+    }
+
+    #[test]
+    fn test_token_count() {
+        let amms = [
+            AMM::UniswapV2Pool(UniswapV2Pool::default()),
+            AMM::UniswapV3Pool(UniswapV3Pool::default()),
+            AMM::ERC4626Vault(ERC4626Vault::default()),
+        ];
+
+        for amm in amms {
+            assert_eq!(amm.token_count(), amm.tokens().len());
+            assert_eq!(amm.token_count(), 2);
+        }
+    }
+
+    #[test]
+    fn test_simulate_swaps() {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+
+        let amm = AMM::UniswapV2Pool(UniswapV2Pool {
+            token_a,
+            token_a_decimals: 18,
+            token_b,
+            token_b_decimals: 18,
+            reserve_0: 1000000000000000000000,
+            reserve_1: 1000000000000000000000,
+            fee: 300,
+            ..Default::default()
+        });
+
+        let amount_in = U256::from(10000000000000000000_u128);
+        let swaps = [(token_a, amount_in), (token_a, amount_in), (token_b, amount_in)];
+        let amounts_out = amm.simulate_swaps(&swaps).unwrap();
+
+        //Each swap sees the state left by the previous swaps
+        let mut expected = amm.clone();
+        for ((token_in, amount_in), amount_out) in swaps.iter().zip(&amounts_out) {
+            assert_eq!(
+                expected.simulate_swap_mut(*token_in, *amount_in).unwrap(),
+                *amount_out
+            );
+        }
+        assert!(amounts_out[1] < amounts_out[0]);
+        assert!(amounts_out[2] > amounts_out[0]);
+
+        //The AMM itself is left untouched
+        let AMM::UniswapV2Pool(pool) = amm else {
+            unreachable!()
+        };
+        assert_eq!(pool.reserve_0, 1000000000000000000000);
+        assert_eq!(pool.reserve_1, 1000000000000000000000);
+    }
+
+    #[test]
+    fn test_unique_tokens() {
+        let token = H160::from_low_u64_be;
+
+        let amms = vec![
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                token_a: token(1),
+                token_b: token(2),
+                ..Default::default()
+            }),
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                token_a: token(2),
+                token_b: token(3),
+                ..Default::default()
+            }),
+            AMM::ERC4626Vault(ERC4626Vault {
+                vault_token: token(4),
+                asset_token: token(1),
+                ..Default::default()
+            }),
+        ];
+
+        assert_eq!(
+            unique_tokens(&amms),
+            HashSet::from([token(1), token(2), token(3), token(4)])
+        );
+        assert!(unique_tokens(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_estimate_memory() {
+        let v2_pool = AMM::UniswapV2Pool(UniswapV2Pool::default());
+        let empty_v3_pool = AMM::UniswapV3Pool(UniswapV3Pool::default());
+
+        let mut v3_pool = UniswapV3Pool::default();
+        for tick in 0..1000 {
+            v3_pool.ticks.insert(tick * 60, Info::default());
+        }
+        v3_pool.tick_bitmap.insert(0, U256::one());
+        let v3_pool = AMM::UniswapV3Pool(v3_pool);
+
+        assert_eq!(estimate_memory(&[]), 0);
+        assert_eq!(
+            estimate_memory(&[v2_pool.clone()]),
+            std::mem::size_of::<AMM>()
+        );
+        assert_eq!(
+            estimate_memory(&[v2_pool.clone(), empty_v3_pool.clone()]),
+            2 * std::mem::size_of::<AMM>()
+        );
+
+        //Tick data should dominate the estimate
+        assert!(
+            estimate_memory(&[v3_pool.clone()])
+                > 1000 * std::mem::size_of::<(i32, Info)>() + std::mem::size_of::<AMM>()
+        );
+        assert_eq!(
+            estimate_memory(&[v2_pool.clone(), v3_pool.clone()]),
+            estimate_memory(&[v2_pool]) + estimate_memory(&[v3_pool])
+        );
+    }
+
+    #[test]
+    fn test_sort_by_liquidity() {
+        //sqrt(1e6 * 4e6) = 2e6
+        let v2_pool = AMM::UniswapV2Pool(UniswapV2Pool {
+            address: H160::from_low_u64_be(1),
+            reserve_0: 1000000,
+            reserve_1: 4000000,
+            ..Default::default()
+        });
+        let v3_pool = AMM::UniswapV3Pool(UniswapV3Pool {
+            address: H160::from_low_u64_be(2),
+            liquidity: 3000000,
+            ..Default::default()
+        });
+        //sqrt(1e6 * 1e6) = 1e6
+        let vault = AMM::ERC4626Vault(ERC4626Vault {
+            vault_token: H160::from_low_u64_be(3),
+            vault_reserve: U256::from(1000000),
+            asset_reserve: U256::from(1000000),
+            ..Default::default()
+        });
+
+        assert_eq!(v2_pool.liquidity(), U256::from(2000000));
+        assert_eq!(vault.liquidity(), U256::from(1000000));
+
+        let mut amms = vec![vault.clone(), v2_pool.clone(), v3_pool.clone()];
+        sort_by_liquidity(&mut amms);
+        let addresses = amms.iter().map(|amm| amm.address()).collect::<Vec<H160>>();
+        assert_eq!(
+            addresses,
+            vec![v3_pool.address(), v2_pool.address(), vault.address()]
+        );
+
+        let top = top_n_by_liquidity(&[vault, v2_pool.clone(), v3_pool.clone()], 2);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].address(), v3_pool.address());
+        assert_eq!(top[1].address(), v2_pool.address());
+        assert!(top_n_by_liquidity(&[], 2).is_empty());
+    }
+
+    #[test]
+    fn test_spread() {
+        let pool = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            reserve_0: 1000000000000000000000000,
+            reserve_1: 2000000000000000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        //A small trade only pays the fee on each leg, 2 * (1 - 0.997^2) / (1 + 0.997^2) ~ 0.6%
+        let small = pool.spread(U256::from(1000000000000000000_u128));
+        assert!((small - 0.6009).abs() < 0.001);
+
+        //Price impact widens the spread
+        let large = pool.spread(U256::from(10000000000000000000000_u128));
+        assert!(large > small);
+
+        assert_eq!(pool.spread(U256::zero()), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_normalized_price() {
+        let (weth, usdc, token) = (
+            H160::from_low_u64_be(1),
+            H160::from_low_u64_be(2),
+            H160::from_low_u64_be(3),
+        );
+
+        //2000 USDC per WETH
+        let usdc_pool = UniswapV2Pool {
+            token_a: weth,
+            token_a_decimals: 18,
+            token_b: usdc,
+            token_b_decimals: 18,
+            reserve_0: 1000000000000000000000,
+            reserve_1: 2000000000000000000000000,
+            ..Default::default()
+        };
+        //0.001 WETH per token
+        let weth_pool = UniswapV2Pool {
+            token_a: token,
+            token_a_decimals: 18,
+            token_b: weth,
+            token_b_decimals: 18,
+            reserve_0: 1000000000000000000000000,
+            reserve_1: 1000000000000000000000,
+            ..Default::default()
+        };
+        let reference_prices = HashMap::from([(weth, 2000.0)]);
+
+        let weth_price = usdc_pool
+            .normalized_price(weth, usdc, &reference_prices)
+            .unwrap();
+        assert!((weth_price - 2000.0).abs() < 1e-6);
+
+        let token_price = weth_pool
+            .normalized_price(token, usdc, &reference_prices)
+            .unwrap();
+        assert!((token_price - 2.0).abs() < 1e-6);
+
+        //Missing reference price and token not in the pool
+        assert!(weth_pool
+            .normalized_price(token, usdc, &HashMap::new())
+            .is_none());
+        assert!(usdc_pool
+            .normalized_price(token, usdc, &reference_prices)
+            .is_none());
+    }
 }
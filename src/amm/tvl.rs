@@ -0,0 +1,133 @@
+use ethers::types::{H160, U256};
+
+use super::{math::u256_to_f64, AMM};
+
+/// Source of token prices used to value AMM reserves, ie. backed by an oracle or an off-chain feed.
+pub trait PriceProvider {
+    /// Returns the price of one whole unit of `token` denominated in `numeraire`, or `None` if the price is unknown.
+    fn price(&self, token: H160, numeraire: H160) -> Option<f64>;
+}
+
+impl AMM {
+    /// Returns the total value locked in the AMM denominated in `numeraire`, summing the balance of each token times its price from `price_provider`.
+    /// Balances are normalized by token decimals. Uniswap V3 pools are valued with the virtual reserves of the active liquidity, and ERC4626 vaults with their assets only since the shares are claims on them.
+    /// Returns `None` if the price of any token is unknown.
+    pub fn tvl_in<P: PriceProvider>(&self, numeraire: H160, price_provider: &P) -> Option<f64> {
+        let balances = match self {
+            #[cfg(feature = "uniswap-v2")]
+            AMM::UniswapV2Pool(pool) => vec![
+                (
+                    pool.token_a,
+                    U256::from(pool.reserve_0),
+                    pool.token_a_decimals,
+                ),
+                (
+                    pool.token_b,
+                    U256::from(pool.reserve_1),
+                    pool.token_b_decimals,
+                ),
+            ],
+            #[cfg(feature = "uniswap-v3")]
+            AMM::UniswapV3Pool(pool) => {
+                let (reserve_a, reserve_b) = pool.virtual_reserves();
+                vec![
+                    (pool.token_a, reserve_a, pool.token_a_decimals),
+                    (pool.token_b, reserve_b, pool.token_b_decimals),
+                ]
+            }
+            AMM::ERC4626Vault(vault) => vec![(
+                vault.asset_token,
+                vault.asset_reserve,
+                vault.asset_token_decimals,
+            )],
+        };
+
+        balances
+            .into_iter()
+            .map(|(token, balance, decimals)| {
+                let price = if token == numeraire {
+                    1.0
+                } else {
+                    price_provider.price(token, numeraire)?
+                };
+
+                Some(u256_to_f64(balance) / 10_f64.powi(decimals as i32) * price)
+            })
+            .sum()
+    }
+}
+
+#[cfg(all(test, feature = "uniswap-v2", feature = "uniswap-v3"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use ethers::types::{H160, U256};
+
+    use crate::amm::{
+        erc_4626::ERC4626Vault, uniswap_v2::UniswapV2Pool, uniswap_v3::UniswapV3Pool, AMM,
+    };
+
+    use super::PriceProvider;
+
+    struct FixedPrices(HashMap<H160, f64>);
+
+    impl PriceProvider for FixedPrices {
+        fn price(&self, token: H160, _numeraire: H160) -> Option<f64> {
+            self.0.get(&token).copied()
+        }
+    }
+
+    #[test]
+    fn test_tvl_in() {
+        let usdc = H160::from_low_u64_be(1);
+        let weth = H160::from_low_u64_be(2);
+        let dai = H160::from_low_u64_be(3);
+
+        let prices = FixedPrices(HashMap::from([(weth, 2000.0)]));
+
+        //1000 USDC and 0.5 WETH
+        let v2_pool = AMM::UniswapV2Pool(UniswapV2Pool {
+            token_a: usdc,
+            token_a_decimals: 6,
+            token_b: weth,
+            token_b_decimals: 18,
+            reserve_0: 1000000000,
+            reserve_1: 500000000000000000,
+            ..Default::default()
+        });
+        assert_eq!(v2_pool.tvl_in(usdc, &prices), Some(2000.0));
+
+        //Price of 1 and 1e18 liquidity, ie. virtual reserves of 1 WETH and 1 USDC at 18 decimals
+        let v3_pool = AMM::UniswapV3Pool(UniswapV3Pool {
+            token_a: weth,
+            token_a_decimals: 18,
+            token_b: usdc,
+            token_b_decimals: 18,
+            liquidity: 1000000000000000000,
+            sqrt_price: U256::one() << 96,
+            ..Default::default()
+        });
+        assert_eq!(v3_pool.tvl_in(usdc, &prices), Some(2001.0));
+
+        let vault = AMM::ERC4626Vault(ERC4626Vault {
+            vault_token: H160::from_low_u64_be(4),
+            vault_token_decimals: 18,
+            asset_token: weth,
+            asset_token_decimals: 18,
+            vault_reserve: U256::from(900000000000000000_u128),
+            asset_reserve: U256::from(1000000000000000000_u128),
+            ..Default::default()
+        });
+        assert_eq!(vault.tvl_in(usdc, &prices), Some(2000.0));
+
+        //Unknown price
+        let dai_pool = AMM::UniswapV2Pool(UniswapV2Pool {
+            token_a: dai,
+            token_b: weth,
+            reserve_0: 1,
+            reserve_1: 1,
+            ..Default::default()
+        });
+        assert_eq!(dai_pool.tvl_in(usdc, &prices), None);
+    }
+}
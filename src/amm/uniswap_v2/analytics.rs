@@ -0,0 +1,537 @@
+use ethers::types::{H160, U256, U512};
+
+use crate::{
+    amm::{math::u256_to_f64, AutomatedMarketMaker},
+    errors::{ArithmeticError, SwapSimulationError},
+};
+
+use super::UniswapV2Pool;
+
+pub const SECONDS_PER_YEAR: f64 = 31536000.0;
+
+/// Returns the impermanent loss of a constant product position when the price moves from `price_initial` to `price_current`.
+/// The loss is expressed as a fraction of the value of holding the tokens instead, ie. -0.2 means the position is worth 20% less than holding.
+pub fn impermanent_loss(price_initial: f64, price_current: f64) -> f64 {
+    let price_ratio = price_current / price_initial;
+
+    2.0 * price_ratio.sqrt() / (1.0 + price_ratio) - 1.0
+}
+
+/// Returns the impermanent loss between two snapshots of the same pool, see `impermanent_loss`.
+/// The loss only depends on the magnitude of the price change, so either token can be used as the base token.
+pub fn impermanent_loss_between(
+    initial: &UniswapV2Pool,
+    current: &UniswapV2Pool,
+) -> Result<f64, ArithmeticError> {
+    current.impermanent_loss_since(initial.calculate_price(initial.token_a)?, initial.token_a)
+}
+
+/// Change of the reserves of a pool between two snapshots, split into the part moved by swaps and the part added or removed by liquidity events.
+/// Amounts are in raw token units, positive when the reserve grew.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ReserveChangeBreakdown {
+    pub swap_delta_0: f64,
+    pub swap_delta_1: f64,
+    pub liquidity_delta_0: f64,
+    pub liquidity_delta_1: f64,
+    /// Factor the liquidity of the pool was scaled by through mints and burns, ie. 1.1 when 10% was added
+    pub liquidity_factor: f64,
+}
+
+/// Direction of a swap relative to the base token of a price.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwapDirection {
+    /// Swap the quote token for the base token, raising the price of the base token
+    BuyBase,
+    /// Swap the base token for the quote token, lowering the price of the base token
+    SellBase,
+}
+
+impl UniswapV2Pool {
+    /// Returns the impermanent loss since the pool price of `base_token` was `baseline_price`, see `impermanent_loss`.
+    pub fn impermanent_loss_since(
+        &self,
+        baseline_price: f64,
+        base_token: H160,
+    ) -> Result<f64, ArithmeticError> {
+        Ok(impermanent_loss(
+            baseline_price,
+            self.calculate_price(base_token)?,
+        ))
+    }
+
+    /// Returns the annualized fee yield of the pool since `earlier`, a snapshot of the same pool taken `elapsed_secs` ago, as a fraction of the pool value (ie. 0.1 for 10%).
+    /// Swap fees stay in the reserves, so the growth of `sqrt(k)` is the fee revenue relative to the value of the pool. Mints and burns between the snapshots change `k` as well and distort the estimate.
+    /// `fee_bps` is the part of the pool fee paid to LPs, ie. 25 out of the 30 bps fee of a Uniswap V2 pool with the protocol fee on.
+    pub fn implied_apr(&self, earlier: &UniswapV2Pool, elapsed_secs: u32, fee_bps: u32) -> f64 {
+        let pool_fee_bps = self.fee_bps();
+        if elapsed_secs == 0
+            || pool_fee_bps == 0
+            || earlier.reserve_0 == 0
+            || earlier.reserve_1 == 0
+        {
+            return 0.0;
+        }
+
+        let sqrt_k = |pool: &UniswapV2Pool| (pool.reserve_0 as f64 * pool.reserve_1 as f64).sqrt();
+        let growth = sqrt_k(self) / sqrt_k(earlier) - 1.0;
+
+        let lp_growth = growth * fee_bps as f64 / pool_fee_bps as f64;
+
+        lp_growth * SECONDS_PER_YEAR / elapsed_secs as f64
+    }
+
+    /// Returns the maximum amount of `token_in` that can be swapped before the price of `token_in` in the output token drops by more than `band_bps`.
+    /// The fee kept by the pool is ignored, which slightly underestimates the depth since the fee only reduces the price impact. Returns `U256::MAX` for a band of 100% or more.
+    pub fn depth_within(&self, band_bps: u32, token_in: H160) -> U256 {
+        if band_bps >= 10000 {
+            return U256::MAX;
+        }
+
+        let reserve_in = U256::from(if self.token_a == token_in {
+            self.reserve_0
+        } else {
+            self.reserve_1
+        });
+
+        //With k constant, the price moves by (r_in / (r_in + x))^2 = 1 - band, so x = r_in / sqrt(1 - band) - r_in
+        let scaled_reserve_in = (reserve_in.full_mul(reserve_in) * U512::from(10000)
+            / U512::from(10000 - band_bps))
+        .integer_sqrt();
+
+        U256::try_from(scaled_reserve_in).unwrap_or(U256::MAX) - reserve_in
+    }
+
+    /// Splits the change of the reserves since `earlier`, a snapshot of the same pool, into swaps and liquidity events.
+    /// Mints and burns scale both reserves by the change of the LP token supply, while swaps move the reserves along the curve, so the reserves
+    /// are first scaled back by the liquidity factor and the remaining change is attributed to swaps. Without the total supply of both snapshots,
+    /// the factor is estimated from the growth of `sqrt(k)`, which also counts the fees earned by swaps as liquidity.
+    pub fn decompose_reserve_change(&self, earlier: &UniswapV2Pool) -> ReserveChangeBreakdown {
+        let (reserve_0, reserve_1) = (self.reserve_0 as f64, self.reserve_1 as f64);
+        let (earlier_reserve_0, earlier_reserve_1) =
+            (earlier.reserve_0 as f64, earlier.reserve_1 as f64);
+
+        //Liquidity added to an empty pool can not be compared to the earlier state
+        if earlier_reserve_0 == 0.0 || earlier_reserve_1 == 0.0 {
+            return ReserveChangeBreakdown {
+                liquidity_delta_0: reserve_0 - earlier_reserve_0,
+                liquidity_delta_1: reserve_1 - earlier_reserve_1,
+                ..Default::default()
+            };
+        }
+
+        let liquidity_factor = if !self.total_supply.is_zero() && !earlier.total_supply.is_zero() {
+            u256_to_f64(self.total_supply) / u256_to_f64(earlier.total_supply)
+        } else {
+            (reserve_0 * reserve_1).sqrt() / (earlier_reserve_0 * earlier_reserve_1).sqrt()
+        };
+
+        if liquidity_factor == 0.0 {
+            return ReserveChangeBreakdown {
+                liquidity_delta_0: -earlier_reserve_0,
+                liquidity_delta_1: -earlier_reserve_1,
+                ..Default::default()
+            };
+        }
+
+        //Reserves the pool would hold with swaps alone
+        let swap_reserve_0 = reserve_0 / liquidity_factor;
+        let swap_reserve_1 = reserve_1 / liquidity_factor;
+
+        ReserveChangeBreakdown {
+            swap_delta_0: swap_reserve_0 - earlier_reserve_0,
+            swap_delta_1: swap_reserve_1 - earlier_reserve_1,
+            liquidity_delta_0: reserve_0 - swap_reserve_0,
+            liquidity_delta_1: reserve_1 - swap_reserve_1,
+            liquidity_factor,
+        }
+    }
+
+    /// Returns the direction and the amount in of the swap that moves the spot price of `base_token` to `target_price`, ie. to arbitrage the pool against an external price.
+    /// `target_price` is denominated in the other token of the pool and adjusted for decimals, like `calculate_price`. `fee_bps` is the fee of the pool in basis points.
+    /// The amount in is found by solving the post swap spot price of a constant product pool for the input, zero is returned if the pool is already at the target price.
+    pub fn arb_to_target_price(
+        &self,
+        target_price: f64,
+        base_token: H160,
+        fee_bps: u32,
+    ) -> Result<(SwapDirection, U256), SwapSimulationError> {
+        if !self.contains_token(base_token) {
+            return Err(SwapSimulationError::InvalidToken(base_token));
+        }
+        if !(target_price.is_finite() && target_price > 0.0) || fee_bps >= 10000 {
+            return Err(SwapSimulationError::InvalidTargetPrice(target_price));
+        }
+
+        let (reserve_base, base_decimals, reserve_quote, quote_decimals) =
+            if self.token_a == base_token {
+                (
+                    self.reserve_0,
+                    self.token_a_decimals,
+                    self.reserve_1,
+                    self.token_b_decimals,
+                )
+            } else {
+                (
+                    self.reserve_1,
+                    self.token_b_decimals,
+                    self.reserve_0,
+                    self.token_a_decimals,
+                )
+            };
+        let (reserve_base, reserve_quote) = (reserve_base as f64, reserve_quote as f64);
+
+        //Target price in raw units of the quote token per raw unit of the base token
+        let target_price = target_price * 10_f64.powi(quote_decimals as i32 - base_decimals as i32);
+
+        //Raising the price of the base token takes the quote token in, and lowering it the base token
+        let (direction, reserve_in, reserve_out, target_ratio) =
+            if target_price > reserve_quote / reserve_base {
+                (
+                    SwapDirection::BuyBase,
+                    reserve_quote,
+                    reserve_base,
+                    target_price,
+                )
+            } else {
+                (
+                    SwapDirection::SellBase,
+                    reserve_base,
+                    reserve_quote,
+                    1.0 / target_price,
+                )
+            };
+
+        //With g the fraction of the input kept after fees, swapping x leaves reserves of r_in + x and r_out * r_in / (r_in + g * x)
+        //Their ratio reaching the target t gives g * x^2 + (1 + g) * r_in * x + r_in^2 - t * r_in * r_out = 0
+        let gamma = (10000 - fee_bps) as f64 / 10000.0;
+        let b = (1.0 + gamma) * reserve_in;
+        let c = reserve_in * reserve_in - target_ratio * reserve_in * reserve_out;
+        if c >= 0.0 {
+            return Ok((direction, U256::zero()));
+        }
+
+        let amount_in = (-b + (b * b - 4.0 * gamma * c).sqrt()) / (2.0 * gamma);
+
+        Ok((direction, U256::from(amount_in as u128)))
+    }
+
+    /// Returns the largest amount of `token_in` whose execution price, fee included, is at most `max_impact_bps` worse than the spot price of the pool.
+    /// Returns zero if the fee alone exceeds the cap, and `U256::MAX` for a cap of 100% or more.
+    pub fn max_input_for_impact(&self, token_in: H160, max_impact_bps: u32) -> U256 {
+        if max_impact_bps >= 10000 {
+            return U256::MAX;
+        }
+
+        if self.fee_denominator == 0 {
+            return U256::zero();
+        }
+
+        let reserve_in = U256::from(if self.token_a == token_in {
+            self.reserve_0
+        } else {
+            self.reserve_1
+        });
+
+        //With g the fraction of the input kept after fees, out / x = g * r_out / (r_in + g * x), so the execution price is worse than r_out / r_in by
+        //1 - g * r_in / (r_in + g * x). Capping it at m gives x = r_in / (1 - m) - r_in / g
+        let fee_denominator = U256::from(self.fee_denominator);
+        let fee_complement = U256::from(self.fee_denominator.saturating_sub(self.fee));
+        let impact_complement = U256::from(10000 - max_impact_bps);
+
+        let scaled_cap = U256::from(10000) * fee_complement;
+        let scaled_fee = fee_denominator * impact_complement;
+        if scaled_cap <= scaled_fee {
+            return U256::zero();
+        }
+
+        reserve_in * (scaled_cap - scaled_fee) / (impact_complement * fee_complement)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{H160, U256};
+
+    use crate::{
+        amm::{uniswap_v2::UniswapV2Pool, AutomatedMarketMaker},
+        errors::SwapSimulationError,
+    };
+
+    use super::{
+        impermanent_loss, impermanent_loss_between, ReserveChangeBreakdown, SwapDirection,
+        SECONDS_PER_YEAR,
+    };
+
+    #[test]
+    fn test_impermanent_loss() {
+        assert_eq!(impermanent_loss(1.0, 1.0), 0.0);
+        assert!((impermanent_loss(1.0, 4.0) + 0.2).abs() < 1e-12);
+        assert!((impermanent_loss(4.0, 1.0) + 0.2).abs() < 1e-12);
+        assert!((impermanent_loss(100.0, 125.0) + 0.006192010000093).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_impermanent_loss_since() -> eyre::Result<()> {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+
+        let pool = UniswapV2Pool {
+            token_a,
+            token_a_decimals: 18,
+            token_b,
+            token_b_decimals: 18,
+            reserve_0: 100000000000000000000,
+            reserve_1: 400000000000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        let loss = pool.impermanent_loss_since(1.0, token_a)?;
+        assert!((loss + 0.2).abs() < 1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_impermanent_loss_between() -> eyre::Result<()> {
+        let initial = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_a_decimals: 18,
+            token_b: H160::from_low_u64_be(2),
+            token_b_decimals: 18,
+            reserve_0: 200000000000000000000,
+            reserve_1: 200000000000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+        //Same k, the price of token a is 4 times higher
+        let current = UniswapV2Pool {
+            reserve_0: 100000000000000000000,
+            reserve_1: 400000000000000000000,
+            ..initial.clone()
+        };
+
+        assert!((impermanent_loss_between(&initial, &current)? + 0.2).abs() < 1e-12);
+        assert!((impermanent_loss_between(&current, &initial)? + 0.2).abs() < 1e-12);
+        assert_eq!(impermanent_loss_between(&initial, &initial)?, 0.0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_implied_apr() {
+        let earlier = UniswapV2Pool {
+            reserve_0: 100000000000000000000,
+            reserve_1: 400000000000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        //sqrt(k) grew by 1% over a day
+        let pool = UniswapV2Pool {
+            reserve_0: 101000000000000000000,
+            reserve_1: 404000000000000000000,
+            ..earlier.clone()
+        };
+
+        let apr = pool.implied_apr(&earlier, 86400, 30);
+        assert!((apr - 0.01 * SECONDS_PER_YEAR / 86400.0).abs() < 1e-9);
+
+        //LPs only receive 25 of the 30 bps with the protocol fee on
+        let apr = pool.implied_apr(&earlier, 86400, 25);
+        assert!((apr - 0.01 * 25.0 / 30.0 * SECONDS_PER_YEAR / 86400.0).abs() < 1e-9);
+
+        assert_eq!(pool.implied_apr(&earlier, 0, 30), 0.0);
+        assert_eq!(pool.implied_apr(&UniswapV2Pool::default(), 86400, 30), 0.0);
+    }
+
+    #[test]
+    fn test_depth_within() -> eyre::Result<()> {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+
+        let pool = UniswapV2Pool {
+            token_a,
+            token_a_decimals: 18,
+            token_b,
+            token_b_decimals: 18,
+            reserve_0: 1000000,
+            reserve_1: 4000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        //sqrt(1 - 0.19) = 0.9, so 1000000 / 0.9 - 1000000
+        assert_eq!(pool.depth_within(1900, token_a), U256::from(111111));
+        assert_eq!(pool.depth_within(1900, token_b), U256::from(444444));
+
+        //The price impact of the depth stays within the band
+        let depth = pool.depth_within(100, token_a);
+        let price_ratio =
+            pool.price_after_swap(token_a, depth, token_a)? / pool.calculate_price(token_a)?;
+        assert!(price_ratio >= 0.99);
+        assert!(price_ratio < 0.9901);
+
+        assert_eq!(pool.depth_within(0, token_a), U256::zero());
+        assert_eq!(pool.depth_within(10000, token_a), U256::MAX);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_input_for_impact() {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+
+        let pool = UniswapV2Pool {
+            token_a,
+            token_a_decimals: 18,
+            token_b,
+            token_b_decimals: 6,
+            reserve_0: 1000000000000000000000,
+            reserve_1: 2000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        //Price impact of a swap of `amount_in` of token a, in bps
+        let impact_bps = |amount_in: U256| {
+            let amount_out = pool.get_amount_out(
+                amount_in,
+                U256::from(pool.reserve_0),
+                U256::from(pool.reserve_1),
+            );
+            let execution_price = amount_out.as_u128() as f64 / amount_in.as_u128() as f64;
+            let spot_price = pool.reserve_1 as f64 / pool.reserve_0 as f64;
+
+            (1.0 - execution_price / spot_price) * 10000.0
+        };
+
+        //r_in / 0.99 - r_in / 0.997, about 7.09 tokens
+        let amount_in = pool.max_input_for_impact(token_a, 100);
+        assert_eq!(amount_in, U256::from(7091983019766369816_u128));
+        //Up to the rounding down of the amount out
+        assert!(impact_bps(amount_in) < 100.001);
+        assert!(impact_bps(amount_in * U256::from(101) / U256::from(100)) > 100.0);
+
+        //The cap applies to the reserve of the input token
+        assert_eq!(
+            pool.max_input_for_impact(token_b, 100),
+            U256::from(14183966039_u128)
+        );
+
+        //The 30 bps fee alone exceeds the cap
+        assert_eq!(pool.max_input_for_impact(token_a, 30), U256::zero());
+        assert_eq!(pool.max_input_for_impact(token_a, 10000), U256::MAX);
+    }
+
+    #[test]
+    fn test_arb_to_target_price() {
+        let base_token = H160::from_low_u64_be(1);
+        let quote_token = H160::from_low_u64_be(2);
+
+        //1000 base at 18 decimals against 2,000,000 quote at 6 decimals, ie. a price of 2000
+        let pool = UniswapV2Pool {
+            token_a: base_token,
+            token_a_decimals: 18,
+            token_b: quote_token,
+            token_b_decimals: 6,
+            reserve_0: 1000000000000000000000,
+            reserve_1: 2000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        for (target_price, expected_direction) in [
+            (2100.0, SwapDirection::BuyBase),
+            (1900.0, SwapDirection::SellBase),
+        ] {
+            let (direction, amount_in) = pool
+                .arb_to_target_price(target_price, base_token, 30)
+                .unwrap();
+            assert_eq!(direction, expected_direction);
+
+            let token_in = match direction {
+                SwapDirection::BuyBase => quote_token,
+                SwapDirection::SellBase => base_token,
+            };
+            let mut arbitraged = pool.clone();
+            arbitraged.simulate_swap_mut(token_in, amount_in).unwrap();
+
+            let price = arbitraged.calculate_price(base_token).unwrap();
+            assert!((price / target_price - 1.0).abs() < 1e-6);
+        }
+
+        let (_, amount_in) = pool.arb_to_target_price(2000.0, base_token, 30).unwrap();
+        assert!(amount_in.is_zero());
+
+        assert!(matches!(
+            pool.arb_to_target_price(2000.0, H160::from_low_u64_be(3), 30),
+            Err(SwapSimulationError::InvalidToken(_))
+        ));
+        assert!(matches!(
+            pool.arb_to_target_price(0.0, base_token, 30),
+            Err(SwapSimulationError::InvalidTargetPrice(_))
+        ));
+    }
+
+    #[test]
+    fn test_decompose_reserve_change() {
+        let earlier = UniswapV2Pool {
+            reserve_0: 1000000000000000000000,
+            reserve_1: 4000000000000000000000,
+            total_supply: U256::from(2000000000000000000000_u128),
+            fee: 300,
+            ..Default::default()
+        };
+
+        //10% more liquidity at the same price
+        let pool = UniswapV2Pool {
+            reserve_0: 1100000000000000000000,
+            reserve_1: 4400000000000000000000,
+            total_supply: U256::from(2200000000000000000000_u128),
+            ..earlier.clone()
+        };
+        let breakdown = pool.decompose_reserve_change(&earlier);
+        assert!((breakdown.liquidity_factor - 1.1).abs() < 1e-12);
+        assert!(breakdown.swap_delta_0.abs() < 1e6);
+        assert!(breakdown.swap_delta_1.abs() < 1e6);
+        assert!((breakdown.liquidity_delta_0 - 1e20).abs() < 1e6);
+        assert!((breakdown.liquidity_delta_1 - 4e20).abs() < 1e6);
+
+        //A swap with no liquidity events only moves along the curve
+        let mut swapped = earlier.clone();
+        let amount_out = swapped.get_amount_out(
+            U256::from(10000000000000000000_u128),
+            U256::from(earlier.reserve_0),
+            U256::from(earlier.reserve_1),
+        );
+        swapped.reserve_0 += 10000000000000000000;
+        swapped.reserve_1 -= amount_out.as_u128();
+
+        let breakdown = swapped.decompose_reserve_change(&earlier);
+        assert_eq!(breakdown.liquidity_factor, 1.0);
+        assert!((breakdown.swap_delta_0 - 1e19).abs() < 1e6);
+        assert!((breakdown.swap_delta_1 + amount_out.as_u128() as f64).abs() < 1e6);
+        assert_eq!(breakdown.liquidity_delta_0, 0.0);
+
+        //Without the supply, the factor comes from the growth of sqrt(k)
+        let pool = UniswapV2Pool {
+            total_supply: U256::zero(),
+            ..pool
+        };
+        let breakdown = pool.decompose_reserve_change(&earlier);
+        assert!((breakdown.liquidity_factor - 1.1).abs() < 1e-12);
+
+        //Everything in a pool that was empty is liquidity
+        assert_eq!(
+            pool.decompose_reserve_change(&UniswapV2Pool::default()),
+            ReserveChangeBreakdown {
+                liquidity_delta_0: 1100000000000000000000.0,
+                liquidity_delta_1: 4400000000000000000000.0,
+                ..Default::default()
+            }
+        );
+    }
+}
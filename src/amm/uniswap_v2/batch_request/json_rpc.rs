@@ -0,0 +1,246 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use ethers::{
+    abi::ParamType,
+    providers::{Middleware, MiddlewareError},
+    types::{BlockNumber, Bytes, TransactionRequest, H160},
+};
+use futures::stream::{self, StreamExt, TryStreamExt};
+
+use crate::{amm::uniswap_v2::UniswapV2Pool, errors::AMMError};
+
+// Maximum number of calls in flight at once
+pub const JSON_RPC_BATCH_SIZE: usize = 300;
+
+// Selectors of `token0()`, `token1()`, `getReserves()` and `decimals()`
+const TOKEN_0_SELECTOR: [u8; 4] = [13, 254, 22, 129];
+const TOKEN_1_SELECTOR: [u8; 4] = [210, 18, 32, 167];
+const GET_RESERVES_SELECTOR: [u8; 4] = [9, 2, 241, 172];
+const DECIMALS_SELECTOR: [u8; 4] = [49, 60, 229, 103];
+
+/// Populates the tokens, decimals and reserves of `pools` with plain `eth_call`s, instead of deploying a batch contract.
+/// The `token0()`, `token1()` and `getReserves()` calls of every pool are issued first, then the `decimals()` calls of their tokens, with at most `JSON_RPC_BATCH_SIZE` calls in flight.
+/// The calls go through `middleware`, so they are rate limited, failed over and authenticated like any other request of the middleware stack.
+/// As with the batch contract, pools with a reverting call or undecodable return data are left as they are. Reserves that do not fit the pool fields fail with `AMMError::BatchRequestError`.
+pub async fn get_pool_data_json_rpc_batch<M: Middleware>(
+    pools: &mut [UniswapV2Pool],
+    block: Option<BlockNumber>,
+    middleware: Arc<M>,
+) -> Result<(), AMMError<M>> {
+    let block = block.unwrap_or(BlockNumber::Latest);
+
+    let pool_calls = pools
+        .iter()
+        .flat_map(|pool| {
+            [
+                (pool.address, TOKEN_0_SELECTOR),
+                (pool.address, TOKEN_1_SELECTOR),
+                (pool.address, GET_RESERVES_SELECTOR),
+            ]
+        })
+        .collect::<Vec<(H160, [u8; 4])>>();
+    let pool_return_data = batch_eth_call(&pool_calls, block, middleware.clone()).await?;
+
+    let mut pool_data = vec![];
+    for (pool, return_data) in pools.iter().zip(pool_return_data.chunks(3)) {
+        let tokens = return_data[0]
+            .as_ref()
+            .and_then(decode_address)
+            .zip(return_data[1].as_ref().and_then(decode_address));
+        let reserves = match &return_data[2] {
+            Some(return_data) => decode_reserves(pool.address, return_data)?,
+            None => None,
+        };
+
+        pool_data.push(tokens.zip(reserves));
+    }
+
+    let tokens = pool_data
+        .iter()
+        .flatten()
+        .flat_map(|((token_a, token_b), _)| [*token_a, *token_b])
+        .collect::<BTreeSet<H160>>()
+        .into_iter()
+        .collect::<Vec<H160>>();
+    let decimals_calls = tokens
+        .iter()
+        .map(|token| (*token, DECIMALS_SELECTOR))
+        .collect::<Vec<(H160, [u8; 4])>>();
+    let decimals = tokens
+        .into_iter()
+        .zip(batch_eth_call(&decimals_calls, block, middleware).await?)
+        .filter_map(|(token, return_data)| Some((token, decode_decimals(&return_data?)?)))
+        .collect::<HashMap<H160, u8>>();
+
+    for (pool, data) in pools.iter_mut().zip(pool_data) {
+        let Some(((token_a, token_b), (reserve_0, reserve_1, last_active_at))) = data else {
+            continue;
+        };
+        let (Some(token_a_decimals), Some(token_b_decimals)) =
+            (decimals.get(&token_a), decimals.get(&token_b))
+        else {
+            continue;
+        };
+
+        pool.token_a = token_a;
+        pool.token_a_decimals = *token_a_decimals;
+        pool.token_b = token_b;
+        pool.token_b_decimals = *token_b_decimals;
+        pool.decimals_synced = true;
+        pool.reserve_0 = reserve_0;
+        pool.reserve_1 = reserve_1;
+        pool.last_active_at = last_active_at;
+    }
+
+    Ok(())
+}
+
+//Returns the return data of each call in order, `None` for calls that returned an error response, ie. reverted
+async fn batch_eth_call<M: Middleware>(
+    calls: &[(H160, [u8; 4])],
+    block: BlockNumber,
+    middleware: Arc<M>,
+) -> Result<Vec<Option<Bytes>>, AMMError<M>> {
+    stream::iter(calls)
+        .map(|(to, selector)| {
+            let middleware = middleware.clone();
+            let tx = TransactionRequest::new().to(*to).data(selector.to_vec());
+
+            async move {
+                match middleware.call(&tx.into(), Some(block.into())).await {
+                    Ok(return_data) => Ok(Some(return_data)),
+                    Err(e) if e.as_error_response().is_some() => Ok(None),
+                    Err(e) => Err(AMMError::MiddlewareError(e)),
+                }
+            }
+        })
+        .buffered(JSON_RPC_BATCH_SIZE)
+        .try_collect()
+        .await
+}
+
+fn decode_address(return_data: &Bytes) -> Option<H160> {
+    ethers::abi::decode(&[ParamType::Address], return_data)
+        .ok()?
+        .pop()?
+        .into_address()
+}
+
+//Returns `None` if the return data does not decode, and an error if it decodes to values that do not fit the pool fields
+fn decode_reserves<M: Middleware>(
+    pool: H160,
+    return_data: &Bytes,
+) -> Result<Option<(u128, u128, u32)>, AMMError<M>> {
+    let Ok(tokens) = ethers::abi::decode(
+        &[
+            ParamType::Uint(112),
+            ParamType::Uint(112),
+            ParamType::Uint(32),
+        ],
+        return_data,
+    ) else {
+        return Ok(None);
+    };
+
+    let invalid_data = || AMMError::<M>::BatchRequestError(pool);
+    let uint = |idx: usize| tokens[idx].to_owned().into_uint().ok_or_else(invalid_data);
+
+    Ok(Some((
+        uint(0)?.try_into().map_err(|_| invalid_data())?,
+        uint(1)?.try_into().map_err(|_| invalid_data())?,
+        uint(2)?.try_into().map_err(|_| invalid_data())?,
+    )))
+}
+
+fn decode_decimals(return_data: &Bytes) -> Option<u8> {
+    ethers::abi::decode(&[ParamType::Uint(8)], return_data)
+        .ok()?
+        .pop()?
+        .into_uint()?
+        .try_into()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::{Bytes, H160, U256},
+    };
+
+    use crate::{amm::uniswap_v2::UniswapV2Pool, errors::AMMError};
+
+    use super::{decode_reserves, get_pool_data_json_rpc_batch};
+
+    #[test]
+    fn test_decode_reserves() {
+        let pool = H160::from_low_u64_be(1);
+        let return_data = |reserve_0: U256| {
+            Bytes::from(ethers::abi::encode(&[
+                ethers::abi::Token::Uint(reserve_0),
+                ethers::abi::Token::Uint(U256::from(2000)),
+                ethers::abi::Token::Uint(U256::from(1700000000)),
+            ]))
+        };
+
+        assert_eq!(
+            decode_reserves::<Provider<Http>>(pool, &return_data(U256::from(1000))).unwrap(),
+            Some((1000, 2000, 1700000000))
+        );
+
+        //Return data that is not a `getReserves()` response is ignored, out of range reserves are an error
+        assert_eq!(
+            decode_reserves::<Provider<Http>>(pool, &Bytes::from(vec![1, 2, 3])).unwrap(),
+            None
+        );
+        assert!(matches!(
+            decode_reserves::<Provider<Http>>(pool, &return_data(U256::MAX)),
+            Err(AMMError::BatchRequestError(address)) if address == pool
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_pool_data_json_rpc_batch() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let mut pools = vec![
+            //USDC/WETH
+            UniswapV2Pool {
+                address: H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?,
+                ..Default::default()
+            },
+            //Not a pair
+            UniswapV2Pool {
+                address: H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f")?,
+                ..Default::default()
+            },
+        ];
+
+        get_pool_data_json_rpc_batch(&mut pools, None, middleware).await?;
+
+        assert_eq!(
+            pools[0].token_a,
+            H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?
+        );
+        assert_eq!(pools[0].token_a_decimals, 6);
+        assert_eq!(
+            pools[0].token_b,
+            H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?
+        );
+        assert_eq!(pools[0].token_b_decimals, 18);
+        assert!(pools[0].decimals_synced);
+        assert_ne!(pools[0].reserve_0, 0);
+        assert_ne!(pools[0].reserve_1, 0);
+
+        assert!(pools[1].token_a.is_zero());
+        assert!(!pools[1].decimals_synced);
+
+        Ok(())
+    }
+}
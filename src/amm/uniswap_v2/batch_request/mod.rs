@@ -1,18 +1,20 @@
+pub mod json_rpc;
+
 use ethers::{
     abi::{ParamType, Token},
     providers::Middleware,
     types::{Bytes, H160, U256},
 };
-use std::sync::Arc;
+use std::{collections::HashSet, sync::Arc};
 
 use crate::{
-    amm::{AutomatedMarketMaker, AMM},
+    amm::{call_options::CallOptions, AutomatedMarketMaker, AMM},
     errors::AMMError,
 };
 
 use ethers::prelude::abigen;
 
-use super::UniswapV2Pool;
+use super::{IErc20, IUniswapV2Pair, UniswapV2Pool};
 
 abigen!(
 
@@ -23,25 +25,169 @@ abigen!(
         "src/amm/uniswap_v2/batch_request/GetUniswapV2PoolDataBatchRequestABI.json";
 );
 
-fn populate_pool_data_from_tokens(
+lazy_static::lazy_static! {
+    static ref PAIRS_RETURN_TYPES: Vec<ParamType> =
+        vec![ParamType::Array(Box::new(ParamType::Address))];
+    static ref POOL_DATA_RETURN_TYPES: Vec<ParamType> =
+        vec![ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Address,   // token a
+            ParamType::Uint(8),   // token a decimals
+            ParamType::Address,   // token b
+            ParamType::Uint(8),   // token b decimals
+            ParamType::Uint(112), // reserve 0
+            ParamType::Uint(112), // reserve 1
+            ParamType::Uint(32),  // block timestamp last
+            ParamType::Uint(256), // total supply
+        ])))];
+}
+
+/// Bit width of the reserves of a standard Uniswap V2 pair.
+pub const DEFAULT_RESERVE_BITS: u8 = 112;
+
+//Reserves are stored as u128, so wider fields can not be represented
+fn pool_data_return_types<M: Middleware>(
+    call_options: &CallOptions,
+) -> Result<Vec<ParamType>, AMMError<M>> {
+    let reserve_bits = call_options.reserve_bits.unwrap_or(DEFAULT_RESERVE_BITS);
+
+    if reserve_bits == DEFAULT_RESERVE_BITS {
+        return Ok(POOL_DATA_RETURN_TYPES.clone());
+    }
+
+    if reserve_bits == 0 || reserve_bits > 128 || reserve_bits % 8 != 0 {
+        return Err(AMMError::InvalidReserveBits(reserve_bits));
+    }
+
+    Ok(vec![ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Address,                     // token a
+        ParamType::Uint(8),                     // token a decimals
+        ParamType::Address,                     // token b
+        ParamType::Uint(8),                     // token b decimals
+        ParamType::Uint(reserve_bits as usize), // reserve 0
+        ParamType::Uint(reserve_bits as usize), // reserve 1
+        ParamType::Uint(32),                    // block timestamp last
+        ParamType::Uint(256),                   // total supply
+    ])))])
+}
+
+/// How to handle pools with a token whose `decimals()` reverts or returns an invalid value when populating pool data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DecimalsPolicy {
+    /// Populate the pool assuming the token has 18 decimals.
+    AssumeEighteen,
+    /// Leave the pool unpopulated so that it is dropped with the other empty pools.
+    #[default]
+    Skip,
+    /// Fail the batch with `AMMError::InvalidTokenDecimals`.
+    Error,
+}
+
+//Values that do not fit the fields of the pool are rejected rather than truncated
+//The ABI decoder reads every uint as a full word, so the reserves are checked against `reserve_bits` here
+fn populate_pool_data_from_tokens<M: Middleware>(
     mut pool: UniswapV2Pool,
     tokens: Vec<Token>,
-) -> Option<UniswapV2Pool> {
-    pool.token_a = tokens[0].to_owned().into_address()?;
-    pool.token_a_decimals = tokens[1].to_owned().into_uint()?.as_u32() as u8;
-    pool.token_b = tokens[2].to_owned().into_address()?;
-    pool.token_b_decimals = tokens[3].to_owned().into_uint()?.as_u32() as u8;
-    pool.reserve_0 = tokens[4].to_owned().into_uint()?.as_u128();
-    pool.reserve_1 = tokens[5].to_owned().into_uint()?.as_u128();
-    pool.last_active_at = tokens[6].to_owned().into_uint()?.as_u32();
-
-    Some(pool)
+    reserve_bits: u8,
+) -> Result<UniswapV2Pool, AMMError<M>> {
+    let pool_address = pool.address;
+    let invalid_data = || AMMError::<M>::BatchRequestError(pool_address);
+    let address = |idx: usize| {
+        tokens[idx]
+            .to_owned()
+            .into_address()
+            .ok_or_else(invalid_data)
+    };
+    let uint = |idx: usize| tokens[idx].to_owned().into_uint().ok_or_else(invalid_data);
+    let reserve = |idx: usize| -> Result<u128, AMMError<M>> {
+        let reserve = uint(idx)?;
+        if reserve >> reserve_bits != U256::zero() {
+            return Err(AMMError::ReserveOverflow(pool_address, reserve_bits));
+        }
+
+        reserve.try_into().map_err(|_| invalid_data())
+    };
+
+    pool.token_a = address(0)?;
+    pool.token_a_decimals = uint(1)?.try_into().map_err(|_| invalid_data())?;
+    pool.token_b = address(2)?;
+    pool.token_b_decimals = uint(3)?.try_into().map_err(|_| invalid_data())?;
+    pool.decimals_synced = true;
+    pool.reserve_0 = reserve(4)?;
+    pool.reserve_1 = reserve(5)?;
+    pool.last_active_at = uint(6)?.try_into().map_err(|_| invalid_data())?;
+    pool.total_supply = uint(7)?;
+
+    Ok(pool)
 }
 
 pub async fn get_pairs_batch_request<M: Middleware>(
     factory: H160,
     from: U256,
     step: U256,
+    call_options: &CallOptions,
+    middleware: Arc<M>,
+) -> Result<Vec<H160>, AMMError<M>> {
+    let pairs = get_pairs_batch_request_raw(factory, from, step, call_options, middleware).await?;
+
+    Ok(dedup_pairs(
+        pairs.into_iter().filter(|addr| !addr.is_zero()).collect(),
+    ))
+}
+
+/// Removes repeated pair addresses, keeping the first occurrence of each. Forks with a faulty `allPairs` can list the same pair at several indices.
+pub fn dedup_pairs(pairs: Vec<H160>) -> Vec<H160> {
+    let mut seen = HashSet::new();
+
+    pairs
+        .into_iter()
+        .filter(|pair| seen.insert(*pair))
+        .collect()
+}
+
+/// Returns the pairs of `factory` from index `from` up to `to`, fetched in batches of at most `initial_step` pairs.
+/// When the node rejects a batch, ie. because the batch contract exceeds the code size or gas limit, the step is halved and the same window is retried.
+/// Returns the pairs along with the converged step, the largest step that succeeded, which can be reused as the initial step for the same chain.
+pub async fn get_pairs_batch_request_adaptive<M: Middleware>(
+    factory: H160,
+    from: U256,
+    to: U256,
+    initial_step: U256,
+    call_options: &CallOptions,
+    middleware: Arc<M>,
+) -> Result<(Vec<H160>, U256), AMMError<M>> {
+    let mut pairs = vec![];
+    let mut step = initial_step.max(U256::one());
+    let mut idx_from = from;
+
+    while idx_from < to {
+        let count = step.min(to - idx_from);
+
+        match get_pairs_batch_request(factory, idx_from, count, call_options, middleware.clone())
+            .await
+        {
+            Ok(mut batch) => {
+                pairs.append(&mut batch);
+                idx_from += count;
+            }
+
+            //Retry the same window with half the step, down to a single pair
+            Err(e) if e.is_batch_rejected() && count > U256::one() => {
+                step = count / 2;
+            }
+
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok((dedup_pairs(pairs), step))
+}
+
+/// Returns the pairs in the range without filtering out zero addresses, so that each element maps to an index of `allPairs`.
+pub async fn get_pairs_batch_request_raw<M: Middleware>(
+    factory: H160,
+    from: U256,
+    step: U256,
+    call_options: &CallOptions,
     middleware: Arc<M>,
 ) -> Result<Vec<H160>, AMMError<M>> {
     let constructor_args = Token::Tuple(vec![
@@ -50,26 +196,33 @@ pub async fn get_pairs_batch_request<M: Middleware>(
         Token::Address(factory),
     ]);
 
-    let deployer = IGetUniswapV2PairsBatchRequest::deploy(middleware, constructor_args)
+    let mut deployer = IGetUniswapV2PairsBatchRequest::deploy(middleware, constructor_args.clone())
         .map_err(|e| AMMError::ContractError("get_pairs_batch_request", factory, e))?;
+    if let Some(block) = call_options.block {
+        deployer = deployer.block(block);
+    }
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
+
     let return_data: Bytes = deployer
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("get_pairs_batch_request", factory, e))?;
+        .map_err(|e| AMMError::batch_call_error("get_pairs_batch_request", factory, e))?;
 
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Array(Box::new(ParamType::Address))],
-        &return_data,
-    )?;
+    let return_data_tokens =
+        ethers::abi::decode(&PAIRS_RETURN_TYPES, &return_data).map_err(|source| {
+            AMMError::AbiDecodeError {
+                context: "get_pairs_batch_request_raw",
+                source,
+            }
+        })?;
 
     let mut pairs = vec![];
     for token_array in return_data_tokens {
         if let Some(arr) = token_array.into_array() {
             for token in arr {
                 if let Some(addr) = token.into_address() {
-                    if !addr.is_zero() {
-                        pairs.push(addr);
-                    }
+                    pairs.push(addr);
                 }
             }
         }
@@ -78,11 +231,56 @@ pub async fn get_pairs_batch_request<M: Middleware>(
     Ok(pairs)
 }
 
+/// Populates the pool data of `amms`, returning the number of the block the pools were read at.
+/// When the node rejects a batch, ie. because the return data exceeds its limit, the batch is split in halves and each half is retried, down to single pools.
+/// Every batch is made at the same block, so the pools are consistent with each other.
 pub async fn get_amm_data_batch_request<M: Middleware>(
     amms: &mut [AMM],
+    decimals_policy: DecimalsPolicy,
+    call_options: &CallOptions,
+    middleware: Arc<M>,
+) -> Result<u64, AMMError<M>> {
+    let block_number = call_options.resolve_block(middleware.clone()).await?;
+    let call_options = call_options.clone().block(block_number);
+
+    //Ranges of `amms` left to request, the first half of a split range is requested first
+    let mut ranges = vec![0..amms.len()];
+    while let Some(range) = ranges.pop() {
+        match get_amm_data_single_batch_request(
+            &mut amms[range.clone()],
+            decimals_policy,
+            block_number,
+            &call_options,
+            middleware.clone(),
+        )
+        .await
+        {
+            Ok(_) => {}
+
+            Err(e) if e.is_batch_rejected() && range.len() > 1 => {
+                let mid = range.start + range.len() / 2;
+                ranges.push(mid..range.end);
+                ranges.push(range.start..mid);
+            }
+
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(block_number)
+}
+
+//Populates the pool data of `amms` at `block_number` with a single batch request
+async fn get_amm_data_single_batch_request<M: Middleware>(
+    amms: &mut [AMM],
+    decimals_policy: DecimalsPolicy,
+    block_number: u64,
+    call_options: &CallOptions,
     middleware: Arc<M>,
 ) -> Result<(), AMMError<M>> {
     let batch_start = amms.first().map(|a| a.address()).unwrap_or_default();
+    let return_types = pool_data_return_types(call_options)?;
+    let reserve_bits = call_options.reserve_bits.unwrap_or(DEFAULT_RESERVE_BITS);
 
     let target_addresses = amms
         .iter()
@@ -91,27 +289,27 @@ pub async fn get_amm_data_batch_request<M: Middleware>(
 
     let constructor_args = Token::Tuple(vec![Token::Array(target_addresses)]);
 
-    let deployer = IGetUniswapV2PoolDataBatchRequest::deploy(middleware.clone(), constructor_args)
-        .map_err(|e| AMMError::ContractError("get_amm_data_batch_request", batch_start, e))?;
+    let mut deployer =
+        IGetUniswapV2PoolDataBatchRequest::deploy(middleware.clone(), constructor_args.clone())
+            .map_err(|e| AMMError::ContractError("get_amm_data_batch_request", batch_start, e))?;
+    deployer = deployer.block(block_number);
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
 
     let return_data: Bytes = deployer
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("get_amm_data_batch_request", batch_start, e))?;
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Array(Box::new(ParamType::Tuple(vec![
-            ParamType::Address,   // token a
-            ParamType::Uint(8),   // token a decimals
-            ParamType::Address,   // token b
-            ParamType::Uint(8),   // token b decimals
-            ParamType::Uint(112), // reserve 0
-            ParamType::Uint(112), // reserve 1
-            ParamType::Uint(32),  // block timestamp last 
-        ])))],
-        &return_data,
-    )?;
+        .map_err(|e| AMMError::batch_call_error("get_amm_data_batch_request", batch_start, e))?;
+    let return_data_tokens =
+        ethers::abi::decode(&return_types, &return_data).map_err(|source| {
+            AMMError::AbiDecodeError {
+                context: "get_amm_data_batch_request",
+                source,
+            }
+        })?;
 
     let mut pool_idx = 0;
+    let mut unpopulated = vec![];
 
     for tokens in return_data_tokens {
         if let Some(tokens_arr) = tokens.into_array() {
@@ -119,18 +317,19 @@ pub async fn get_amm_data_batch_request<M: Middleware>(
                 if let Some(pool_data) = tup.into_tuple() {
                     //If the pool token A is not zero, signaling that the pool data was populated
                     if let Some(address) = pool_data[0].to_owned().into_address() {
-                        if !address.is_zero() {
+                        if address.is_zero() {
+                            unpopulated.push(pool_idx);
+                        } else {
                             //Update the pool data
                             if let AMM::UniswapV2Pool(uniswap_v2_pool) = amms
                                 .get_mut(pool_idx)
                                 .expect("Pool idx should be in bounds")
                             {
-                                if let Some(pool) = populate_pool_data_from_tokens(
+                                *uniswap_v2_pool = populate_pool_data_from_tokens(
                                     uniswap_v2_pool.to_owned(),
                                     pool_data,
-                                ) {
-                                    *uniswap_v2_pool = pool;
-                                }
+                                    reserve_bits,
+                                )?;
                             }
                         }
                     }
@@ -141,34 +340,201 @@ pub async fn get_amm_data_batch_request<M: Middleware>(
         }
     }
 
+    //The batch contract skips pools with invalid decimals, so they are populated with individual calls instead
+    if decimals_policy != DecimalsPolicy::Skip {
+        for idx in unpopulated {
+            if let Some(AMM::UniswapV2Pool(pool)) = amms.get_mut(idx) {
+                populate_pool_data_with_decimals_policy(
+                    pool,
+                    decimals_policy,
+                    block_number,
+                    call_options,
+                    middleware.clone(),
+                )
+                .await?;
+            }
+        }
+    }
+
     Ok(())
 }
 
-pub async fn get_v2_pool_data_batch_request<M: Middleware>(
+//Pools that are not pairs or whose reserves can not be read are left unpopulated, as in the batch request
+//Every call is made at `block_number`, the block of the batch request, and waits for the rate limiter of `call_options`
+async fn populate_pool_data_with_decimals_policy<M: Middleware>(
     pool: &mut UniswapV2Pool,
+    decimals_policy: DecimalsPolicy,
+    block_number: u64,
+    call_options: &CallOptions,
     middleware: Arc<M>,
 ) -> Result<(), AMMError<M>> {
-    let constructor_args = Token::Tuple(vec![Token::Array(vec![Token::Address(pool.address)])]);
+    let v2_pair = IUniswapV2Pair::new(pool.address, middleware.clone());
 
-    let deployer = IGetUniswapV2PoolDataBatchRequest::deploy(middleware.clone(), constructor_args)
-        .map_err(|e| AMMError::ContractError("get_v2_pool_data_batch_request", pool.address, e))?;
+    call_options.acquire().await;
+    let Ok(token_a) = v2_pair.token_0().block(block_number).call().await else {
+        return Ok(());
+    };
+    call_options.acquire().await;
+    let Ok(token_b) = v2_pair.token_1().block(block_number).call().await else {
+        return Ok(());
+    };
 
-    let return_data: Bytes = deployer
-        .call_raw()
+    let Some((token_a_decimals, token_a_decimals_read)) = get_token_decimals(
+        token_a,
+        decimals_policy,
+        block_number,
+        call_options,
+        middleware.clone(),
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+    let Some((token_b_decimals, token_b_decimals_read)) = get_token_decimals(
+        token_b,
+        decimals_policy,
+        block_number,
+        call_options,
+        middleware.clone(),
+    )
+    .await?
+    else {
+        return Ok(());
+    };
+
+    call_options.acquire().await;
+    let Ok((reserve_0, reserve_1, last_active_at)) =
+        v2_pair.get_reserves().block(block_number).call().await
+    else {
+        return Ok(());
+    };
+    //Pairs that do not expose the LP token supply are left at zero, as in the batch request
+    call_options.acquire().await;
+    let total_supply = v2_pair
+        .total_supply()
+        .block(block_number)
+        .call()
         .await
-        .map_err(|e| AMMError::ProviderError("get_v2_pool_data_batch_request", pool.address, e))?;
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Array(Box::new(ParamType::Tuple(vec![
-            ParamType::Address,   // token a
-            ParamType::Uint(8),   // token a decimals
-            ParamType::Address,   // token b
-            ParamType::Uint(8),   // token b decimals
-            ParamType::Uint(112), // reserve 0
-            ParamType::Uint(112), // reserve 1
-            ParamType::Uint(32),  // block timestamp last 
-        ])))],
-        &return_data,
-    )?;
+        .unwrap_or_default();
+
+    pool.token_a = token_a;
+    pool.token_a_decimals = token_a_decimals;
+    pool.token_b = token_b;
+    pool.token_b_decimals = token_b_decimals;
+    //Assumed decimals were not read from the chain
+    pool.decimals_synced = token_a_decimals_read && token_b_decimals_read;
+    pool.reserve_0 = reserve_0;
+    pool.reserve_1 = reserve_1;
+    pool.last_active_at = last_active_at;
+    pool.total_supply = total_supply;
+
+    Ok(())
+}
+
+//Returns the decimals of the token along with whether they were read rather than assumed
+async fn get_token_decimals<M: Middleware>(
+    token: H160,
+    decimals_policy: DecimalsPolicy,
+    block_number: u64,
+    call_options: &CallOptions,
+    middleware: Arc<M>,
+) -> Result<Option<(u8, bool)>, AMMError<M>> {
+    call_options.acquire().await;
+    match IErc20::new(token, middleware)
+        .decimals()
+        .block(block_number)
+        .call()
+        .await
+    {
+        Ok(decimals) => Ok(Some((decimals, true))),
+        _ => match decimals_policy {
+            DecimalsPolicy::AssumeEighteen => Ok(Some((18, false))),
+            DecimalsPolicy::Skip => Ok(None),
+            DecimalsPolicy::Error => Err(AMMError::InvalidTokenDecimals(token)),
+        },
+    }
+}
+
+/// Fetches the current reserves of `pools` and returns for each pool whether the cached reserves match.
+/// Every batch of pools is read at the same block, so all reserves are from the same block.
+pub async fn audit_reserves<M: Middleware>(
+    pools: &[UniswapV2Pool],
+    middleware: Arc<M>,
+) -> Result<Vec<(H160, bool)>, AMMError<M>> {
+    let step = 109; //Max batch size for call
+    let block_number = CallOptions::default()
+        .resolve_block(middleware.clone())
+        .await?;
+    let call_options = CallOptions::new().block(block_number);
+
+    let mut audit = Vec::with_capacity(pools.len());
+    for pool_chunk in pools.chunks(step) {
+        let mut amms = pool_chunk
+            .iter()
+            .map(|pool| {
+                AMM::UniswapV2Pool(UniswapV2Pool {
+                    address: pool.address,
+                    ..Default::default()
+                })
+            })
+            .collect::<Vec<AMM>>();
+
+        get_amm_data_batch_request(
+            &mut amms,
+            DecimalsPolicy::Skip,
+            &call_options,
+            middleware.clone(),
+        )
+        .await?;
+
+        for (pool, amm) in pool_chunk.iter().zip(amms) {
+            //Pools that could not be read are reported as not matching
+            let matches = match amm {
+                AMM::UniswapV2Pool(fresh_pool) => {
+                    !fresh_pool.token_a.is_zero()
+                        && fresh_pool.reserve_0 == pool.reserve_0
+                        && fresh_pool.reserve_1 == pool.reserve_1
+                }
+                _ => false,
+            };
+
+            audit.push((pool.address, matches));
+        }
+    }
+
+    Ok(audit)
+}
+
+/// Populates the data of `pool`, returning the number of the block the pool was read at.
+pub async fn get_v2_pool_data_batch_request<M: Middleware>(
+    pool: &mut UniswapV2Pool,
+    call_options: &CallOptions,
+    middleware: Arc<M>,
+) -> Result<u64, AMMError<M>> {
+    let return_types = pool_data_return_types(call_options)?;
+    let reserve_bits = call_options.reserve_bits.unwrap_or(DEFAULT_RESERVE_BITS);
+    let constructor_args = Token::Tuple(vec![Token::Array(vec![Token::Address(pool.address)])]);
+
+    let mut deployer =
+        IGetUniswapV2PoolDataBatchRequest::deploy(middleware.clone(), constructor_args.clone())
+            .map_err(|e| {
+                AMMError::ContractError("get_v2_pool_data_batch_request", pool.address, e)
+            })?;
+    let block_number = call_options.resolve_block(middleware.clone()).await?;
+    deployer = deployer.block(block_number);
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
+
+    let return_data: Bytes = deployer.call_raw().await.map_err(|e| {
+        AMMError::batch_call_error("get_v2_pool_data_batch_request", pool.address, e)
+    })?;
+    let return_data_tokens =
+        ethers::abi::decode(&return_types, &return_data).map_err(|source| {
+            AMMError::AbiDecodeError {
+                context: "get_v2_pool_data_batch_request",
+                source,
+            }
+        })?;
 
     for tokens in return_data_tokens {
         if let Some(tokens_arr) = tokens.into_array() {
@@ -177,11 +543,341 @@ pub async fn get_v2_pool_data_batch_request<M: Middleware>(
                     .into_tuple()
                     .ok_or(AMMError::BatchRequestError(pool.address))?;
 
-                *pool = populate_pool_data_from_tokens(pool.to_owned(), pool_data)
-                    .ok_or(AMMError::BatchRequestError(pool.address))?;
+                *pool = populate_pool_data_from_tokens(pool.to_owned(), pool_data, reserve_bits)?;
             }
         }
     }
 
-    Ok(())
+    Ok(block_number)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        abi::{ParamType, Token},
+        providers::{Http, Provider},
+        types::{H160, U256},
+    };
+
+    use crate::{
+        amm::{call_options::CallOptions, uniswap_v2::UniswapV2Pool, AMM},
+        errors::AMMError,
+    };
+
+    use super::{
+        audit_reserves, dedup_pairs, get_amm_data_batch_request, get_pairs_batch_request,
+        get_pairs_batch_request_adaptive, get_token_decimals, get_v2_pool_data_batch_request,
+        pool_data_return_types, populate_pool_data_from_tokens, DecimalsPolicy, IUniswapV2Pair,
+        POOL_DATA_RETURN_TYPES,
+    };
+
+    #[test]
+    fn test_pool_data_return_types() {
+        let return_types =
+            pool_data_return_types::<Provider<Http>>(&CallOptions::default()).unwrap();
+        assert_eq!(return_types, *POOL_DATA_RETURN_TYPES);
+
+        let return_types =
+            pool_data_return_types::<Provider<Http>>(&CallOptions::new().reserve_bits(128))
+                .unwrap();
+        let ParamType::Array(inner) = &return_types[0] else {
+            panic!("Expected an array of pool data");
+        };
+        let ParamType::Tuple(fields) = inner.as_ref() else {
+            panic!("Expected a pool data tuple");
+        };
+        assert_eq!(fields[4], ParamType::Uint(128));
+        assert_eq!(fields[5], ParamType::Uint(128));
+
+        for reserve_bits in [0, 100, 136] {
+            assert!(matches!(
+                pool_data_return_types::<Provider<Http>>(
+                    &CallOptions::new().reserve_bits(reserve_bits)
+                ),
+                Err(AMMError::InvalidReserveBits(bits)) if bits == reserve_bits
+            ));
+        }
+    }
+
+    #[test]
+    fn test_populate_pool_data_from_tokens() {
+        let address = H160::from_low_u64_be(1);
+        let pool_data = |reserve_0: U256| {
+            vec![
+                Token::Address(H160::from_low_u64_be(2)),
+                Token::Uint(U256::from(18)),
+                Token::Address(H160::from_low_u64_be(3)),
+                Token::Uint(U256::from(6)),
+                Token::Uint(reserve_0),
+                Token::Uint(U256::from(2000)),
+                Token::Uint(U256::from(1700000000)),
+                Token::Uint(U256::from(100)),
+            ]
+        };
+        let pool = UniswapV2Pool {
+            address,
+            ..Default::default()
+        };
+
+        let populated = populate_pool_data_from_tokens::<Provider<Http>>(
+            pool.clone(),
+            pool_data(1000.into()),
+            112,
+        )
+        .unwrap();
+        assert_eq!(populated.token_a_decimals, 18);
+        assert_eq!(populated.token_b_decimals, 6);
+        assert_eq!((populated.reserve_0, populated.reserve_1), (1000, 2000));
+        assert_eq!(populated.last_active_at, 1700000000);
+        assert_eq!(populated.total_supply, U256::from(100));
+
+        //Reserves wider than the reserve bit width are rejected instead of being truncated
+        assert!(matches!(
+            populate_pool_data_from_tokens::<Provider<Http>>(
+                pool.clone(),
+                pool_data(U256::MAX),
+                128
+            ),
+            Err(AMMError::ReserveOverflow(pool_address, 128)) if pool_address == address
+        ));
+        assert!(matches!(
+            populate_pool_data_from_tokens::<Provider<Http>>(
+                pool.clone(),
+                pool_data(U256::one() << 112),
+                112
+            ),
+            Err(AMMError::ReserveOverflow(pool_address, 112)) if pool_address == address
+        ));
+        let populated = populate_pool_data_from_tokens::<Provider<Http>>(
+            pool,
+            pool_data(U256::one() << 112),
+            128,
+        )
+        .unwrap();
+        assert_eq!(populated.reserve_0, 1 << 112);
+    }
+
+    #[test]
+    fn test_dedup_pairs() {
+        let pair = H160::from_low_u64_be;
+
+        assert_eq!(
+            dedup_pairs(vec![pair(3), pair(1), pair(3), pair(2), pair(1)]),
+            vec![pair(3), pair(1), pair(2)]
+        );
+        assert!(dedup_pairs(vec![]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_pairs_batch_request_adaptive() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //Uniswap v2 factory
+        let factory = H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f")?;
+
+        //A batch of 5000 pairs exceeds the code size limit of the batch contract, so the step has to shrink
+        let (pairs, step) = get_pairs_batch_request_adaptive(
+            factory,
+            U256::zero(),
+            U256::from(5000),
+            U256::from(5000),
+            &CallOptions::default(),
+            middleware.clone(),
+        )
+        .await?;
+
+        assert_eq!(pairs.len(), 5000);
+        assert!(step < U256::from(5000));
+
+        //The converged step works as a fixed step
+        let first_batch = get_pairs_batch_request(
+            factory,
+            U256::zero(),
+            step,
+            &CallOptions::default(),
+            middleware,
+        )
+        .await?;
+        assert_eq!(first_batch[..], pairs[..step.as_usize()]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_v2_pool_data_at_block() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //USDC/WETH pair
+        let address = H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?;
+        let block_number = 12000000_u64;
+
+        let mut pool = UniswapV2Pool {
+            address,
+            ..Default::default()
+        };
+        let read_at = get_v2_pool_data_batch_request(
+            &mut pool,
+            &CallOptions::new().block(block_number),
+            middleware.clone(),
+        )
+        .await?;
+        assert_eq!(read_at, block_number);
+
+        let (reserve_0, reserve_1, _) = IUniswapV2Pair::new(address, middleware)
+            .get_reserves()
+            .block(block_number)
+            .call()
+            .await?;
+
+        assert_eq!(pool.reserve_0, reserve_0);
+        assert_eq!(pool.reserve_1, reserve_1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_amm_data_batch_request_block_number() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //USDC/WETH pair, which is swapped against in virtually every block
+        let address = H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?;
+        let mut amms = vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address,
+            ..Default::default()
+        })];
+
+        let read_at = get_amm_data_batch_request(
+            &mut amms,
+            DecimalsPolicy::default(),
+            &CallOptions::default(),
+            middleware.clone(),
+        )
+        .await?;
+
+        //The reserves and total supply read are the ones at the returned block, even if the chain moved on since
+        let v2_pair = IUniswapV2Pair::new(address, middleware);
+        let (reserve_0, reserve_1, _) = v2_pair.get_reserves().block(read_at).call().await?;
+        let total_supply = v2_pair.total_supply().block(read_at).call().await?;
+
+        let AMM::UniswapV2Pool(pool) = &amms[0] else {
+            panic!("Expected a Uniswap V2 pool");
+        };
+        assert_eq!(pool.reserve_0, reserve_0);
+        assert_eq!(pool.reserve_1, reserve_1);
+        assert_eq!(pool.total_supply, total_supply);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_amm_data_batch_request_zero_decimals() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //CHI/WETH pair, CHI has 0 decimals
+        let mut amms = vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address: H160::from_str("0xa6f3ef841d371a82ca757FaD08efc0DeE2F1f5e2")?,
+            ..Default::default()
+        })];
+
+        //Skipping pools with invalid decimals ensures the pool data comes from the batch contract
+        get_amm_data_batch_request(
+            &mut amms,
+            DecimalsPolicy::Skip,
+            &CallOptions::default(),
+            middleware,
+        )
+        .await?;
+
+        let AMM::UniswapV2Pool(pool) = &amms[0] else {
+            panic!("Expected a Uniswap V2 pool");
+        };
+        assert_eq!(
+            pool.token_a,
+            H160::from_str("0x0000000000004946c0e9F43F4Dee607b0eF1fA1c")?
+        );
+        assert_eq!(pool.token_a_decimals, 0);
+        assert_eq!(pool.token_b_decimals, 18);
+        assert!(pool.decimals_synced);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_get_token_decimals() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+        let block_number = 12000000_u64;
+        let call_options = CallOptions::default();
+
+        //CHI, which has 0 decimals
+        let chi = H160::from_str("0x0000000000004946c0e9F43F4Dee607b0eF1fA1c")?;
+        let decimals = get_token_decimals(
+            chi,
+            DecimalsPolicy::Error,
+            block_number,
+            &call_options,
+            middleware.clone(),
+        )
+        .await?;
+        assert_eq!(decimals, Some((0, true)));
+
+        //Not a token, decimals can not be read
+        let not_a_token = H160::from_low_u64_be(1);
+        assert!(matches!(
+            get_token_decimals(
+                not_a_token,
+                DecimalsPolicy::Error,
+                block_number,
+                &call_options,
+                middleware.clone()
+            )
+            .await,
+            Err(AMMError::InvalidTokenDecimals(token)) if token == not_a_token
+        ));
+        let decimals = get_token_decimals(
+            not_a_token,
+            DecimalsPolicy::AssumeEighteen,
+            block_number,
+            &call_options,
+            middleware,
+        )
+        .await?;
+        assert_eq!(decimals, Some((18, false)));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_audit_reserves() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //USDC/WETH pair with cached reserves that have drifted from the chain
+        let pool = UniswapV2Pool {
+            address: H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?,
+            reserve_0: 1,
+            reserve_1: 1,
+            ..Default::default()
+        };
+        //Not a pair, can not be read
+        let invalid_pool = UniswapV2Pool {
+            address: H160::from_low_u64_be(1),
+            ..Default::default()
+        };
+
+        let audit = audit_reserves(&[pool.clone(), invalid_pool.clone()], middleware).await?;
+
+        assert_eq!(
+            audit,
+            vec![(pool.address, false), (invalid_pool.address, false)]
+        );
+
+        Ok(())
+    }
 }
@@ -3,19 +3,26 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use ethers::{
     abi::RawLog,
-    prelude::EthEvent,
-    providers::Middleware,
+    prelude::{ContractError, EthEvent},
+    providers::{Middleware, MiddlewareError, RpcError},
     types::{Log, H160, H256, U256},
 };
 
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    amm::{factory::AutomatedMarketMakerFactory, AMM},
+    amm::{
+        call_options::CallOptions,
+        factory::{AutomatedMarketMakerFactory, Factory},
+        AutomatedMarketMaker, AMM,
+    },
     errors::AMMError,
 };
 
-use super::{batch_request, UniswapV2Pool};
+use super::{
+    batch_request::{self, DecimalsPolicy},
+    UniswapV2Pool, DEFAULT_FEE_DENOMINATOR,
+};
 
 use ethers::prelude::abigen;
 
@@ -26,6 +33,7 @@ abigen!(
         function allPairs(uint256 index) external view returns (address)
         event PairCreated(address indexed token0, address indexed token1, address pair, uint256)
         function allPairsLength() external view returns (uint256)
+        function swapFee() external view returns (uint256)
 
     ]"#;
 );
@@ -35,6 +43,11 @@ pub const PAIR_CREATED_EVENT_SIGNATURE: H256 = H256([
     131, 85, 205, 222, 253, 227, 26, 250, 40, 208, 233,
 ]);
 
+// Fee applied to pools whose factory does not expose a fee getter, 300 = 30 bps
+pub const DEFAULT_FEE: u32 = 300;
+// Block range of each `PairCreated` log request when fetching new pairs
+pub const NEW_PAIRS_LOG_STEP: u64 = 2000;
+
 #[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct UniswapV2Factory {
     pub address: H160,
@@ -51,6 +64,33 @@ impl UniswapV2Factory {
         }
     }
 
+    /// Reads the factory level fee from forks that expose `swapFee()`, returning the fee in the same units as `UniswapV2Pool::fee`.
+    /// `fee_denominator` is the scale of the value returned by the getter, ie. `BPS_FEE_DENOMINATOR` for forks returning basis points.
+    /// If the factory does not implement the getter, `DEFAULT_FEE` is returned. A fee that is not below `fee_denominator` returns an error.
+    pub async fn get_fee<M: Middleware>(
+        &self,
+        fee_denominator: u32,
+        middleware: Arc<M>,
+    ) -> Result<u32, AMMError<M>> {
+        let factory = IUniswapV2Factory::new(self.address, middleware);
+
+        match factory.swap_fee().call().await {
+            Ok(fee) if fee < U256::from(fee_denominator) => {
+                Ok((fee.as_u64() * DEFAULT_FEE_DENOMINATOR as u64 / fee_denominator as u64) as u32)
+            }
+            Ok(fee) => Err(AMMError::InvalidFactoryFee(self.address, fee)),
+
+            // Transport failures are propagated, any response from the node means the getter is not available
+            Err(ContractError::MiddlewareError { e }) if e.as_error_response().is_none() => Err(
+                AMMError::ContractError("get_fee", self.address, ContractError::MiddlewareError { e }),
+            ),
+            Err(ContractError::ProviderError { e }) if e.as_error_response().is_none() => Err(
+                AMMError::ContractError("get_fee", self.address, ContractError::ProviderError { e }),
+            ),
+            Err(_) => Ok(DEFAULT_FEE),
+        }
+    }
+
     pub async fn get_all_pairs_via_batched_calls<M: Middleware>(
         &self,
         middleware: Arc<M>,
@@ -78,6 +118,7 @@ impl UniswapV2Factory {
                     self.address,
                     idx_from,
                     idx_to,
+                    &CallOptions::default(),
                     middleware.clone(),
                 )
                 .await?,
@@ -94,10 +135,11 @@ impl UniswapV2Factory {
 
         let mut amms = vec![];
 
-        //Create new empty pools for each pair
-        for addr in pairs {
+        //Create new empty pools for each pair, pairs listed more than once across batches are only added once
+        for addr in batch_request::dedup_pairs(pairs) {
             let amm = UniswapV2Pool {
                 address: addr,
+                fee: self.fee,
                 ..Default::default()
             };
 
@@ -106,6 +148,63 @@ impl UniswapV2Factory {
 
         Ok(amms)
     }
+
+    /// Returns all pairs of the factory like `get_all_pairs_via_batched_calls`, tuning the batch size instead of using a fixed step.
+    /// Batches start at `initial_step` pairs and are halved whenever the node rejects them. The converged step is returned along with the pairs.
+    pub async fn get_all_pairs_via_adaptive_batched_calls<M: Middleware>(
+        &self,
+        initial_step: usize,
+        middleware: Arc<M>,
+    ) -> Result<(Vec<AMM>, usize), AMMError<M>> {
+        let factory = IUniswapV2Factory::new(self.address, middleware.clone());
+
+        let pairs_length: U256 = factory.all_pairs_length().call().await.map_err(|e| {
+            AMMError::ContractError("get_all_pairs_via_adaptive_batched_calls", self.address, e)
+        })?;
+
+        let (pairs, step) = batch_request::get_pairs_batch_request_adaptive(
+            self.address,
+            U256::zero(),
+            pairs_length,
+            U256::from(initial_step),
+            &CallOptions::default(),
+            middleware,
+        )
+        .await?;
+
+        let amms = pairs
+            .into_iter()
+            .map(|addr| {
+                AMM::UniswapV2Pool(UniswapV2Pool {
+                    address: addr,
+                    fee: self.fee,
+                    ..Default::default()
+                })
+            })
+            .collect();
+
+        Ok((amms, step.as_usize()))
+    }
+}
+
+/// Returns the pairs created by `factory` from `since_block` up to the latest block, scanning `PairCreated` logs instead of the `allPairs` array.
+pub async fn get_new_pairs_since<M: 'static + Middleware>(
+    factory: H160,
+    since_block: u64,
+    middleware: Arc<M>,
+) -> Result<Vec<H160>, AMMError<M>> {
+    let current_block = middleware
+        .get_block_number()
+        .await
+        .map_err(AMMError::MiddlewareError)?
+        .as_u64();
+
+    let factory = Factory::UniswapV2Factory(UniswapV2Factory::new(factory, since_block, 0));
+    let amms = factory
+        .get_all_pools_from_logs(since_block, current_block, NEW_PAIRS_LOG_STEP, middleware)
+        .await?;
+
+    Ok(amms.iter().map(|amm| amm.address()).collect())
 }
 
 #[async_trait]
@@ -143,9 +242,12 @@ impl AutomatedMarketMakerFactory for UniswapV2Factory {
             token_b_decimals: 0,
             reserve_0: 0,
             reserve_1: 0,
-            fee: 0,
+            fee: self.fee,
+            fee_denominator: DEFAULT_FEE_DENOMINATOR,
             last_active_at: 0,
             last_active_at_block: block_number,
+            total_supply: U256::zero(),
+            decimals_synced: false,
         }))
     }
 
@@ -161,12 +263,29 @@ impl AutomatedMarketMakerFactory for UniswapV2Factory {
     async fn populate_amm_data<M: Middleware>(
         &self,
         amms: &mut [AMM],
-        _block_number: Option<u64>,
+        block_number: Option<u64>,
         middleware: Arc<M>,
     ) -> Result<(), AMMError<M>> {
+        //The block is resolved once so that every chunk is read at the same block
+        let block_number = match block_number {
+            Some(block_number) => block_number,
+            None => {
+                CallOptions::default()
+                    .resolve_block(middleware.clone())
+                    .await?
+            }
+        };
+        let call_options = CallOptions::new().block(block_number);
+
         let step = 109; //Max batch size for call
         for amm_chunk in amms.chunks_mut(step) {
-            batch_request::get_amm_data_batch_request(amm_chunk, middleware.clone()).await?;
+            batch_request::get_amm_data_batch_request(
+                amm_chunk,
+                DecimalsPolicy::default(),
+                &call_options,
+                middleware.clone(),
+            )
+            .await?;
         }
         Ok(())
     }
@@ -175,3 +294,36 @@ impl AutomatedMarketMakerFactory for UniswapV2Factory {
         self.creation_block
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::H160,
+    };
+
+    use super::{UniswapV2Factory, DEFAULT_FEE};
+    use crate::amm::uniswap_v2::BPS_FEE_DENOMINATOR;
+
+    #[tokio::test]
+    async fn test_get_fee_falls_back_to_default() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        // Uniswap v2 factory does not expose a fee getter
+        let factory = UniswapV2Factory::new(
+            H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f")?,
+            10000835,
+            0,
+        );
+
+        assert_eq!(
+            factory.get_fee(BPS_FEE_DENOMINATOR, middleware).await?,
+            DEFAULT_FEE
+        );
+
+        Ok(())
+    }
+}
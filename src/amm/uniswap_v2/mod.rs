@@ -1,5 +1,9 @@
+pub mod analytics;
 pub mod batch_request;
 pub mod factory;
+pub mod pair_address;
+pub mod pricing;
+pub mod router;
 
 use std::sync::Arc;
 
@@ -8,19 +12,25 @@ use ethers::{
     abi::{ethabi::Bytes, RawLog, Token},
     prelude::EthEvent,
     providers::Middleware,
-    types::{Log, H160, H256, U64, U256},
+    types::{Log, H160, H256, I256, U64, U256},
 };
-use num_bigfloat::BigFloat;
 use serde::{Deserialize, Serialize};
 
+pub use crate::amm::math::{
+    div_uu, normalize_decimals, q64_to_f64, U128_0X10000000000000000, U256_0X100, U256_0X10000,
+    U256_0X100000000, U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF,
+    U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF, U256_128, U256_16, U256_191, U256_192,
+    U256_2, U256_255, U256_32, U256_4, U256_64, U256_8,
+};
+
 use crate::{
-    amm::AutomatedMarketMaker,
-    errors::{AMMError, ArithmeticError, EventLogError, SwapSimulationError},
+    amm::{call_options::CallOptions, AutomatedMarketMaker},
+    errors::{AMMError, ArithmeticError, EventLogError, PoolValidationError, SwapSimulationError},
 };
 
 use ethers::prelude::abigen;
 
-use self::factory::PAIR_CREATED_EVENT_SIGNATURE;
+use self::factory::{DEFAULT_FEE, PAIR_CREATED_EVENT_SIGNATURE};
 
 abigen!(
     IUniswapV2Pair,
@@ -28,8 +38,10 @@ abigen!(
         function getReserves() external view returns (uint112 reserve0, uint112 reserve1, uint32 blockTimestampLast)
         function token0() external view returns (address)
         function token1() external view returns (address)
+        function totalSupply() external view returns (uint256)
         function swap(uint256 amount0Out, uint256 amount1Out, address to, bytes calldata data);
         event Sync(uint112 reserve0, uint112 reserve1)
+        event Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out, address indexed to)
     ]"#;
 
     IErc20,
@@ -39,13 +51,30 @@ abigen!(
     ]"#;
 );
 
-pub const U128_0X10000000000000000: u128 = 18446744073709551616;
+// Fee denominator used by default, the fee is expressed in tenths of a basis point, ie. 300 => 0.3%
+pub const DEFAULT_FEE_DENOMINATOR: u32 = 100000;
+// Fee denominator for forks accounting fees in basis points, ie. 25 => 0.25%
+pub const BPS_FEE_DENOMINATOR: u32 = 10000;
+// Fee denominator for forks accounting fees in thousandths, ie. 3 => 0.3%
+pub const PER_MILLE_FEE_DENOMINATOR: u32 = 1000;
+// Largest value of a uint112, the type of the pair reserves on chain
+pub const U112_MAX: u128 = 5192296858534827628530496329220095;
+// Largest token decimals for which 10^decimals fits in a U256
+pub const MAX_TOKEN_DECIMALS: u8 = 77;
+// Maximum factor a reserve can move by in a single update before the update is considered invalid
+pub const DEFAULT_MAX_RESERVE_CHANGE_FACTOR: f64 = 10.0;
+// Approximate gas used by a swap called directly on the pair, including the token transfers
+pub const SWAP_GAS_ESTIMATE: u64 = 90000;
 pub const SYNC_EVENT_SIGNATURE: H256 = H256([
     28, 65, 30, 154, 150, 224, 113, 36, 28, 47, 33, 247, 114, 107, 23, 174, 137, 227, 202, 180,
     199, 139, 229, 14, 6, 43, 3, 169, 255, 251, 186, 209,
 ]);
+pub const SWAP_EVENT_SIGNATURE: H256 = H256([
+    215, 138, 217, 95, 164, 108, 153, 75, 101, 81, 208, 218, 133, 252, 39, 95, 230, 19, 206, 55,
+    101, 127, 184, 213, 227, 209, 48, 132, 1, 89, 216, 34,
+]);
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UniswapV2Pool {
     pub address: H160,
     pub token_a: H160,
@@ -54,11 +83,44 @@ pub struct UniswapV2Pool {
     pub token_b_decimals: u8,
     pub reserve_0: u128,
     pub reserve_1: u128,
+    /// Swap fee, as a fraction of `fee_denominator`
     pub fee: u32,
+    #[serde(default = "default_fee_denominator")]
+    pub fee_denominator: u32,
     /// Unix timestamp of block the pool reserves were updated last (might be out of sync with `last_active_at_block`)
     pub last_active_at: u32, 
     /// Number of block the pool was updated by logs from (might be out of sync with `last_active_at`)
     pub last_active_at_block: u64, 
+    /// Total supply of the LP token as of the last time the pool data was populated. `Sync` events do not carry the supply, so it is not updated from logs.
+    #[serde(default)]
+    pub total_supply: U256,
+    /// True once the token decimals were read from the chain, to tell tokens with zero decimals apart from decimals that were never fetched.
+    #[serde(default)]
+    pub decimals_synced: bool,
+}
+
+impl Default for UniswapV2Pool {
+    fn default() -> Self {
+        UniswapV2Pool {
+            address: H160::zero(),
+            token_a: H160::zero(),
+            token_a_decimals: 0,
+            token_b: H160::zero(),
+            token_b_decimals: 0,
+            reserve_0: 0,
+            reserve_1: 0,
+            fee: 0,
+            fee_denominator: DEFAULT_FEE_DENOMINATOR,
+            last_active_at: 0,
+            last_active_at_block: 0,
+            total_supply: U256::zero(),
+            decimals_synced: false,
+        }
+    }
+}
+
+fn default_fee_denominator() -> u32 {
+    DEFAULT_FEE_DENOMINATOR
 }
 
 #[async_trait]
@@ -77,7 +139,12 @@ impl AutomatedMarketMaker for UniswapV2Pool {
         _block_number: Option<u64>,
         middleware: Arc<M>,
     ) -> Result<(), AMMError<M>> {
-        batch_request::get_v2_pool_data_batch_request(self, middleware.clone()).await?;
+        batch_request::get_v2_pool_data_batch_request(
+            self,
+            &CallOptions::default(),
+            middleware.clone(),
+        )
+        .await?;
 
         Ok(())
     }
@@ -111,19 +178,7 @@ impl AutomatedMarketMaker for UniswapV2Pool {
     }
 
     fn simulate_swap(&self, token_in: H160, amount_in: U256) -> Result<U256, SwapSimulationError> {
-        if self.token_a == token_in {
-            Ok(self.get_amount_out(
-                amount_in,
-                U256::from(self.reserve_0),
-                U256::from(self.reserve_1),
-            ))
-        } else {
-            Ok(self.get_amount_out(
-                amount_in,
-                U256::from(self.reserve_1),
-                U256::from(self.reserve_0),
-            ))
-        }
+        self.simulate_swap_at(token_in, amount_in, (self.reserve_0, self.reserve_1))
     }
 
     fn simulate_swap_mut(
@@ -131,6 +186,10 @@ impl AutomatedMarketMaker for UniswapV2Pool {
         token_in: H160,
         amount_in: U256,
     ) -> Result<U256, SwapSimulationError> {
+        if token_in != self.token_a && token_in != self.token_b {
+            return Err(SwapSimulationError::InvalidToken(token_in));
+        }
+
         if self.token_a == token_in {
             let amount_out = self.get_amount_out(
                 amount_in,
@@ -163,6 +222,22 @@ impl AutomatedMarketMaker for UniswapV2Pool {
             self.token_a
         }
     }
+
+    fn fee_bps(&self) -> u32 {
+        if self.fee_denominator == 0 {
+            return 0;
+        }
+
+        (self.fee as u64 * 10000 / self.fee_denominator as u64) as u32
+    }
+
+    fn liquidity(&self) -> U256 {
+        (U256::from(self.reserve_0) * U256::from(self.reserve_1)).integer_sqrt()
+    }
+
+    fn swap_gas_estimate(&self) -> u64 {
+        SWAP_GAS_ESTIMATE
+    }
 }
 
 impl UniswapV2Pool {
@@ -187,8 +262,38 @@ impl UniswapV2Pool {
             reserve_0,
             reserve_1,
             fee,
+            fee_denominator: DEFAULT_FEE_DENOMINATOR,
             last_active_at: 0,
             last_active_at_block,
+            total_supply: U256::zero(),
+            decimals_synced: false,
+        }
+    }
+
+    /// Creates a pool from the results of `token0()`, `token1()`, `decimals()` and `getReserves()` fetched from any data source, ie. a subgraph or a custom multicall.
+    /// The fee is set to `DEFAULT_FEE`, pools of factories with other fees should set `fee` afterwards.
+    pub fn from_raw(
+        address: H160,
+        token_a: H160,
+        token_a_decimals: u8,
+        token_b: H160,
+        token_b_decimals: u8,
+        reserve_0: u128,
+        reserve_1: u128,
+    ) -> UniswapV2Pool {
+        UniswapV2Pool {
+            decimals_synced: true,
+            ..UniswapV2Pool::new(
+                address,
+                token_a,
+                token_a_decimals,
+                token_b,
+                token_b_decimals,
+                reserve_0,
+                reserve_1,
+                DEFAULT_FEE,
+                0,
+            )
         }
     }
 
@@ -208,8 +313,11 @@ impl UniswapV2Pool {
             reserve_0: 0,
             reserve_1: 0,
             fee,
+            fee_denominator: DEFAULT_FEE_DENOMINATOR,
             last_active_at: 0,
             last_active_at_block: block_number.unwrap_or_default().as_u64(),
+            total_supply: U256::zero(),
+            decimals_synced: false,
         };
 
         pool.populate_data(None, middleware.clone()).await?;
@@ -220,6 +328,33 @@ impl UniswapV2Pool {
 
         Ok(pool)
     }
+
+    /// Returns the pool at `pair_address` with its tokens, decimals and reserves read in a single batch request, as of the block returned with the read.
+    /// Pairs do not expose their fee, so the Uniswap V2 fee of 0.3% is assumed. Use `new_from_address` for forks with a different fee.
+    pub async fn from_address<M: Middleware>(
+        pair_address: H160,
+        middleware: Arc<M>,
+    ) -> Result<Self, AMMError<M>> {
+        let mut pool = UniswapV2Pool {
+            address: pair_address,
+            fee: factory::DEFAULT_FEE,
+            ..Default::default()
+        };
+
+        pool.last_active_at_block = batch_request::get_v2_pool_data_batch_request(
+            &mut pool,
+            &CallOptions::default(),
+            middleware,
+        )
+        .await?;
+
+        if !pool.data_is_populated() {
+            return Err(AMMError::PoolDataError);
+        }
+
+        Ok(pool)
+    }
+
     pub async fn new_from_log<M: Middleware>(
         log: Log,
         fee: u32,
@@ -252,14 +387,43 @@ impl UniswapV2Pool {
                 reserve_0: 0,
                 reserve_1: 0,
                 fee: 0,
+                fee_denominator: DEFAULT_FEE_DENOMINATOR,
                 last_active_at: 0,
                 last_active_at_block: block_number,
+                total_supply: U256::zero(),
+                decimals_synced: false,
             })
         } else {
             Err(EventLogError::InvalidEventSignature)?
         }
     }
 
+    /// Same as `sync_from_log`, but returns the signed change of `(reserve_0, reserve_1)` applied by the log.
+    /// `Sync` logs update the reserves and return the change from the previous reserves. `Swap` logs return the amounts in minus the amounts out and leave the reserves as they are,
+    /// since the pair emits the `Sync` carrying the new reserves before each `Swap`.
+    /// The deltas of a `Swap` describe the same change as the deltas of the `Sync` emitted before it in the same transaction, so they must not be applied on top of the `Sync` deltas.
+    /// To track the reserves from deltas, only sum the deltas of `Sync` logs, `Swap` deltas attribute that change to the swap.
+    pub fn sync_from_log_with_deltas(&mut self, log: Log) -> Result<(I256, I256), EventLogError> {
+        let event_signature = log.topics[0];
+
+        if event_signature == SWAP_EVENT_SIGNATURE {
+            let swap_event = SwapFilter::decode_log(&RawLog::from(log))?;
+
+            Ok((
+                I256::from_raw(swap_event.amount_0_in) - I256::from_raw(swap_event.amount_0_out),
+                I256::from_raw(swap_event.amount_1_in) - I256::from_raw(swap_event.amount_1_out),
+            ))
+        } else {
+            let (reserve_0, reserve_1) = (self.reserve_0, self.reserve_1);
+            self.sync_from_log(log)?;
+
+            Ok((
+                I256::from_raw(U256::from(self.reserve_0)) - I256::from_raw(U256::from(reserve_0)),
+                I256::from_raw(U256::from(self.reserve_1)) - I256::from_raw(U256::from(reserve_1)),
+            ))
+        }
+    }
+
     pub fn fee(&self) -> u32 {
         self.fee
     }
@@ -335,20 +499,12 @@ impl UniswapV2Pool {
     }
 
     pub fn calculate_price_64_x_64(&self, base_token: H160) -> Result<u128, ArithmeticError> {
-        let decimal_shift = self.token_a_decimals as i8 - self.token_b_decimals as i8;
-
-        let (r_0, r_1) = if decimal_shift < 0 {
-            (
-                U256::from(self.reserve_0)
-                    * U256::from(10u128.pow(decimal_shift.unsigned_abs() as u32)),
-                U256::from(self.reserve_1),
-            )
-        } else {
-            (
-                U256::from(self.reserve_0),
-                U256::from(self.reserve_1) * U256::from(10u128.pow(decimal_shift as u32)),
-            )
-        };
+        let (r_0, r_1) = normalize_decimals(
+            U256::from(self.reserve_0),
+            self.token_a_decimals,
+            U256::from(self.reserve_1),
+            self.token_b_decimals,
+        )?;
 
         if base_token == self.token_a {
             if r_0.is_zero() {
@@ -363,149 +519,160 @@ impl UniswapV2Pool {
         }
     }
 
-    pub fn get_amount_out(&self, amount_in: U256, reserve_in: U256, reserve_out: U256) -> U256 {
-        if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
-            return U256::zero();
+    /// Returns the reserves as `(reserve of token_first, reserve of the other token)`, regardless of the order of the tokens in the pool.
+    /// As with `get_token_out`, a token that is not `token_a` is treated as `token_b`.
+    pub fn reserves_for(&self, token_first: H160) -> (U256, U256) {
+        if self.token_a == token_first {
+            (U256::from(self.reserve_0), U256::from(self.reserve_1))
+        } else {
+            (U256::from(self.reserve_1), U256::from(self.reserve_0))
         }
-        let fee = (10000 - (self.fee / 10)) / 10; //Fee of 300 => (10,000 - 30) / 10  = 997
-        let amount_in_with_fee = amount_in * U256::from(fee);
-        let numerator = amount_in_with_fee * reserve_out;
-        let denominator = reserve_in * U256::from(1000) + amount_in_with_fee;
-
-        numerator / denominator
     }
 
-    pub fn swap_calldata(
-        &self,
-        amount_0_out: U256,
-        amount_1_out: U256,
-        to: H160,
-        calldata: Vec<u8>,
-    ) -> Result<Bytes, ethers::abi::Error> {
-        let input_tokens = vec![
-            Token::Uint(amount_0_out),
-            Token::Uint(amount_1_out),
-            Token::Address(to),
-            Token::Bytes(calldata),
-        ];
+    /// Checks that the reserves fit in a uint112 and the token decimals are at most `MAX_TOKEN_DECIMALS`, which holds for any pool read from chain.
+    pub fn validate(&self) -> Result<(), PoolValidationError> {
+        for reserve in [self.reserve_0, self.reserve_1] {
+            if reserve > U112_MAX {
+                return Err(PoolValidationError::ReserveOverflow(self.address, reserve));
+            }
+        }
 
-        IUNISWAPV2PAIR_ABI
-            .function("swap")?
-            .encode_input(&input_tokens)
+        for (token, decimals) in [
+            (self.token_a, self.token_a_decimals),
+            (self.token_b, self.token_b_decimals),
+        ] {
+            if decimals > MAX_TOKEN_DECIMALS {
+                return Err(PoolValidationError::InvalidDecimals(token, decimals));
+            }
+        }
+
+        Ok(())
     }
-}
 
-pub const U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF: U256 = U256([
-    18446744073709551615,
-    18446744073709551615,
-    18446744073709551615,
-    0,
-]);
+    /// Returns false if the new reserves are implausible, ie. both reserves drop to zero or a reserve changes by more than `DEFAULT_MAX_RESERVE_CHANGE_FACTOR` in one update.
+    pub fn validate_update(&self, new_reserve_0: u128, new_reserve_1: u128) -> bool {
+        self.validate_update_with_factor(
+            new_reserve_0,
+            new_reserve_1,
+            DEFAULT_MAX_RESERVE_CHANGE_FACTOR,
+        )
+    }
 
-pub const U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF: U256 =
-    U256([18446744073709551615, 18446744073709551615, 0, 0]);
-
-pub const U256_0X100000000: U256 = U256([4294967296, 0, 0, 0]);
-pub const U256_0X10000: U256 = U256([65536, 0, 0, 0]);
-pub const U256_0X100: U256 = U256([256, 0, 0, 0]);
-pub const U256_255: U256 = U256([255, 0, 0, 0]);
-pub const U256_192: U256 = U256([192, 0, 0, 0]);
-pub const U256_191: U256 = U256([191, 0, 0, 0]);
-pub const U256_128: U256 = U256([128, 0, 0, 0]);
-pub const U256_64: U256 = U256([64, 0, 0, 0]);
-pub const U256_32: U256 = U256([32, 0, 0, 0]);
-pub const U256_16: U256 = U256([16, 0, 0, 0]);
-pub const U256_8: U256 = U256([8, 0, 0, 0]);
-pub const U256_4: U256 = U256([4, 0, 0, 0]);
-pub const U256_2: U256 = U256([2, 0, 0, 0]);
-
-pub fn div_uu(x: U256, y: U256) -> Result<u128, ArithmeticError> {
-    if !y.is_zero() {
-        let mut answer;
-
-        if x <= U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF {
-            answer = (x << U256_64) / y;
-        } else {
-            let mut msb = U256_192;
-            let mut xc = x >> U256_192;
+    /// Returns false if both new reserves are zero or a reserve changes by more than `max_change_factor` (in either direction) in one update.
+    /// Reserves that are currently zero can be updated to any value.
+    pub fn validate_update_with_factor(
+        &self,
+        new_reserve_0: u128,
+        new_reserve_1: u128,
+        max_change_factor: f64,
+    ) -> bool {
+        if new_reserve_0 == 0 && new_reserve_1 == 0 {
+            return false;
+        }
 
-            if xc >= U256_0X100000000 {
-                xc >>= U256_32;
-                msb += U256_32;
+        let within_factor = |reserve: u128, new_reserve: u128| {
+            if reserve == 0 {
+                return true;
             }
 
-            if xc >= U256_0X10000 {
-                xc >>= U256_16;
-                msb += U256_16;
-            }
+            let (reserve, new_reserve) = (reserve as f64, new_reserve as f64);
+            new_reserve <= reserve * max_change_factor && new_reserve >= reserve / max_change_factor
+        };
 
-            if xc >= U256_0X100 {
-                xc >>= U256_8;
-                msb += U256_8;
-            }
+        within_factor(self.reserve_0, new_reserve_0) && within_factor(self.reserve_1, new_reserve_1)
+    }
 
-            if xc >= U256_16 {
-                xc >>= U256_4;
-                msb += U256_4;
-            }
+    /// Returns the spot price of `base_token` after a swap of `amount_in` of `token_in` is applied to a copy of the pool.
+    pub fn price_after_swap(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        base_token: H160,
+    ) -> Result<f64, SwapSimulationError> {
+        let mut pool = self.clone();
+        pool.simulate_swap_mut(token_in, amount_in)?;
 
-            if xc >= U256_4 {
-                xc >>= U256_2;
-                msb += U256_2;
-            }
+        Ok(pool.calculate_price(base_token)?)
+    }
 
-            if xc >= U256_2 {
-                msb += U256::one();
-            }
+    /// Simulates a swap against the provided `(reserve_0, reserve_1)` instead of the current pool reserves, without mutating the pool.
+    pub fn simulate_swap_at(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        reserves: (u128, u128),
+    ) -> Result<U256, SwapSimulationError> {
+        let (reserve_0, reserve_1) = reserves;
 
-            answer =
-                (x << (U256_255 - msb)) / (((y - U256::one()) >> (msb - U256_191)) + U256::one());
+        if token_in != self.token_a && token_in != self.token_b {
+            return Err(SwapSimulationError::InvalidToken(token_in));
         }
 
-        if answer > U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF {
-            return Err(ArithmeticError::ShadowOverflow(answer));
+        if self.token_a == token_in {
+            Ok(self.get_amount_out(amount_in, U256::from(reserve_0), U256::from(reserve_1)))
+        } else {
+            Ok(self.get_amount_out(amount_in, U256::from(reserve_1), U256::from(reserve_0)))
         }
+    }
 
-        let hi = answer * (y >> U256_128);
-        let mut lo = answer * (y & U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF);
-
-        let mut xh = x >> U256_192;
-        let mut xl = x << U256_64;
-
-        if xl < lo {
-            xh -= U256::one();
+    pub fn get_amount_out(&self, amount_in: U256, reserve_in: U256, reserve_out: U256) -> U256 {
+        if amount_in.is_zero() || reserve_in.is_zero() || reserve_out.is_zero() {
+            return U256::zero();
         }
-
-        xl = xl.overflowing_sub(lo).0;
-        lo = hi << U256_128;
-
-        if xl < lo {
-            xh -= U256::one();
+        if self.fee_denominator == 0 {
+            return U256::zero();
         }
 
-        xl = xl.overflowing_sub(lo).0;
+        //Fee of 300 with a denominator of 100,000 => amount_in * 99,700 / 100,000
+        let fee = self.fee_denominator.saturating_sub(self.fee);
+        let amount_in_with_fee = amount_in * U256::from(fee);
+        let numerator = amount_in_with_fee * reserve_out;
+        let denominator = reserve_in * U256::from(self.fee_denominator) + amount_in_with_fee;
+
+        numerator / denominator
+    }
 
-        if xh != hi >> U256_128 {
-            return Err(ArithmeticError::RoundingError);
+    /// Returns the amount in required to receive `amount_out`, as `getAmountIn` of the Uniswap V2 library.
+    /// Returns `None` if `amount_out` is not below `reserve_out` or the pool has no liquidity.
+    pub fn get_amount_in(
+        &self,
+        amount_out: U256,
+        reserve_in: U256,
+        reserve_out: U256,
+    ) -> Option<U256> {
+        if amount_out.is_zero() {
+            return Some(U256::zero());
         }
 
-        answer += xl / y;
-
-        if answer > U256_0XFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF {
-            return Err(ArithmeticError::ShadowOverflow(answer));
+        let fee = self.fee_denominator.saturating_sub(self.fee);
+        if reserve_in.is_zero() || amount_out >= reserve_out || fee == 0 {
+            return None;
         }
 
-        Ok(answer.as_u128())
-    } else {
-        Err(ArithmeticError::YIsZero)
+        let numerator = reserve_in * amount_out * U256::from(self.fee_denominator);
+        let denominator = (reserve_out - amount_out) * U256::from(fee);
+
+        Some(numerator / denominator + 1)
     }
-}
 
-//Converts a Q64 fixed point to a Q16 fixed point -> f64
-pub fn q64_to_f64(x: u128) -> f64 {
-    BigFloat::from(x)
-        .div(&BigFloat::from(U128_0X10000000000000000))
-        .to_f64()
+    pub fn swap_calldata(
+        &self,
+        amount_0_out: U256,
+        amount_1_out: U256,
+        to: H160,
+        calldata: Vec<u8>,
+    ) -> Result<Bytes, ethers::abi::Error> {
+        let input_tokens = vec![
+            Token::Uint(amount_0_out),
+            Token::Uint(amount_1_out),
+            Token::Address(to),
+            Token::Bytes(calldata),
+        ];
+
+        IUNISWAPV2PAIR_ABI
+            .function("swap")?
+            .encode_input(&input_tokens)
+    }
 }
 
 #[cfg(test)]
@@ -513,13 +680,19 @@ mod tests {
     use std::{str::FromStr, sync::Arc};
 
     use ethers::{
+        abi::Token,
         providers::{Http, Provider},
-        types::{H160, U256},
+        types::{Log, H160, H256, I256, U256, U64},
     };
 
     use crate::amm::AutomatedMarketMaker;
 
-    use super::UniswapV2Pool;
+    use proptest::{prop_assert, test_runner::TestRunner};
+
+    use super::{
+        normalize_decimals, UniswapV2Pool, BPS_FEE_DENOMINATOR, DEFAULT_FEE_DENOMINATOR,
+        SWAP_EVENT_SIGNATURE, SYNC_EVENT_SIGNATURE, U112_MAX,
+    };
 
     #[test]
     fn test_swap_calldata() -> eyre::Result<()> {
@@ -568,6 +741,44 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_from_address() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let pool = UniswapV2Pool::from_address(
+            H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?,
+            middleware.clone(),
+        )
+        .await?;
+
+        assert_eq!(
+            pool.token_a,
+            H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")?
+        );
+        assert_eq!(pool.token_a_decimals, 6);
+        assert_eq!(
+            pool.token_b,
+            H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")?
+        );
+        assert_eq!(pool.token_b_decimals, 18);
+        assert!(pool.decimals_synced);
+        assert_eq!(pool.fee, 300);
+        assert_ne!(pool.reserve_0, 0);
+        assert_ne!(pool.reserve_1, 0);
+        assert_ne!(pool.last_active_at_block, 0);
+
+        //Not a pair
+        assert!(UniswapV2Pool::from_address(
+            H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f")?,
+            middleware
+        )
+        .await
+        .is_err());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_get_pool_data() -> eyre::Result<()> {
         let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
@@ -594,6 +805,7 @@ mod tests {
             H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")?
         );
         assert_eq!(pool.token_b_decimals, 18);
+        assert!(!pool.total_supply.is_zero());
 
         Ok(())
     }
@@ -611,8 +823,11 @@ mod tests {
             reserve_0: 23595096345912178729927,
             reserve_1: 154664232014390554564,
             fee: 300,
+            fee_denominator: DEFAULT_FEE_DENOMINATOR,
             last_active_at: 1234567890,
             last_active_at_block: 12345678,
+            total_supply: U256::zero(),
+            decimals_synced: false,
         };
 
         assert!(x.calculate_price(token_a)? != 0.0);
@@ -668,4 +883,502 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_simulate_swap_at() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let pool = UniswapV2Pool {
+            token_a,
+            token_a_decimals: 6,
+            token_b,
+            token_b_decimals: 18,
+            reserve_0: 47092140895915,
+            reserve_1: 28396598565590008529300,
+            fee: 300,
+            ..Default::default()
+        };
+
+        let historical = UniswapV2Pool {
+            reserve_0: 23595096345912,
+            reserve_1: 14198299282795004264650,
+            ..pool.clone()
+        };
+
+        let amount_in = U256::from(1000000000_u128);
+
+        assert_eq!(
+            pool.simulate_swap_at(
+                token_a,
+                amount_in,
+                (historical.reserve_0, historical.reserve_1)
+            )?,
+            historical.simulate_swap(token_a, amount_in)?
+        );
+        assert_eq!(
+            pool.simulate_swap_at(
+                token_b,
+                amount_in,
+                (historical.reserve_0, historical.reserve_1)
+            )?,
+            historical.simulate_swap(token_b, amount_in)?
+        );
+
+        // The pool reserves are left untouched
+        assert_eq!(pool.reserve_0, 47092140895915);
+        assert_eq!(pool.reserve_1, 28396598565590008529300);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_price_after_swap() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let pool = UniswapV2Pool {
+            token_a,
+            token_a_decimals: 6,
+            token_b,
+            token_b_decimals: 18,
+            reserve_0: 47092140895915,
+            reserve_1: 28396598565590008529300,
+            fee: 300,
+            ..Default::default()
+        };
+
+        let amount_in = U256::from(1000000000000_u128);
+        let price_before = pool.calculate_price(token_b)?;
+        let price_after = pool.price_after_swap(token_a, amount_in, token_b)?;
+
+        //Selling token a into the pool makes token b more expensive
+        assert!(price_after > price_before);
+
+        let mut swapped_pool = pool.clone();
+        swapped_pool.simulate_swap_mut(token_a, amount_in)?;
+        assert_eq!(price_after, swapped_pool.calculate_price(token_b)?);
+
+        //The pool itself is not modified
+        assert_eq!(pool.reserve_0, 47092140895915);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_amount_out_bps_fee_denominator() {
+        //Fork charging 25 bps, ie. amount_in * 9975 / 10000
+        let pool = UniswapV2Pool {
+            reserve_0: 1000000000000000000000,
+            reserve_1: 2000000000000000000000,
+            fee: 25,
+            fee_denominator: BPS_FEE_DENOMINATOR,
+            ..Default::default()
+        };
+
+        let amount_in = U256::from(1000000000000000000_u128);
+        let amount_out = pool.get_amount_out(
+            amount_in,
+            U256::from(pool.reserve_0),
+            U256::from(pool.reserve_1),
+        );
+
+        let amount_in_with_fee = amount_in * U256::from(9975);
+        let expected_amount_out = amount_in_with_fee * U256::from(pool.reserve_1)
+            / (U256::from(pool.reserve_0) * U256::from(10000) + amount_in_with_fee);
+
+        assert_eq!(amount_out, expected_amount_out);
+        assert_eq!(amount_out, U256::from(1993011970559367031_u128));
+
+        //The same fee expressed with the default denominator gives the same quote
+        let default_denominator_pool = UniswapV2Pool {
+            fee: 250,
+            fee_denominator: DEFAULT_FEE_DENOMINATOR,
+            ..pool.clone()
+        };
+        assert_eq!(
+            default_denominator_pool.simulate_swap(H160::zero(), amount_in).ok(),
+            Some(amount_out)
+        );
+        assert_eq!(pool.fee_bps(), 25);
+    }
+
+    #[test]
+    fn test_simulate_swap_invalid_token() {
+        let mut pool = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            reserve_0: 1000000000000000000000,
+            reserve_1: 1000000000000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        let token = H160::from_low_u64_be(3);
+        let amount_in = U256::from(1000000000000000000_u128);
+
+        assert!(matches!(
+            pool.simulate_swap(token, amount_in),
+            Err(crate::errors::SwapSimulationError::InvalidToken(t)) if t == token
+        ));
+        assert!(matches!(
+            pool.simulate_swap_mut(token, amount_in),
+            Err(crate::errors::SwapSimulationError::InvalidToken(t)) if t == token
+        ));
+        assert_eq!(pool.reserve_0, 1000000000000000000000);
+        assert_eq!(pool.reserve_1, 1000000000000000000000);
+    }
+
+    #[test]
+    fn test_fee_bps() {
+        let mut pool = UniswapV2Pool {
+            fee: 300,
+            ..Default::default()
+        };
+        assert_eq!(pool.fee_bps(), 30);
+
+        pool.fee = 250;
+        assert_eq!(pool.fee_bps(), 25);
+
+        pool.fee = 1000;
+        assert_eq!(pool.fee_bps(), 100);
+    }
+
+    #[test]
+    fn test_validate_update() {
+        let pool = UniswapV2Pool {
+            reserve_0: 1000000,
+            reserve_1: 2000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        assert!(pool.validate_update(1100000, 1800000));
+        assert!(pool.validate_update(10000000, 200000));
+        assert!(!pool.validate_update(0, 0));
+        assert!(!pool.validate_update(10000001, 2000000));
+        assert!(!pool.validate_update(1000000, 199999));
+
+        assert!(pool.validate_update_with_factor(1900000, 1100000, 2.0));
+        assert!(!pool.validate_update_with_factor(2000001, 2000000, 2.0));
+
+        //Unpopulated reserves accept any non zero update
+        let empty_pool = UniswapV2Pool::default();
+        assert!(empty_pool.validate_update(1000000, 2000000));
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut pool = UniswapV2Pool {
+            token_a_decimals: 18,
+            token_b_decimals: 6,
+            reserve_0: super::U112_MAX,
+            reserve_1: 2000000,
+            fee: 300,
+            ..Default::default()
+        };
+        assert!(pool.validate().is_ok());
+
+        pool.reserve_0 += 1;
+        assert!(pool.validate().is_err());
+
+        pool.reserve_0 = 1000000;
+        pool.token_b_decimals = 78;
+        assert!(pool.validate().is_err());
+    }
+
+    #[test]
+    fn test_varying_decimals() -> eyre::Result<()> {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+
+        for token_a_decimals in [0_u8, 2, 6, 8, 18] {
+            for token_b_decimals in [0_u8, 2, 6, 8, 18] {
+                //1,000,000 token a against 2,000,000 token b, ie. a price of 2 token b per token a
+                let pool = UniswapV2Pool {
+                    token_a,
+                    token_a_decimals,
+                    token_b,
+                    token_b_decimals,
+                    reserve_0: 1000000 * 10_u128.pow(token_a_decimals as u32),
+                    reserve_1: 2000000 * 10_u128.pow(token_b_decimals as u32),
+                    fee: 300,
+                    ..Default::default()
+                };
+
+                assert_eq!(pool.calculate_price(token_a)?, 2.0);
+                assert_eq!(pool.calculate_price(token_b)?, 0.5);
+
+                //Swapping 1000 token a returns a bit less than 2000 token b after fees and price impact
+                let amount_in = U256::from(1000 * 10_u128.pow(token_a_decimals as u32));
+                let amount_out = pool.simulate_swap(token_a, amount_in)?.as_u128() as f64
+                    / 10_f64.powi(token_b_decimals as i32);
+                assert!(amount_out < 2000.0);
+                assert!(amount_out > 1990.0);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalize_decimals() -> eyre::Result<()> {
+        let reserve_0 = U256::from(1000000);
+        let reserve_1 = U256::from(2000000);
+
+        assert_eq!(
+            normalize_decimals(reserve_0, 0, reserve_1, 18)?,
+            (reserve_0 * U256::exp10(18), reserve_1)
+        );
+        assert_eq!(
+            normalize_decimals(reserve_0, 18, reserve_1, 0)?,
+            (reserve_0, reserve_1 * U256::exp10(18))
+        );
+        assert_eq!(
+            normalize_decimals(reserve_0, 6, reserve_1, 6)?,
+            (reserve_0, reserve_1)
+        );
+
+        //Shifts past 10^38 no longer fit in a u128, but still fit in a U256
+        assert_eq!(
+            normalize_decimals(reserve_0, 0, reserve_1, 40)?,
+            (reserve_0 * U256::exp10(40), reserve_1)
+        );
+        assert!(normalize_decimals(reserve_0, 0, reserve_1, 77).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_amount_in() {
+        let pool = UniswapV2Pool {
+            reserve_0: 1000000000000000000000,
+            reserve_1: 2000000000000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+        let (reserve_in, reserve_out) = (U256::from(pool.reserve_0), U256::from(pool.reserve_1));
+
+        //The amount in is the smallest amount that returns at least the amount out
+        let amount_out = U256::from(1000000000000000000_u128);
+        let amount_in = pool
+            .get_amount_in(amount_out, reserve_in, reserve_out)
+            .expect("Amount out should be below the reserve");
+        assert!(pool.get_amount_out(amount_in, reserve_in, reserve_out) >= amount_out);
+        assert!(pool.get_amount_out(amount_in - 2, reserve_in, reserve_out) < amount_out);
+
+        assert_eq!(
+            pool.get_amount_in(U256::zero(), reserve_in, reserve_out),
+            Some(U256::zero())
+        );
+        assert_eq!(pool.get_amount_in(reserve_out, reserve_in, reserve_out), None);
+    }
+
+    #[test]
+    fn test_swap_round_trip() {
+        //Seeded runner, so that failures are reproducible
+        let mut runner = TestRunner::deterministic();
+        let strategy = (1..=U112_MAX, 1..=U112_MAX, 1..=U112_MAX, 0..=1000_u32);
+
+        runner
+            .run(&strategy, |(reserve_0, reserve_1, amount_in, fee)| {
+                let pool = UniswapV2Pool {
+                    token_a: H160::from_low_u64_be(1),
+                    token_b: H160::from_low_u64_be(2),
+                    reserve_0,
+                    reserve_1,
+                    fee,
+                    ..Default::default()
+                };
+                let (reserve_in, reserve_out) = (U256::from(reserve_0), U256::from(reserve_1));
+                let amount_in = U256::from(amount_in);
+
+                let amount_out = pool.simulate_swap(pool.token_a, amount_in).unwrap();
+                if amount_out.is_zero() {
+                    return Ok(());
+                }
+
+                //The exact out input is the smallest input returning the amount out, up to the rounding of `getAmountIn`
+                let round_trip_amount_in = pool
+                    .get_amount_in(amount_out, reserve_in, reserve_out)
+                    .unwrap();
+                prop_assert!(round_trip_amount_in <= amount_in + 1);
+                prop_assert!(
+                    pool.simulate_swap(pool.token_a, round_trip_amount_in)
+                        .unwrap()
+                        >= amount_out
+                );
+                if round_trip_amount_in > U256::one() {
+                    prop_assert!(
+                        pool.get_amount_out(round_trip_amount_in - 2, reserve_in, reserve_out)
+                            < amount_out
+                    );
+                }
+
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_from_raw() {
+        let pool = UniswapV2Pool::from_raw(
+            H160::from_low_u64_be(1),
+            H160::from_low_u64_be(2),
+            6,
+            H160::from_low_u64_be(3),
+            18,
+            1000000000000,
+            500000000000000000000,
+        );
+
+        assert_eq!(pool.token_a_decimals, 6);
+        assert_eq!(pool.reserve_1, 500000000000000000000);
+        assert_eq!(pool.fee, 300);
+        assert!(pool.decimals_synced);
+        assert_eq!(
+            pool.simulate_swap(pool.token_a, U256::from(1000000)).unwrap(),
+            pool.get_amount_out(
+                U256::from(1000000),
+                U256::from(pool.reserve_0),
+                U256::from(pool.reserve_1)
+            )
+        );
+    }
+
+    #[test]
+    fn test_reserves_for() {
+        let pool = UniswapV2Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            reserve_0: 100,
+            reserve_1: 200,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            pool.reserves_for(pool.token_a),
+            (U256::from(100), U256::from(200))
+        );
+        assert_eq!(
+            pool.reserves_for(pool.token_b),
+            (U256::from(200), U256::from(100))
+        );
+    }
+
+    #[test]
+    fn test_sync_from_log_with_deltas() -> eyre::Result<()> {
+        let mut pool = UniswapV2Pool {
+            reserve_0: 1000,
+            reserve_1: 2000,
+            ..Default::default()
+        };
+
+        let sync_log = Log {
+            topics: vec![SYNC_EVENT_SIGNATURE],
+            data: ethers::abi::encode(&[
+                Token::Uint(U256::from(1100)),
+                Token::Uint(U256::from(1819)),
+            ])
+            .into(),
+            block_number: Some(U64::from(100)),
+            ..Default::default()
+        };
+        let deltas = pool.sync_from_log_with_deltas(sync_log)?;
+        assert_eq!(deltas, (I256::from(100), I256::from(-181)));
+        assert_eq!((pool.reserve_0, pool.reserve_1), (1100, 1819));
+        assert_eq!(pool.last_active_at_block, 100);
+
+        //The swap is only reported, the reserves were set by the preceding sync
+        let swap_log = Log {
+            topics: vec![SWAP_EVENT_SIGNATURE, H256::zero(), H256::zero()],
+            data: ethers::abi::encode(&[
+                Token::Uint(U256::from(100)),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::zero()),
+                Token::Uint(U256::from(181)),
+            ])
+            .into(),
+            ..Default::default()
+        };
+        let deltas = pool.sync_from_log_with_deltas(swap_log)?;
+        assert_eq!(deltas, (I256::from(100), I256::from(-181)));
+        assert_eq!((pool.reserve_0, pool.reserve_1), (1100, 1819));
+
+        assert!(pool
+            .sync_from_log_with_deltas(Log {
+                topics: vec![H256::zero()],
+                ..Default::default()
+            })
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_from_log_with_deltas_sync_and_swap_pair() -> eyre::Result<()> {
+        let mut pool = UniswapV2Pool {
+            reserve_0: 1000,
+            reserve_1: 2000,
+            ..Default::default()
+        };
+        let (reserve_0, reserve_1) = (pool.reserve_0, pool.reserve_1);
+
+        //A swap of 100 token 0 for 181 token 1, the pair emits the sync before the swap in the same transaction
+        let transaction_hash = Some(H256::from_low_u64_be(1));
+        let logs = vec![
+            Log {
+                topics: vec![SYNC_EVENT_SIGNATURE],
+                data: ethers::abi::encode(&[
+                    Token::Uint(U256::from(1100)),
+                    Token::Uint(U256::from(1819)),
+                ])
+                .into(),
+                block_number: Some(U64::from(100)),
+                transaction_hash,
+                log_index: Some(U256::from(0)),
+                ..Default::default()
+            },
+            Log {
+                topics: vec![SWAP_EVENT_SIGNATURE, H256::zero(), H256::zero()],
+                data: ethers::abi::encode(&[
+                    Token::Uint(U256::from(100)),
+                    Token::Uint(U256::zero()),
+                    Token::Uint(U256::zero()),
+                    Token::Uint(U256::from(181)),
+                ])
+                .into(),
+                block_number: Some(U64::from(100)),
+                transaction_hash,
+                log_index: Some(U256::from(1)),
+                ..Default::default()
+            },
+        ];
+
+        let mut sync_deltas = (I256::zero(), I256::zero());
+        let mut swap_deltas = (I256::zero(), I256::zero());
+        for log in logs {
+            let is_sync = log.topics[0] == SYNC_EVENT_SIGNATURE;
+            let (delta_0, delta_1) = pool.sync_from_log_with_deltas(log)?;
+
+            if is_sync {
+                sync_deltas = (sync_deltas.0 + delta_0, sync_deltas.1 + delta_1);
+            } else {
+                swap_deltas = (swap_deltas.0 + delta_0, swap_deltas.1 + delta_1);
+            }
+        }
+
+        //The sync deltas alone account for the change of the reserves, the swap deltas describe the same change
+        assert_eq!((pool.reserve_0, pool.reserve_1), (1100, 1819));
+        assert_eq!(
+            sync_deltas,
+            (
+                I256::from(pool.reserve_0 as i128 - reserve_0 as i128),
+                I256::from(pool.reserve_1 as i128 - reserve_1 as i128)
+            )
+        );
+        assert_eq!(swap_deltas, sync_deltas);
+
+        Ok(())
+    }
 }
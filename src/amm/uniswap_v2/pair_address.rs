@@ -0,0 +1,220 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ethers::{
+    prelude::abigen,
+    providers::Middleware,
+    types::{H160, H256, U256},
+    utils::{get_create2_address_from_hash, keccak256},
+};
+
+use crate::errors::AMMError;
+
+use super::{factory::IUniswapV2Factory, IUniswapV2Pair};
+
+abigen!(
+    IInitCodeHashGetters,
+    r#"[
+        function pairCodeHash() external view returns (bytes32)
+        function INIT_CODE_PAIR_HASH() external view returns (bytes32)
+    ]"#;
+);
+
+pub const UNISWAP_V2_FACTORY: H160 = H160([
+    92, 105, 190, 231, 1, 239, 129, 74, 43, 106, 62, 221, 75, 22, 82, 203, 156, 197, 170, 111,
+]);
+
+// keccak256 of the Uniswap V2 pair creation code
+pub const UNISWAP_V2_INIT_CODE_HASH: H256 = H256([
+    150, 232, 172, 66, 119, 25, 143, 248, 182, 247, 133, 71, 138, 169, 163, 159, 64, 60, 183, 104,
+    221, 2, 203, 238, 50, 108, 62, 125, 163, 72, 132, 95,
+]);
+
+/// Returns the CREATE2 address of the pair of `token_a` and `token_b` deployed by `factory`, without any call to the chain.
+pub fn compute_pair_address(
+    factory: H160,
+    token_a: H160,
+    token_b: H160,
+    init_code_hash: H256,
+) -> H160 {
+    let (token_0, token_1) = if token_a < token_b {
+        (token_a, token_b)
+    } else {
+        (token_b, token_a)
+    };
+
+    //The salt is keccak256(abi.encodePacked(token0, token1))
+    let salt = keccak256([token_0.as_bytes(), token_1.as_bytes()].concat());
+
+    get_create2_address_from_hash(factory, salt, init_code_hash)
+}
+
+/// Returns the init code hash of known factories.
+pub fn known_init_code_hash(factory: H160) -> Option<H256> {
+    if factory == UNISWAP_V2_FACTORY {
+        Some(UNISWAP_V2_INIT_CODE_HASH)
+    } else {
+        None
+    }
+}
+
+/// Returns the init code hash of the pairs deployed by `factory`, to compute pair addresses offline with `compute_pair_address`.
+/// Known factories are resolved without any call. Otherwise the hash is read from the `pairCodeHash()` or `INIT_CODE_PAIR_HASH()` getter exposed by most forks,
+/// and is only returned if it derives the address of the first pair of the factory.
+pub async fn fetch_init_code_hash<M: Middleware>(
+    factory: H160,
+    middleware: Arc<M>,
+) -> Result<H256, AMMError<M>> {
+    if let Some(init_code_hash) = known_init_code_hash(factory) {
+        return Ok(init_code_hash);
+    }
+
+    let getters = IInitCodeHashGetters::new(factory, middleware.clone());
+
+    let init_code_hash = if let Ok(init_code_hash) = getters.pair_code_hash().call().await {
+        H256::from(init_code_hash)
+    } else if let Ok(init_code_hash) = getters.init_code_pair_hash().call().await {
+        H256::from(init_code_hash)
+    } else {
+        return Err(AMMError::InitCodeHashNotFound(factory));
+    };
+
+    //Check the hash against a pair that was actually deployed by the factory
+    let uniswap_v2_factory = IUniswapV2Factory::new(factory, middleware.clone());
+    let pair = uniswap_v2_factory
+        .all_pairs(U256::zero())
+        .call()
+        .await
+        .map_err(|e| AMMError::ContractError("fetch_init_code_hash", factory, e))?;
+
+    let v2_pair = IUniswapV2Pair::new(pair, middleware);
+    let token_0 = v2_pair
+        .token_0()
+        .call()
+        .await
+        .map_err(|e| AMMError::ContractError("fetch_init_code_hash", pair, e))?;
+    let token_1 = v2_pair
+        .token_1()
+        .call()
+        .await
+        .map_err(|e| AMMError::ContractError("fetch_init_code_hash", pair, e))?;
+
+    if compute_pair_address(factory, token_0, token_1, init_code_hash) == pair {
+        Ok(init_code_hash)
+    } else {
+        Err(AMMError::InitCodeHashNotFound(factory))
+    }
+}
+
+/// Init code hashes by factory address, fetched once per factory.
+#[derive(Debug, Clone, Default)]
+pub struct InitCodeHashCache {
+    pub init_code_hashes: HashMap<H160, H256>,
+}
+
+impl InitCodeHashCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, factory: H160) -> Option<H256> {
+        self.init_code_hashes.get(&factory).copied()
+    }
+
+    pub fn insert(&mut self, factory: H160, init_code_hash: H256) {
+        self.init_code_hashes.insert(factory, init_code_hash);
+    }
+
+    /// Returns the cached init code hash of `factory`, fetching it with `fetch_init_code_hash` on the first lookup.
+    pub async fn get_or_fetch<M: Middleware>(
+        &mut self,
+        factory: H160,
+        middleware: Arc<M>,
+    ) -> Result<H256, AMMError<M>> {
+        if let Some(init_code_hash) = self.get(factory) {
+            return Ok(init_code_hash);
+        }
+
+        let init_code_hash = fetch_init_code_hash(factory, middleware).await?;
+        self.insert(factory, init_code_hash);
+
+        Ok(init_code_hash)
+    }
+
+    /// Returns the pair address of `token_a` and `token_b` on `factory`, fetching the init code hash of the factory on the first lookup.
+    pub async fn compute_pair_address<M: Middleware>(
+        &mut self,
+        factory: H160,
+        token_a: H160,
+        token_b: H160,
+        middleware: Arc<M>,
+    ) -> Result<H160, AMMError<M>> {
+        let init_code_hash = self.get_or_fetch(factory, middleware).await?;
+
+        Ok(compute_pair_address(factory, token_a, token_b, init_code_hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::H160,
+    };
+
+    use super::{
+        compute_pair_address, fetch_init_code_hash, InitCodeHashCache, UNISWAP_V2_FACTORY,
+        UNISWAP_V2_INIT_CODE_HASH,
+    };
+
+    #[test]
+    fn test_compute_pair_address() -> eyre::Result<()> {
+        let usdc = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let weth = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+        let pair = H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?;
+
+        assert_eq!(
+            compute_pair_address(UNISWAP_V2_FACTORY, usdc, weth, UNISWAP_V2_INIT_CODE_HASH),
+            pair
+        );
+
+        //Token order does not matter
+        assert_eq!(
+            compute_pair_address(UNISWAP_V2_FACTORY, weth, usdc, UNISWAP_V2_INIT_CODE_HASH),
+            pair
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_init_code_hash() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let usdc = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let weth = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        //Sushiswap factory exposes `pairCodeHash()`
+        let sushiswap_factory = H160::from_str("0xC0AEe478e3658e2610c5F7A4A2E1777cE9e4f2Ac")?;
+
+        let mut cache = InitCodeHashCache::new();
+        let pair = cache
+            .compute_pair_address(sushiswap_factory, usdc, weth, middleware.clone())
+            .await?;
+
+        assert_eq!(
+            pair,
+            H160::from_str("0x397FF1542f962076d0BFE58eA045FfA2d347ACa0")?
+        );
+        assert!(cache.get(sushiswap_factory).is_some());
+
+        assert_eq!(
+            fetch_init_code_hash(UNISWAP_V2_FACTORY, middleware).await?,
+            UNISWAP_V2_INIT_CODE_HASH
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+
+use ethers::types::{H160, U256};
+
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+use crate::amm::AutomatedMarketMaker;
+
+use super::UniswapV2Pool;
+
+/// Returns the average spot price of `base_token` denominated in `quote_token` across all pools trading the pair, weighted by the quote token reserves of each pool.
+/// Pools that do not trade the pair or have no liquidity are skipped, `None` is returned if no pool can be priced.
+pub fn weighted_average_price(
+    pools: &[UniswapV2Pool],
+    base_token: H160,
+    quote_token: H160,
+) -> Option<f64> {
+    let mut weighted_price_sum = 0.0;
+    let mut total_weight = 0.0;
+
+    for pool in pools {
+        let quote_reserve = if pool.token_a == base_token && pool.token_b == quote_token {
+            pool.reserve_1
+        } else if pool.token_a == quote_token && pool.token_b == base_token {
+            pool.reserve_0
+        } else {
+            continue;
+        };
+
+        if quote_reserve == 0 {
+            continue;
+        }
+
+        let price = match pool.calculate_price(base_token) {
+            Ok(price) => price,
+            Err(_) => continue,
+        };
+
+        //Every pool trades the same quote token, so the raw reserves are comparable weights
+        let weight = quote_reserve as f64;
+        weighted_price_sum += price * weight;
+        total_weight += weight;
+    }
+
+    if total_weight == 0.0 {
+        None
+    } else {
+        Some(weighted_price_sum / total_weight)
+    }
+}
+
+/// Returns the price of `token` denominated in WETH as the geometric mean of its price across all `token`/WETH pools, weighted by the WETH reserves of each pool.
+/// Compared to an arithmetic mean, a thin pool quoting an outlier price moves the result much less. `None` is returned if no pool can be priced.
+pub fn robust_token_price(pools: &[UniswapV2Pool], token: H160, weth: H160) -> Option<f64> {
+    let mut weighted_log_price_sum = 0.0;
+    let mut total_weight = 0.0;
+
+    for pool in pools {
+        let weth_reserve = if pool.token_a == token && pool.token_b == weth {
+            pool.reserve_1
+        } else if pool.token_a == weth && pool.token_b == token {
+            pool.reserve_0
+        } else {
+            continue;
+        };
+
+        if weth_reserve == 0 {
+            continue;
+        }
+
+        let price = match pool.calculate_price(token) {
+            Ok(price) if price > 0.0 => price,
+            _ => continue,
+        };
+
+        let weight = weth_reserve as f64;
+        weighted_log_price_sum += price.ln() * weight;
+        total_weight += weight;
+    }
+
+    if total_weight == 0.0 {
+        None
+    } else {
+        Some((weighted_log_price_sum / total_weight).exp())
+    }
+}
+
+/// Returns the total value locked across `pools` denominated in WETH, using `reference_prices` (price of each token in WETH) to value the reserves.
+/// When only one token of a pool has a known price (WETH is always priced at 1), the other token is valued with the pool's own price.
+/// Pools where neither token has a known price are skipped.
+pub fn total_value_locked_weth(
+    pools: &[UniswapV2Pool],
+    weth: H160,
+    reference_prices: &HashMap<H160, f64>,
+) -> f64 {
+    let weth_price = |token: H160| {
+        if token == weth {
+            Some(1.0)
+        } else {
+            reference_prices.get(&token).copied()
+        }
+    };
+
+    let mut total_value_locked = 0.0;
+
+    for pool in pools {
+        let (price_a, price_b) = match (weth_price(pool.token_a), weth_price(pool.token_b)) {
+            (Some(price_a), Some(price_b)) => (price_a, price_b),
+            (Some(price_a), None) => match pool.calculate_price(pool.token_b) {
+                Ok(price) => (price_a, price * price_a),
+                Err(_) => continue,
+            },
+            (None, Some(price_b)) => match pool.calculate_price(pool.token_a) {
+                Ok(price) => (price * price_b, price_b),
+                Err(_) => continue,
+            },
+            (None, None) => continue,
+        };
+
+        let reserve_a = pool.reserve_0 as f64 / 10_f64.powi(pool.token_a_decimals as i32);
+        let reserve_b = pool.reserve_1 as f64 / 10_f64.powi(pool.token_b_decimals as i32);
+
+        total_value_locked += reserve_a * price_a + reserve_b * price_b;
+    }
+
+    total_value_locked
+}
+
+/// Returns the best effective prices of `base_token` denominated in `quote_token` to sell and to buy `size` of `base_token` across all pools trading the pair, as `(bid, ask)`.
+/// The bid is the highest amount of quote received per base sold and the ask the lowest amount of quote paid per base bought, both including fees and price impact.
+/// Pools that do not trade the pair are skipped, `None` is returned if no pool can fill `size` on either side.
+pub fn best_bid_ask(
+    pools: &[UniswapV2Pool],
+    base_token: H160,
+    quote_token: H160,
+    size: U256,
+) -> Option<(f64, f64)> {
+    //Reserves are u128, so no pool can fill a larger size
+    if size.is_zero() || size > U256::from(u128::MAX) {
+        return None;
+    }
+
+    let mut best_bid: Option<f64> = None;
+    let mut best_ask: Option<f64> = None;
+
+    for pool in pools {
+        let (reserve_base, reserve_quote, base_decimals, quote_decimals) =
+            if pool.token_a == base_token && pool.token_b == quote_token {
+                (
+                    pool.reserve_0,
+                    pool.reserve_1,
+                    pool.token_a_decimals,
+                    pool.token_b_decimals,
+                )
+            } else if pool.token_a == quote_token && pool.token_b == base_token {
+                (
+                    pool.reserve_1,
+                    pool.reserve_0,
+                    pool.token_b_decimals,
+                    pool.token_a_decimals,
+                )
+            } else {
+                continue;
+            };
+
+        let (reserve_base, reserve_quote) = (U256::from(reserve_base), U256::from(reserve_quote));
+
+        //Quote amounts per base amount, adjusted for the decimals of each token
+        let effective_price = |quote_amount: U256| {
+            (quote_amount.as_u128() as f64 / 10_f64.powi(quote_decimals as i32))
+                / (size.as_u128() as f64 / 10_f64.powi(base_decimals as i32))
+        };
+
+        let quote_out = pool.get_amount_out(size, reserve_base, reserve_quote);
+        if !quote_out.is_zero() {
+            let bid = effective_price(quote_out);
+            if best_bid.is_none() || Some(bid) > best_bid {
+                best_bid = Some(bid);
+            }
+        }
+
+        if let Some(quote_in) = pool.get_amount_in(size, reserve_quote, reserve_base) {
+            if quote_in <= U256::from(u128::MAX) {
+                let ask = effective_price(quote_in);
+                if best_ask.is_none() || Some(ask) < best_ask {
+                    best_ask = Some(ask);
+                }
+            }
+        }
+    }
+
+    Some((best_bid?, best_ask?))
+}
+
+/// Returns the price of `token` denominated in USDC, chaining its price in WETH from `token_pool` with the price of WETH in USDC from `weth_usdc_pool`.
+/// Each hop is adjusted for the decimals of its tokens. `None` is returned if a pool does not trade the expected pair or can not be priced.
+pub fn price_in_usdc(
+    token_pool: &UniswapV2Pool,
+    weth_usdc_pool: &UniswapV2Pool,
+    token: H160,
+    weth: H160,
+) -> Option<f64> {
+    if !weth_usdc_pool.contains_token(weth) {
+        return None;
+    }
+
+    let weth_price = match weth_usdc_pool.calculate_price(weth) {
+        Ok(price) if price.is_finite() && price > 0.0 => price,
+        _ => return None,
+    };
+
+    if token == weth {
+        return Some(weth_price);
+    }
+
+    if !token_pool.contains_token(token) || token_pool.get_token_out(token) != weth {
+        return None;
+    }
+
+    match token_pool.calculate_price(token) {
+        Ok(price) if price.is_finite() => Some(price * weth_price),
+        _ => None,
+    }
+}
+
+/// Returns the price of `base_token` in each of `pools`, computed in parallel across the rayon thread pool.
+/// Prices are returned in the same order as `pools`, `None` for pools that do not contain `base_token` or can not be priced.
+#[cfg(feature = "parallel")]
+pub fn compute_prices_par(pools: &[UniswapV2Pool], base_token: H160) -> Vec<(H160, Option<f64>)> {
+    pools
+        .par_iter()
+        .map(|pool| {
+            let price = if pool.contains_token(base_token) {
+                pool.calculate_price(base_token).ok()
+            } else {
+                None
+            };
+
+            (pool.address, price)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ethers::types::{H160, U256};
+
+    use crate::amm::uniswap_v2::UniswapV2Pool;
+
+    use super::{
+        best_bid_ask, price_in_usdc, robust_token_price, total_value_locked_weth,
+        weighted_average_price,
+    };
+
+    #[test]
+    fn test_weighted_average_price() {
+        let base_token = H160::from_low_u64_be(1);
+        let quote_token = H160::from_low_u64_be(2);
+
+        let pools = vec![
+            //Price of 2, 200 quote tokens in reserve
+            UniswapV2Pool {
+                token_a: base_token,
+                token_a_decimals: 18,
+                token_b: quote_token,
+                token_b_decimals: 18,
+                reserve_0: 100000000000000000000,
+                reserve_1: 200000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+            //Price of 4, 400 quote tokens in reserve
+            UniswapV2Pool {
+                token_a: quote_token,
+                token_a_decimals: 18,
+                token_b: base_token,
+                token_b_decimals: 18,
+                reserve_0: 400000000000000000000,
+                reserve_1: 100000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+            //Pool for a different pair is ignored
+            UniswapV2Pool {
+                token_a: base_token,
+                token_a_decimals: 18,
+                token_b: H160::from_low_u64_be(3),
+                token_b_decimals: 18,
+                reserve_0: 100000000000000000000,
+                reserve_1: 100000000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+        ];
+
+        let price = weighted_average_price(&pools, base_token, quote_token)
+            .expect("Pools should be priced");
+
+        assert!((price - 10.0 / 3.0).abs() < 1e-9);
+
+        assert!(weighted_average_price(&pools[2..], base_token, quote_token).is_none());
+    }
+
+    #[test]
+    fn test_total_value_locked_weth() {
+        let weth = H160::from_low_u64_be(1);
+        let token_x = H160::from_low_u64_be(2);
+        let token_y = H160::from_low_u64_be(3);
+        let token_z = H160::from_low_u64_be(4);
+
+        let pools = vec![
+            //10 WETH and 20,000 X, X is valued with the pool price
+            UniswapV2Pool {
+                token_a: weth,
+                token_a_decimals: 18,
+                token_b: token_x,
+                token_b_decimals: 18,
+                reserve_0: 10000000000000000000,
+                reserve_1: 20000000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+            //100 Y at 0.5 WETH and 25 Z at 2 WETH
+            UniswapV2Pool {
+                token_a: token_y,
+                token_a_decimals: 6,
+                token_b: token_z,
+                token_b_decimals: 18,
+                reserve_0: 100000000,
+                reserve_1: 25000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+            //No price for either token
+            UniswapV2Pool {
+                token_a: token_x,
+                token_a_decimals: 18,
+                token_b: H160::from_low_u64_be(5),
+                token_b_decimals: 18,
+                reserve_0: 10000000000000000000,
+                reserve_1: 10000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+        ];
+
+        let reference_prices = HashMap::from([(token_y, 0.5), (token_z, 2.0)]);
+
+        let total_value_locked = total_value_locked_weth(&pools, weth, &reference_prices);
+
+        assert!((total_value_locked - 120.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_robust_token_price() {
+        let token = H160::from_low_u64_be(1);
+        let weth = H160::from_low_u64_be(2);
+
+        let pools = vec![
+            //Price of 2 WETH, 200 WETH in reserve
+            UniswapV2Pool {
+                token_a: token,
+                token_a_decimals: 18,
+                token_b: weth,
+                token_b_decimals: 18,
+                reserve_0: 100000000000000000000,
+                reserve_1: 200000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+            //Price of 8 WETH, 200 WETH in reserve
+            UniswapV2Pool {
+                token_a: weth,
+                token_a_decimals: 18,
+                token_b: token,
+                token_b_decimals: 18,
+                reserve_0: 200000000000000000000,
+                reserve_1: 25000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+            //Thin pool quoting a manipulated price of 1000 WETH
+            UniswapV2Pool {
+                token_a: token,
+                token_a_decimals: 18,
+                token_b: weth,
+                token_b_decimals: 18,
+                reserve_0: 1000000000000000,
+                reserve_1: 1000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+        ];
+
+        //Equal weights give the geometric mean of 2 and 8
+        let price = robust_token_price(&pools[..2], token, weth).expect("Pools should be priced");
+        assert!((price - 4.0).abs() < 1e-9);
+
+        //The thin pool barely moves the price
+        let price = robust_token_price(&pools, token, weth).expect("Pools should be priced");
+        assert!(price > 4.0 && price < 4.1);
+
+        assert!(robust_token_price(&pools, token, H160::from_low_u64_be(3)).is_none());
+    }
+
+    #[test]
+    fn test_best_bid_ask() {
+        let base_token = H160::from_low_u64_be(1);
+        let quote_token = H160::from_low_u64_be(2);
+
+        let pools = vec![
+            //Price of 2
+            UniswapV2Pool {
+                token_a: base_token,
+                token_a_decimals: 18,
+                token_b: quote_token,
+                token_b_decimals: 6,
+                reserve_0: 1000000000000000000000,
+                reserve_1: 2000000000,
+                fee: 300,
+                ..Default::default()
+            },
+            //Price of 2.1, with the tokens in the opposite order
+            UniswapV2Pool {
+                token_a: quote_token,
+                token_a_decimals: 6,
+                token_b: base_token,
+                token_b_decimals: 18,
+                reserve_0: 2100000000,
+                reserve_1: 1000000000000000000000,
+                fee: 300,
+                ..Default::default()
+            },
+        ];
+
+        //Selling 1 base token is best in the pool with the higher price, buying in the pool with the lower price
+        let (bid, ask) = best_bid_ask(
+            &pools,
+            base_token,
+            quote_token,
+            U256::from(1000000000000000000_u128),
+        )
+        .expect("Pools should fill the size");
+
+        assert!(bid < 2.1 && bid > 2.1 * 0.99);
+        assert!(ask > 2.0 && ask < 2.0 * 1.01);
+
+        //Larger sizes widen the spread
+        let (large_bid, large_ask) = best_bid_ask(
+            &pools,
+            base_token,
+            quote_token,
+            U256::from(100000000000000000000_u128),
+        )
+        .expect("Pools should fill the size");
+        assert!(large_bid < bid);
+        assert!(large_ask > ask);
+
+        //No pool can sell its whole base reserve
+        assert_eq!(
+            best_bid_ask(
+                &pools,
+                base_token,
+                quote_token,
+                U256::from(1000000000000000000000_u128)
+            ),
+            None
+        );
+        assert_eq!(best_bid_ask(&[], base_token, quote_token, U256::one()), None);
+    }
+
+    #[test]
+    fn test_price_in_usdc() {
+        let token = H160::from_low_u64_be(1);
+        let weth = H160::from_low_u64_be(2);
+        let usdc = H160::from_low_u64_be(3);
+
+        //Token with 8 decimals priced at 0.01 WETH
+        let token_pool = UniswapV2Pool {
+            token_a: weth,
+            token_a_decimals: 18,
+            token_b: token,
+            token_b_decimals: 8,
+            reserve_0: 10000000000000000000,
+            reserve_1: 100000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        //WETH priced at 2000 USDC
+        let weth_usdc_pool = UniswapV2Pool {
+            token_a: usdc,
+            token_a_decimals: 6,
+            token_b: weth,
+            token_b_decimals: 18,
+            reserve_0: 2000000000000,
+            reserve_1: 1000000000000000000000,
+            fee: 300,
+            ..Default::default()
+        };
+
+        let price = price_in_usdc(&token_pool, &weth_usdc_pool, token, weth)
+            .expect("Token should be priced");
+        assert!((price - 20.0).abs() < 1e-6);
+
+        //WETH is priced by the WETH/USDC pool alone
+        let price = price_in_usdc(&token_pool, &weth_usdc_pool, weth, weth)
+            .expect("WETH should be priced");
+        assert!((price - 2000.0).abs() < 1e-6);
+
+        //Both pools must trade the expected pairs
+        assert!(price_in_usdc(&token_pool, &weth_usdc_pool, usdc, weth).is_none());
+        assert!(price_in_usdc(&token_pool, &token_pool, token, usdc).is_none());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_compute_prices_par() {
+        use super::compute_prices_par;
+        use crate::amm::AutomatedMarketMaker;
+
+        let base_token = H160::from_low_u64_be(1);
+        let quote_token = H160::from_low_u64_be(2);
+
+        let pools = (1..=1000_u64)
+            .map(|i| UniswapV2Pool {
+                address: H160::from_low_u64_be(100 + i),
+                token_a: base_token,
+                token_a_decimals: 18,
+                token_b: if i == 1000 {
+                    H160::from_low_u64_be(3)
+                } else {
+                    quote_token
+                },
+                token_b_decimals: 18,
+                reserve_0: 1000000000000000000,
+                reserve_1: i as u128 * 1000000000000000000,
+                fee: 300,
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+
+        let prices = compute_prices_par(&pools, base_token);
+
+        assert_eq!(prices.len(), pools.len());
+        for ((address, price), pool) in prices.iter().zip(&pools) {
+            assert_eq!(*address, pool.address);
+            assert_eq!(*price, pool.calculate_price(base_token).ok());
+        }
+
+        //Pools without the base token are not priced
+        assert_eq!(compute_prices_par(&pools, H160::from_low_u64_be(4))[0].1, None);
+    }
+}
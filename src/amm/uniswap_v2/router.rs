@@ -0,0 +1,99 @@
+use ethers::{
+    abi::Token,
+    prelude::abigen,
+    types::{Bytes, TransactionRequest, H160, U256},
+};
+
+abigen!(
+    IUniswapV2Router02,
+    r#"[
+        function swapExactTokensForTokens(uint256 amountIn, uint256 amountOutMin, address[] calldata path, address to, uint256 deadline) external returns (uint256[] memory amounts)
+    ]"#;
+);
+
+/// Returns the calldata of `swapExactTokensForTokens` on a Uniswap V2 router, swapping `amount_in` of `path[0]` for at least `amount_out_min` of the last token of `path`.
+pub fn build_swap_exact_tokens_calldata(
+    path: &[H160],
+    amount_in: U256,
+    amount_out_min: U256,
+    to: H160,
+    deadline: U256,
+) -> Result<Bytes, ethers::abi::Error> {
+    let input_tokens = vec![
+        Token::Uint(amount_in),
+        Token::Uint(amount_out_min),
+        Token::Array(path.iter().map(|token| Token::Address(*token)).collect()),
+        Token::Address(to),
+        Token::Uint(deadline),
+    ];
+
+    Ok(IUNISWAPV2ROUTER02_ABI
+        .function("swapExactTokensForTokens")?
+        .encode_input(&input_tokens)?
+        .into())
+}
+
+/// Returns a transaction calling `swapExactTokensForTokens` on `router`, see `build_swap_exact_tokens_calldata`.
+pub fn build_swap_exact_tokens_tx(
+    router: H160,
+    path: &[H160],
+    amount_in: U256,
+    amount_out_min: U256,
+    to: H160,
+    deadline: U256,
+) -> Result<TransactionRequest, ethers::abi::Error> {
+    let calldata = build_swap_exact_tokens_calldata(path, amount_in, amount_out_min, to, deadline)?;
+
+    Ok(TransactionRequest::new().to(router).data(calldata))
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::AbiDecode,
+        types::{NameOrAddress, H160, U256},
+    };
+
+    use super::{
+        build_swap_exact_tokens_calldata, build_swap_exact_tokens_tx,
+        SwapExactTokensForTokensCall,
+    };
+
+    #[test]
+    fn test_build_swap_exact_tokens_calldata() -> eyre::Result<()> {
+        let path = vec![H160::from_low_u64_be(1), H160::from_low_u64_be(2)];
+        let to = H160::from_low_u64_be(3);
+
+        let calldata = build_swap_exact_tokens_calldata(
+            &path,
+            U256::from(1000),
+            U256::from(990),
+            to,
+            U256::from(1700000000),
+        )?;
+
+        //swapExactTokensForTokens(uint256,uint256,address[],address,uint256)
+        assert_eq!(calldata[..4], [0x38, 0xed, 0x17, 0x39]);
+
+        let call = SwapExactTokensForTokensCall::decode(&calldata)?;
+        assert_eq!(call.amount_in, U256::from(1000));
+        assert_eq!(call.amount_out_min, U256::from(990));
+        assert_eq!(call.path, path);
+        assert_eq!(call.to, to);
+        assert_eq!(call.deadline, U256::from(1700000000));
+
+        let router = H160::from_low_u64_be(4);
+        let tx = build_swap_exact_tokens_tx(
+            router,
+            &path,
+            U256::from(1000),
+            U256::from(990),
+            to,
+            U256::from(1700000000),
+        )?;
+        assert_eq!(tx.to, Some(NameOrAddress::Address(router)));
+        assert_eq!(tx.data, Some(calldata));
+
+        Ok(())
+    }
+}
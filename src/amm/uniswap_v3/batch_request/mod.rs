@@ -2,16 +2,17 @@ use std::{sync::Arc, vec};
 
 use ethers::{
     abi::{ParamType, Token},
+    contract::Multicall,
     providers::Middleware,
     types::{Bytes, I256, U256, U64},
 };
 
 use crate::{
-    amm::{AutomatedMarketMaker, AMM},
+    amm::{call_options::CallOptions, AutomatedMarketMaker, AMM},
     errors::AMMError,
 };
 
-use super::UniswapV3Pool;
+use super::{IUniswapV3Pool, UniswapV3Pool};
 
 use ethers::prelude::abigen;
 
@@ -25,6 +26,37 @@ abigen!(
 
 );
 
+// Return data layouts of the batch request contracts, built once and reused for every decode
+lazy_static::lazy_static! {
+    static ref POOL_DATA_RETURN_TYPES: Vec<ParamType> =
+        vec![ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Address,   // token a
+            ParamType::Uint(8),   // token a decimals
+            ParamType::Address,   // token b
+            ParamType::Uint(8),   // token b decimals
+            ParamType::Uint(128), // liquidity
+            ParamType::Uint(160), // sqrtPrice
+            ParamType::Int(24),   // tick
+            ParamType::Int(24),   // tickSpacing
+            ParamType::Uint(24),  // fee
+            ParamType::Int(128),  // liquidityNet
+        ])))];
+    static ref TICK_DATA_RETURN_TYPES: Vec<ParamType> = vec![
+        ParamType::Array(Box::new(ParamType::Tuple(vec![
+            ParamType::Bool,
+            ParamType::Int(24),
+            ParamType::Int(128),
+        ]))),
+        ParamType::Uint(32),
+    ];
+    static ref SYNC_POOL_RETURN_TYPES: Vec<ParamType> = vec![ParamType::Tuple(vec![
+        ParamType::Uint(128), // liquidity
+        ParamType::Uint(160), // sqrtPrice
+        ParamType::Int(24),   // tick
+        ParamType::Int(128),  // liquidityNet
+    ])];
+}
+
 fn populate_pool_data_from_tokens(
     mut pool: UniswapV3Pool,
     tokens: Vec<Token>,
@@ -33,6 +65,7 @@ fn populate_pool_data_from_tokens(
     pool.token_a_decimals = tokens[1].to_owned().into_uint()?.as_u32() as u8;
     pool.token_b = tokens[2].to_owned().into_address()?;
     pool.token_b_decimals = tokens[3].to_owned().into_uint()?.as_u32() as u8;
+    pool.decimals_synced = true;
     pool.liquidity = tokens[4].to_owned().into_uint()?.as_u128();
     pool.sqrt_price = tokens[5].to_owned().into_uint()?;
     pool.tick = I256::from_raw(tokens[6].to_owned().into_int()?).as_i32();
@@ -42,38 +75,35 @@ fn populate_pool_data_from_tokens(
     Some(pool)
 }
 
+/// Populates the data of `pool` at `block_number`, or the block of `call_options` if unset, returning the number of the block the pool was read at.
 pub async fn get_v3_pool_data_batch_request<M: Middleware>(
     pool: &mut UniswapV3Pool,
     block_number: Option<u64>,
+    call_options: &CallOptions,
     middleware: Arc<M>,
-) -> Result<(), AMMError<M>> {
+) -> Result<u64, AMMError<M>> {
     let constructor_args = Token::Tuple(vec![Token::Array(vec![Token::Address(pool.address)])]);
 
-    let mut deployer = IGetUniswapV3PoolDataBatchRequest::deploy(middleware.clone(), constructor_args)
+    let mut deployer = IGetUniswapV3PoolDataBatchRequest::deploy(middleware.clone(), constructor_args.clone())
         .map_err(|e| AMMError::ContractError("get_v3_pool_data_batch_request", pool.address, e))?;
-    if let Some(block_number) = block_number {
-        deployer = deployer.block(block_number);
-    }
+    let block_number = match block_number {
+        Some(block_number) => block_number,
+        None => call_options.resolve_block(middleware.clone()).await?,
+    };
+    deployer = deployer.block(block_number);
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
+
     let return_data: Bytes = deployer
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("get_v3_pool_data_batch_request", pool.address, e))?;
+        .map_err(|e| AMMError::batch_call_error("get_v3_pool_data_batch_request", pool.address, e))?;
 
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Array(Box::new(ParamType::Tuple(vec![
-            ParamType::Address,   // token a
-            ParamType::Uint(8),   // token a decimals
-            ParamType::Address,   // token b
-            ParamType::Uint(8),   // token b decimals
-            ParamType::Uint(128), // liquidity
-            ParamType::Uint(160), // sqrtPrice
-            ParamType::Int(24),   // tick
-            ParamType::Int(24),   // tickSpacing
-            ParamType::Uint(24),  // fee
-            ParamType::Int(128),  // liquidityNet
-        ])))],
-        &return_data,
-    )?;
+    let return_data_tokens = ethers::abi::decode(&POOL_DATA_RETURN_TYPES, &return_data)
+        .map_err(|source| AMMError::AbiDecodeError {
+            context: "get_v3_pool_data_batch_request",
+            source,
+        })?;
 
     //Update pool data
     for tokens in return_data_tokens {
@@ -88,7 +118,13 @@ pub async fn get_v3_pool_data_batch_request<M: Middleware>(
             }
         }
     }
-    Ok(())
+
+    if call_options.fee_growth_globals {
+        get_fee_growth_globals_batch_request(&mut [pool], block_number, call_options, middleware)
+            .await?;
+    }
+
+    Ok(block_number)
 }
 
 pub struct UniswapV3TickData {
@@ -103,6 +139,7 @@ pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
     zero_for_one: bool,
     num_ticks: u16,
     block_number: Option<U64>,
+    call_options: &CallOptions,
     middleware: Arc<M>,
 ) -> Result<(Vec<UniswapV3TickData>, U64), AMMError<M>> {
     let constructor_args = Token::Tuple(vec![
@@ -113,27 +150,26 @@ pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
         Token::Int(I256::from(pool.tick_spacing).into_raw()),
     ]);
 
-    let mut deployer = IGetUniswapV3TickDataBatchRequest::deploy(middleware.clone(), constructor_args)
+    let mut deployer = IGetUniswapV3TickDataBatchRequest::deploy(middleware.clone(), constructor_args.clone())
         .map_err(|e| AMMError::ContractError("get_uniswap_v3_tick_data_batch_request", pool.address, e))?;
-    if let Some(block_number) = block_number {
-        deployer = deployer.block(block_number);
-    }
+    let block_number = match block_number {
+        Some(block_number) => block_number,
+        None => call_options.resolve_block(middleware.clone()).await?,
+    };
+    deployer = deployer.block(block_number);
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
+
     let return_data: Bytes = deployer
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("get_uniswap_v3_tick_data_batch_request", pool.address, e))?;
-
-    let return_data_tokens = ethers::abi::decode(
-        &[
-            ParamType::Array(Box::new(ParamType::Tuple(vec![
-                ParamType::Bool,
-                ParamType::Int(24),
-                ParamType::Int(128),
-            ]))),
-            ParamType::Uint(32),
-        ],
-        &return_data,
-    )?;
+        .map_err(|e| AMMError::batch_call_error("get_uniswap_v3_tick_data_batch_request", pool.address, e))?;
+
+    let return_data_tokens = ethers::abi::decode(&TICK_DATA_RETURN_TYPES, &return_data)
+        .map_err(|source| AMMError::AbiDecodeError {
+            context: "get_uniswap_v3_tick_data_batch_request",
+            source,
+        })?;
 
     let tick_data_array = return_data_tokens[0]
         .to_owned()
@@ -180,28 +216,30 @@ pub async fn get_uniswap_v3_tick_data_batch_request<M: Middleware>(
     Ok((tick_data, U64::from(block_number.as_u64())))
 }
 
+/// Syncs the liquidity, price and tick of `pool`, returning the number of the block the pool was read at.
 pub async fn sync_v3_pool_batch_request<M: Middleware>(
     pool: &mut UniswapV3Pool,
+    call_options: &CallOptions,
     middleware: Arc<M>,
-) -> Result<(), AMMError<M>> {
+) -> Result<u64, AMMError<M>> {
     let constructor_args = Token::Tuple(vec![Token::Address(pool.address)]);
 
-    let deployer = ISyncUniswapV3PoolBatchRequest::deploy(middleware.clone(), constructor_args)
+    let mut deployer = ISyncUniswapV3PoolBatchRequest::deploy(middleware.clone(), constructor_args.clone())
         .map_err(|e| AMMError::ContractError("sync_v3_pool_batch_request", pool.address, e))?;
+    let block_number = call_options.resolve_block(middleware.clone()).await?;
+    deployer = deployer.block(block_number);
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
 
     let return_data: Bytes = deployer
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("sync_v3_pool_batch_request", pool.address, e))?;
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Tuple(vec![
-            ParamType::Uint(128), // liquidity
-            ParamType::Uint(160), // sqrtPrice
-            ParamType::Int(24),   // tick
-            ParamType::Int(128),  // liquidityNet
-        ])],
-        &return_data,
-    )?;
+        .map_err(|e| AMMError::batch_call_error("sync_v3_pool_batch_request", pool.address, e))?;
+    let return_data_tokens = ethers::abi::decode(&SYNC_POOL_RETURN_TYPES, &return_data)
+        .map_err(|source| AMMError::AbiDecodeError {
+            context: "sync_v3_pool_batch_request",
+            source,
+        })?;
 
     for tokens in return_data_tokens {
         if let Some(pool_data) = tokens.into_tuple() {
@@ -234,14 +272,116 @@ pub async fn sync_v3_pool_batch_request<M: Middleware>(
         }
     }
 
-    Ok(())
+    Ok(block_number)
 }
 
+/// Populates the pool data of `amms` at `block_number`, returning `block_number` like the other batch requests.
+/// When the node rejects a batch, ie. because the return data exceeds its limit, the batch is split in halves and each half is retried, down to single pools.
 pub async fn get_amm_data_batch_request<M: Middleware>(
     amms: &mut [AMM],
     block_number: u64,
+    call_options: &CallOptions,
+    middleware: Arc<M>,
+) -> Result<u64, AMMError<M>> {
+    //Ranges of `amms` left to request, the first half of a split range is requested first
+    let mut ranges = vec![0..amms.len()];
+    while let Some(range) = ranges.pop() {
+        match get_amm_data_single_batch_request(
+            &mut amms[range.clone()],
+            block_number,
+            call_options,
+            middleware.clone(),
+        )
+        .await
+        {
+            Ok(_) => {}
+
+            Err(e) if e.is_batch_rejected() && range.len() > 1 => {
+                let mid = range.start + range.len() / 2;
+                ranges.push(mid..range.end);
+                ranges.push(range.start..mid);
+            }
+
+            Err(e) => return Err(e),
+        }
+    }
+
+    if call_options.fee_growth_globals {
+        let mut pools = amms
+            .iter_mut()
+            .filter_map(|amm| match amm {
+                AMM::UniswapV3Pool(pool) if !pool.token_a.is_zero() => Some(pool),
+                _ => None,
+            })
+            .collect::<Vec<&mut UniswapV3Pool>>();
+        get_fee_growth_globals_batch_request(&mut pools, block_number, call_options, middleware)
+            .await?;
+    }
+
+    Ok(block_number)
+}
+
+/// Reads the `feeGrowthGlobal0X128` and `feeGrowthGlobal1X128` of each pool at `block_number`, aggregating the calls with Multicall3.
+/// Pools whose calls revert keep their fee growth. Fails with `AMMError::MulticallError` on chains without a known Multicall3 deployment.
+/// The `from` and access list of `call_options` are set on the aggregate call, `bytecode` does not apply since no batch contract is deployed.
+pub async fn get_fee_growth_globals_batch_request<M: Middleware>(
+    pools: &mut [&mut UniswapV3Pool],
+    block_number: u64,
+    call_options: &CallOptions,
     middleware: Arc<M>,
 ) -> Result<(), AMMError<M>> {
+    let Some(batch_start) = pools.first().map(|pool| pool.address) else {
+        return Ok(());
+    };
+
+    let mut multicall = Multicall::new(middleware.clone(), None)
+        .await
+        .map_err(AMMError::MulticallError)?
+        .block(block_number);
+
+    for pool in pools.iter() {
+        let v3_pool = IUniswapV3Pool::new(pool.address, middleware.clone());
+        multicall.add_call(v3_pool.method::<_, U256>("feeGrowthGlobal0X128", ())?, true);
+        multicall.add_call(v3_pool.method::<_, U256>("feeGrowthGlobal1X128", ())?, true);
+    }
+
+    let mut aggregate_call = multicall.as_aggregate_3();
+    if let Some(from) = call_options.from {
+        aggregate_call = aggregate_call.from(from);
+    }
+    if let Some(access_list) = &call_options.access_list {
+        aggregate_call.tx.set_access_list(access_list.clone());
+    }
+    call_options.acquire().await;
+
+    let results = aggregate_call.call().await.map_err(|e| {
+        AMMError::ContractError("get_fee_growth_globals_batch_request", batch_start, e)
+    })?;
+
+    //Reverted calls return no data, which is not decoded
+    let fee_growth = |success: bool, return_data: &Bytes| {
+        (success && return_data.len() == 32).then(|| U256::from_big_endian(return_data))
+    };
+    for (pool, results) in pools.iter_mut().zip(results.chunks(2)) {
+        if let (Some(fee_growth_0), Some(fee_growth_1)) = (
+            fee_growth(results[0].success, &results[0].return_data),
+            fee_growth(results[1].success, &results[1].return_data),
+        ) {
+            pool.fee_growth_global_0_x_128 = Some(fee_growth_0);
+            pool.fee_growth_global_1_x_128 = Some(fee_growth_1);
+        }
+    }
+
+    Ok(())
+}
+
+//Populates the pool data of `amms` at `block_number` with a single batch request
+async fn get_amm_data_single_batch_request<M: Middleware>(
+    amms: &mut [AMM],
+    block_number: u64,
+    call_options: &CallOptions,
+    middleware: Arc<M>,
+) -> Result<u64, AMMError<M>> {
     let batch_start = amms.first().map(|a| a.address()).unwrap_or_default();
     let target_addresses = amms
         .iter()
@@ -249,30 +389,22 @@ pub async fn get_amm_data_batch_request<M: Middleware>(
         .collect::<Vec<Token>>();
 
     let constructor_args = Token::Tuple(vec![Token::Array(target_addresses)]);
-    let deployer = IGetUniswapV3PoolDataBatchRequest::deploy(middleware.clone(), constructor_args)
+    let mut deployer = IGetUniswapV3PoolDataBatchRequest::deploy(middleware.clone(), constructor_args.clone())
         .map_err(|e| AMMError::ContractError("get_amm_data_batch_request", batch_start, e))?;
+    call_options.apply(&mut deployer.deployer.tx, constructor_args);
+    call_options.acquire().await;
 
     let return_data: Bytes = deployer
         .block(block_number)
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("get_amm_data_batch_request", batch_start, e))?;
+        .map_err(|e| AMMError::batch_call_error("get_amm_data_batch_request", batch_start, e))?;
 
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Array(Box::new(ParamType::Tuple(vec![
-            ParamType::Address,   // token a
-            ParamType::Uint(8),   // token a decimals
-            ParamType::Address,   // token b
-            ParamType::Uint(8),   // token b decimals
-            ParamType::Uint(128), // liquidity
-            ParamType::Uint(160), // sqrtPrice
-            ParamType::Int(24),   // tick
-            ParamType::Int(24),   // tickSpacing
-            ParamType::Uint(24),  // fee
-            ParamType::Int(128),  // liquidityNet
-        ])))],
-        &return_data,
-    )?;
+    let return_data_tokens = ethers::abi::decode(&POOL_DATA_RETURN_TYPES, &return_data)
+        .map_err(|source| AMMError::AbiDecodeError {
+            context: "get_amm_data_batch_request",
+            source,
+        })?;
 
     let mut pool_idx = 0;
 
@@ -305,5 +437,5 @@ pub async fn get_amm_data_batch_request<M: Middleware>(
 
     //TODO: should we clean up empty pools here?
 
-    Ok(())
+    Ok(block_number)
 }
@@ -15,6 +15,7 @@ use tokio::task::JoinHandle;
 
 use crate::{
     amm::{
+        call_options::CallOptions,
         factory::{AutomatedMarketMakerFactory, TASK_LIMIT},
         AutomatedMarketMaker, AMM,
     },
@@ -103,6 +104,7 @@ impl AutomatedMarketMakerFactory for UniswapV3Factory {
                 batch_request::get_amm_data_batch_request(
                     amm_chunk,
                     block_number,
+                    &CallOptions::default(),
                     middleware.clone(),
                 )
                 .await?;
@@ -132,6 +134,9 @@ impl AutomatedMarketMakerFactory for UniswapV3Factory {
             tick_bitmap: HashMap::new(),
             ticks: HashMap::new(),
             last_active_at_block: block_number,
+            decimals_synced: false,
+            fee_growth_global_0_x_128: None,
+            fee_growth_global_1_x_128: None,
         }))
     }
 }
@@ -1,5 +1,7 @@
 pub mod batch_request;
 pub mod factory;
+pub mod oracle;
+pub mod quoter;
 
 use std::{
     cmp::Ordering,
@@ -8,7 +10,7 @@ use std::{
 };
 
 use crate::{
-    amm::AutomatedMarketMaker,
+    amm::{call_options::CallOptions, AutomatedMarketMaker},
     errors::{AMMError, ArithmeticError, EventLogError, SwapSimulationError},
 };
 use async_trait::async_trait;
@@ -16,7 +18,7 @@ use ethers::{
     abi::{ethabi::Bytes, RawLog, Token},
     prelude::{AbiError, EthEvent},
     providers::Middleware,
-    types::{BlockNumber, Filter, Log, H160, H256, I256, U256, U64},
+    types::{BlockNumber, Filter, Log, H160, H256, I256, U256, U512, U64},
 };
 use num_bigfloat::BigFloat;
 use serde::{Deserialize, Serialize};
@@ -41,11 +43,14 @@ abigen!(
         function token0() external view returns (address)
         function token1() external view returns (address)
         function liquidity() external view returns (uint128)
+        function feeGrowthGlobal0X128() external view returns (uint256)
+        function feeGrowthGlobal1X128() external view returns (uint256)
         function slot0() external view returns (uint160, int24, uint16, uint16, uint16, uint8, bool)
         function fee() external view returns (uint24)
         function tickSpacing() external view returns (int24)
         function ticks(int24 tick) external view returns (uint128, int128, uint256, uint256, int56, uint160, uint32, bool)
         function tickBitmap(int16 wordPosition) external view returns (uint256)
+        function observe(uint32[] secondsAgos) external view returns (int56[] tickCumulatives, uint160[] secondsPerLiquidityCumulativeX128s)
         function swap(address recipient, bool zeroForOne, int256 amountSpecified, uint160 sqrtPriceLimitX96, bytes calldata data) external returns (int256, int256)
         event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)
         event Burn(address indexed owner, int24 indexed tickLower, int24 indexed tickUpper, uint128 amount, uint256 amount0, uint256 amount1)
@@ -81,9 +86,25 @@ pub const MINT_EVENT_SIGNATURE: H256 = H256([
     133, 72, 143, 8, 83, 174, 22, 35, 157, 11, 222,
 ]);
 
+pub const UNISWAP_V3_FACTORY: H160 = H160([
+    31, 152, 67, 28, 138, 217, 133, 35, 99, 26, 228, 165, 159, 38, 115, 70, 234, 49, 249, 132,
+]);
+
+// keccak256 of the Uniswap V3 pool creation code
+pub const UNISWAP_V3_POOL_INIT_CODE_HASH: H256 = H256([
+    227, 79, 25, 155, 25, 178, 180, 244, 127, 104, 68, 38, 25, 213, 85, 82, 125, 36, 79, 120, 163,
+    41, 126, 168, 147, 37, 248, 67, 248, 123, 139, 84,
+]);
+
 pub const U256_TWO: U256 = U256([2, 0, 0, 0]);
 pub const Q128: U256 = U256([0, 0, 1, 0]);
 pub const Q224: U256 = U256([0, 0, 0, 4294967296]);
+pub const Q96: u128 = 79228162514264337593543950336;
+pub const Q96_MASK: U256 = U256([18446744073709551615, 4294967295, 0, 0]);
+// Approximate gas used by a swap within a single tick range, including the token transfers
+pub const SWAP_GAS_ESTIMATE: u64 = 120000;
+// Approximate additional gas used for each initialized tick crossed by a swap
+pub const TICK_CROSS_GAS_ESTIMATE: u64 = 25000;
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UniswapV3Pool {
     pub address: H160,
@@ -99,6 +120,15 @@ pub struct UniswapV3Pool {
     pub tick_bitmap: HashMap<i16, U256>,
     pub ticks: HashMap<i32, Info>,
     pub last_active_at_block: Option<u64>,
+    /// True once the token decimals were read from the chain, to tell tokens with zero decimals apart from decimals that were never fetched.
+    #[serde(default)]
+    pub decimals_synced: bool,
+    /// Fee growth per unit of liquidity of each token over the life of the pool, as a Q128.128. `None` until read from the chain, it is not updated from logs.
+    /// Only read by batch requests made with `CallOptions::fee_growth_globals`.
+    #[serde(default)]
+    pub fee_growth_global_0_x_128: Option<U256>,
+    #[serde(default)]
+    pub fee_growth_global_1_x_128: Option<U256>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -125,7 +155,8 @@ impl AutomatedMarketMaker for UniswapV3Pool {
     }
 
     async fn sync<M: Middleware>(&mut self, middleware: Arc<M>) -> Result<(), AMMError<M>> {
-        batch_request::sync_v3_pool_batch_request(self, middleware.clone()).await?;
+        batch_request::sync_v3_pool_batch_request(self, &CallOptions::default(), middleware.clone())
+            .await?;
         Ok(())
     }
 
@@ -159,158 +190,57 @@ impl AutomatedMarketMaker for UniswapV3Pool {
     }
 
     fn calculate_price(&self, base_token: H160) -> Result<f64, ArithmeticError> {
-        let tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(self.sqrt_price)?;
-        let shift = self.token_a_decimals as i8 - self.token_b_decimals as i8;
+        self.check_sqrt_price()?;
 
-        let price = match shift.cmp(&0) {
-            Ordering::Less => 1.0001_f64.powi(tick) / 10_f64.powi(-shift as i32),
-            Ordering::Greater => 1.0001_f64.powi(tick) * 10_f64.powi(shift as i32),
-            Ordering::Equal => 1.0001_f64.powi(tick),
-        };
+        //Price of token_a denominated in token_b, adjusted for the decimals of each token
+        let sqrt_price = self.sqrt_price_big_float();
+        let price = shift_decimals(
+            sqrt_price.mul(&sqrt_price),
+            self.token_a_decimals as i32 - self.token_b_decimals as i32,
+        );
 
         if base_token == self.token_a {
-            Ok(price)
+            Ok(price.to_f64())
         } else {
-            Ok(1.0 / price)
+            Ok(BigFloat::from(1).div(&price).to_f64())
         }
     }
+
+    //The price of token_b is squared from the inverse sqrt price rather than inverted from the price of token_a
+    fn calculate_price_both(&self) -> Result<(f64, f64), ArithmeticError> {
+        self.check_sqrt_price()?;
+
+        let sqrt_price = self.sqrt_price_big_float();
+        let inverse_sqrt_price = BigFloat::from(1).div(&sqrt_price);
+        let shift = self.token_a_decimals as i32 - self.token_b_decimals as i32;
+
+        let price_a = shift_decimals(sqrt_price.mul(&sqrt_price), shift);
+        let price_b = shift_decimals(inverse_sqrt_price.mul(&inverse_sqrt_price), -shift);
+
+        Ok((price_a.to_f64(), price_b.to_f64()))
+    }
     // NOTE: This function will not populate the tick_bitmap and ticks, if you want to populate those, you must call populate_tick_data on an initialized pool
     async fn populate_data<M: Middleware>(
         &mut self,
         block_number: Option<u64>,
         middleware: Arc<M>,
     ) -> Result<(), AMMError<M>> {
-        batch_request::get_v3_pool_data_batch_request(self, block_number, middleware.clone())
-            .await?;
+        batch_request::get_v3_pool_data_batch_request(
+            self,
+            block_number,
+            &CallOptions::default(),
+            middleware.clone(),
+        )
+        .await?;
         Ok(())
     }
 
     fn simulate_swap(&self, token_in: H160, amount_in: U256) -> Result<U256, SwapSimulationError> {
-        if amount_in.is_zero() {
-            return Ok(U256::zero());
-        }
-
-        let zero_for_one = token_in == self.token_a;
-
-        //Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
-        let sqrt_price_limit_x_96 = if zero_for_one {
-            MIN_SQRT_RATIO + 1
-        } else {
-            MAX_SQRT_RATIO - 1
-        };
-
-        //Initialize a mutable state state struct to hold the dynamic simulated state of the pool
-        let mut current_state = CurrentState {
-            sqrt_price_x_96: self.sqrt_price, //Active price on the pool
-            amount_calculated: I256::zero(),  //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: self.tick,                                       //Current i24 tick of the pool
-            liquidity: self.liquidity, //Current available liquidity in the tick range
-        };
-
-        while current_state.amount_specified_remaining != I256::zero()
-            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
-        {
-            //Initialize a new step struct to hold the dynamic state of the pool at each step
-            let mut step = StepComputations {
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
-                ..Default::default()
-            };
-
-            //Get the next tick from the current tick
-            (step.tick_next, step.initialized) =
-                uniswap_v3_math::tick_bitmap::next_initialized_tick_within_one_word(
-                    &self.tick_bitmap,
-                    current_state.tick,
-                    self.tick_spacing,
-                    zero_for_one,
-                )?;
-
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            //Note: this could be removed as we are clamping in the batch contract
-            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
-
-            //Get the next sqrt price from the input amount
-            step.sqrt_price_next_x96 =
-                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
-
-            //Target spot price
-            let swap_target_sqrt_ratio = if zero_for_one {
-                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
-                    sqrt_price_limit_x_96
-                } else {
-                    step.sqrt_price_next_x96
-                }
-            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
-                sqrt_price_limit_x_96
-            } else {
-                step.sqrt_price_next_x96
-            };
-
-            //Compute swap step and update the current state
-            (
-                current_state.sqrt_price_x_96,
-                step.amount_in,
-                step.amount_out,
-                step.fee_amount,
-            ) = uniswap_v3_math::swap_math::compute_swap_step(
-                current_state.sqrt_price_x_96,
-                swap_target_sqrt_ratio,
-                current_state.liquidity,
-                current_state.amount_specified_remaining,
-                self.fee,
-            )?;
-
-            //Decrement the amount remaining to be swapped and amount received from the step
-            current_state.amount_specified_remaining = current_state
-                .amount_specified_remaining
-                .overflowing_sub(I256::from_raw(
-                    step.amount_in.overflowing_add(step.fee_amount).0,
-                ))
-                .0;
-
-            current_state.amount_calculated -= I256::from_raw(step.amount_out);
-
-            //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
-            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if step.initialized {
-                    let mut liquidity_net = if let Some(info) = self.ticks.get(&step.tick_next) {
-                        info.liquidity_net
-                    } else {
-                        0
-                    };
-
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
-                    if zero_for_one {
-                        liquidity_net = -liquidity_net;
-                    }
-
-                    current_state.liquidity = if liquidity_net < 0 {
-                        if current_state.liquidity < (-liquidity_net as u128) {
-                            return Err(SwapSimulationError::LiquidityUnderflow);
-                        } else {
-                            current_state.liquidity - (-liquidity_net as u128)
-                        }
-                    } else {
-                        current_state.liquidity + (liquidity_net as u128)
-                    };
-                }
-                //Increment the current tick
-                current_state.tick = if zero_for_one {
-                    step.tick_next.wrapping_sub(1)
-                } else {
-                    step.tick_next
-                }
-                //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
-            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
-                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
-                    current_state.sqrt_price_x_96,
-                )?;
-            }
-        }
-
-        Ok((-current_state.amount_calculated).into_raw())
+        self.simulate_swap_at(
+            token_in,
+            amount_in,
+            (self.sqrt_price, self.liquidity, self.tick),
+        )
     }
 
     fn simulate_swap_mut(
@@ -318,136 +248,20 @@ impl AutomatedMarketMaker for UniswapV3Pool {
         token_in: H160,
         amount_in: U256,
     ) -> Result<U256, SwapSimulationError> {
-        if amount_in.is_zero() {
-            return Ok(U256::zero());
-        }
-
-        let zero_for_one = token_in == self.token_a;
-
-        //Set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
-        let sqrt_price_limit_x_96 = if zero_for_one {
-            MIN_SQRT_RATIO + 1
-        } else {
-            MAX_SQRT_RATIO - 1
-        };
-
-        //Initialize a mutable state state struct to hold the dynamic simulated state of the pool
-        let mut current_state = CurrentState {
-            sqrt_price_x_96: self.sqrt_price, //Active price on the pool
-            amount_calculated: I256::zero(),  //Amount of token_out that has been calculated
-            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
-            tick: self.tick,                                       //Current i24 tick of the pool
-            liquidity: self.liquidity, //Current available liquidity in the tick range
-        };
-
-        while current_state.amount_specified_remaining != I256::zero()
-            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
-        {
-            //Initialize a new step struct to hold the dynamic state of the pool at each step
-            let mut step = StepComputations {
-                sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
-                ..Default::default()
-            };
-
-            //Get the next tick from the current tick
-            (step.tick_next, step.initialized) =
-                uniswap_v3_math::tick_bitmap::next_initialized_tick_within_one_word(
-                    &self.tick_bitmap,
-                    current_state.tick,
-                    self.tick_spacing,
-                    zero_for_one,
-                )?;
-
-            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
-            //Note: this could be removed as we are clamping in the batch contract
-            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
-
-            //Get the next sqrt price from the input amount
-            step.sqrt_price_next_x96 =
-                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
-
-            //Target spot price
-            let swap_target_sqrt_ratio = if zero_for_one {
-                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
-                    sqrt_price_limit_x_96
-                } else {
-                    step.sqrt_price_next_x96
-                }
-            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
-                sqrt_price_limit_x_96
-            } else {
-                step.sqrt_price_next_x96
-            };
-
-            //Compute swap step and update the current state
-            (
-                current_state.sqrt_price_x_96,
-                step.amount_in,
-                step.amount_out,
-                step.fee_amount,
-            ) = uniswap_v3_math::swap_math::compute_swap_step(
-                current_state.sqrt_price_x_96,
-                swap_target_sqrt_ratio,
-                current_state.liquidity,
-                current_state.amount_specified_remaining,
-                self.fee,
-            )?;
-
-            //Decrement the amount remaining to be swapped and amount received from the step
-            current_state.amount_specified_remaining = current_state
-                .amount_specified_remaining
-                .overflowing_sub(I256::from_raw(
-                    step.amount_in.overflowing_add(step.fee_amount).0,
-                ))
-                .0;
-
-            current_state.amount_calculated -= I256::from_raw(step.amount_out);
-
-            //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
-            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
-                if step.initialized {
-                    let mut liquidity_net = if let Some(info) = self.ticks.get(&step.tick_next) {
-                        info.liquidity_net
-                    } else {
-                        0
-                    };
-
-                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
-                    if zero_for_one {
-                        liquidity_net = -liquidity_net;
-                    }
-
-                    current_state.liquidity = if liquidity_net < 0 {
-                        if current_state.liquidity < (-liquidity_net as u128) {
-                            return Err(SwapSimulationError::LiquidityUnderflow);
-                        } else {
-                            current_state.liquidity - (-liquidity_net as u128)
-                        }
-                    } else {
-                        current_state.liquidity + (liquidity_net as u128)
-                    };
-                }
-                //Increment the current tick
-                current_state.tick = if zero_for_one {
-                    step.tick_next.wrapping_sub(1)
-                } else {
-                    step.tick_next
-                }
-                //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
-                //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
-            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
-                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
-                    current_state.sqrt_price_x_96,
-                )?;
-            }
-        }
+        let (_, amount_out, (sqrt_price, liquidity, tick)) = self.simulate_swap_traced(
+            token_in,
+            amount_in,
+            (self.sqrt_price, self.liquidity, self.tick),
+            None,
+            None,
+        )?;
 
         //Update the pool state
-        self.liquidity = current_state.liquidity;
-        self.sqrt_price = current_state.sqrt_price_x_96;
-        self.tick = current_state.tick;
+        self.liquidity = liquidity;
+        self.sqrt_price = sqrt_price;
+        self.tick = tick;
 
-        Ok((-current_state.amount_calculated).into_raw())
+        Ok(amount_out)
     }
 
     fn get_token_out(&self, token_in: H160) -> H160 {
@@ -457,6 +271,32 @@ impl AutomatedMarketMaker for UniswapV3Pool {
             self.token_a
         }
     }
+
+    //The fee tier is stored in hundredths of a basis point, ie. 500 => 5 bps
+    fn fee_bps(&self) -> u32 {
+        self.fee / 100
+    }
+
+    fn liquidity(&self) -> U256 {
+        U256::from(self.liquidity)
+    }
+
+    fn swap_gas_estimate(&self) -> u64 {
+        SWAP_GAS_ESTIMATE
+    }
+}
+
+//Scales a raw price by 10^shift, where shift is the decimals of the base token minus the decimals of the quote token
+//The power of ten is built in BigFloat, as an f64 power of ten is inexact past 10^22
+fn shift_decimals(price: BigFloat, shift: i32) -> BigFloat {
+    let ten = BigFloat::from(10);
+    let scale = (0..shift.unsigned_abs()).fold(BigFloat::from(1), |scale, _| scale.mul(&ten));
+
+    match shift.cmp(&0) {
+        Ordering::Less => price.div(&scale),
+        Ordering::Greater => price.mul(&scale),
+        Ordering::Equal => price,
+    }
 }
 
 impl UniswapV3Pool {
@@ -490,6 +330,9 @@ impl UniswapV3Pool {
             tick_bitmap,
             ticks,
             last_active_at_block,
+            decimals_synced: false,
+            fee_growth_global_0_x_128: None,
+            fee_growth_global_1_x_128: None,
         }
     }
 
@@ -513,6 +356,9 @@ impl UniswapV3Pool {
             tick_bitmap: HashMap::new(),
             ticks: HashMap::new(),
             last_active_at_block: Some(creation_block),
+            decimals_synced: false,
+            fee_growth_global_0_x_128: None,
+            fee_growth_global_1_x_128: None,
         };
 
         //We need to get tick spacing before populating tick data because tick spacing can not be uninitialized when syncing burn and mint logs
@@ -532,6 +378,33 @@ impl UniswapV3Pool {
         Ok(pool)
     }
 
+    /// Returns the pool at `pair_address` with its tokens, decimals, fee, tick spacing, liquidity and `slot0` state read in a single batch request.
+    /// Tick data is not populated, so swap simulations are only exact while they stay within the current tick range. Use `new_from_address` to also populate the ticks.
+    pub async fn from_address<M: Middleware>(
+        pair_address: H160,
+        middleware: Arc<M>,
+    ) -> Result<Self, AMMError<M>> {
+        let mut pool = UniswapV3Pool {
+            address: pair_address,
+            ..Default::default()
+        };
+
+        let block_number = batch_request::get_v3_pool_data_batch_request(
+            &mut pool,
+            None,
+            &CallOptions::default(),
+            middleware,
+        )
+        .await?;
+        pool.last_active_at_block = Some(block_number);
+
+        if !pool.data_is_populated() {
+            return Err(AMMError::PoolDataError);
+        }
+
+        Ok(pool)
+    }
+
     pub async fn new_from_log<M: 'static + Middleware>(
         log: Log,
         middleware: Arc<M>,
@@ -556,6 +429,31 @@ impl UniswapV3Pool {
         }
     }
 
+    /// Returns the CREATE2 address of the pool of `token_a` and `token_b` with `fee` deployed by `factory`, without any call to the chain.
+    /// The pool exists only if the address has code. `fee` is in hundredths of a basis point, ie. 3000 for 0.3%.
+    pub fn compute_address(
+        factory: H160,
+        token_a: H160,
+        token_b: H160,
+        fee: u32,
+        init_code_hash: H256,
+    ) -> H160 {
+        let (token_0, token_1) = if token_a < token_b {
+            (token_a, token_b)
+        } else {
+            (token_b, token_a)
+        };
+
+        //The salt is keccak256(abi.encode(token0, token1, fee))
+        let salt = ethers::utils::keccak256(ethers::abi::encode(&[
+            Token::Address(token_0),
+            Token::Address(token_1),
+            Token::Uint(U256::from(fee)),
+        ]));
+
+        ethers::utils::get_create2_address_from_hash(factory, salt, init_code_hash)
+    }
+
     pub fn new_empty_pool_from_log(log: Log) -> Result<Self, EventLogError> {
         let event_signature = log.topics[0];
 
@@ -577,6 +475,9 @@ impl UniswapV3Pool {
                 tick_bitmap: HashMap::new(),
                 ticks: HashMap::new(),
                 last_active_at_block: block_number,
+                decimals_synced: false,
+                fee_growth_global_0_x_128: None,
+                fee_growth_global_1_x_128: None,
             })
         } else {
             Err(EventLogError::InvalidEventSignature)
@@ -840,11 +741,15 @@ impl UniswapV3Pool {
 
         if liquidity_delta != 0 {
             //if the tick is between the tick lower and tick upper, update the liquidity between the ticks
-            if self.tick > tick_lower && self.tick < tick_upper {
+            if self.tick >= tick_lower && self.tick < tick_upper {
                 self.liquidity = if liquidity_delta < 0 {
-                    self.liquidity - ((-liquidity_delta) as u128)
+                    self.liquidity
+                        .checked_sub((-liquidity_delta) as u128)
+                        .ok_or(ArithmeticError::LiquidityUnderflow)?
                 } else {
-                    self.liquidity + (liquidity_delta as u128)
+                    self.liquidity
+                        .checked_add(liquidity_delta as u128)
+                        .ok_or(ArithmeticError::LiquidityOverflow)?
                 }
             }
         }
@@ -852,6 +757,182 @@ impl UniswapV3Pool {
         Ok(())
     }
 
+    //The sqrt price is a Q64.96, the integer and fractional parts are converted separately to avoid losing precision
+    //Errors for a sqrt price outside of the valid range, ie. zero for an uninitialized pool
+    fn check_sqrt_price(&self) -> Result<(), ArithmeticError> {
+        uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(self.sqrt_price)?;
+        Ok(())
+    }
+
+    fn sqrt_price_big_float(&self) -> BigFloat {
+        BigFloat::from((self.sqrt_price >> 96).as_u128()).add(
+            &BigFloat::from((self.sqrt_price & Q96_MASK).as_u128()).div(&BigFloat::from(Q96)),
+        )
+    }
+
+    /// Returns the fees of each token earned by the in-range liquidity since `earlier`, a snapshot of the same pool, or `None` if the fee growth of either snapshot was not read.
+    /// The fee growth is scaled by the current liquidity, so the estimate is only exact if the active liquidity did not change between the snapshots.
+    pub fn fees_accrued_since(&self, earlier: &UniswapV3Pool) -> Option<(U256, U256)> {
+        let fees = |current: Option<U256>, earlier: Option<U256>| {
+            //Fee growth is allowed to overflow on chain, so the difference wraps as well
+            let growth = current?.overflowing_sub(earlier?).0;
+            U256::try_from(growth.full_mul(U256::from(self.liquidity)) >> 128).ok()
+        };
+
+        Some((
+            fees(
+                self.fee_growth_global_0_x_128,
+                earlier.fee_growth_global_0_x_128,
+            )?,
+            fees(
+                self.fee_growth_global_1_x_128,
+                earlier.fee_growth_global_1_x_128,
+            )?,
+        ))
+    }
+
+    /// Returns the reserves `(x, y)` of the constant product pool equivalent to the active liquidity at the current price, ie. `x = L / sqrtP` and `y = L * sqrtP`.
+    /// The approximation is only valid while the price stays within the current tick range, past the next initialized tick the active liquidity changes.
+    pub fn virtual_reserves(&self) -> (U256, U256) {
+        if self.sqrt_price.is_zero() {
+            return (U256::zero(), U256::zero());
+        }
+
+        let liquidity = U256::from(self.liquidity);
+
+        //L < 2^128 and 2^32 < sqrtP < 2^160, so neither reserve can overflow a U256
+        let reserve_x = liquidity.full_mul(U256::from(Q96)) / U512::from(self.sqrt_price);
+        let reserve_y = liquidity.full_mul(self.sqrt_price) >> 96;
+
+        (
+            U256::try_from(reserve_x).unwrap_or(U256::MAX),
+            U256::try_from(reserve_y).unwrap_or(U256::MAX),
+        )
+    }
+
+    /// Returns the maximum amount of `token_in`, fees included, that can be swapped before the price of `token_in` in the output token drops by more than `band_bps`.
+    /// The liquidity of every initialized tick crossed before the band boundary is summed, as in a swap simulation stopping at the boundary price.
+    pub fn depth_within(&self, band_bps: u32, token_in: H160) -> Result<U256, SwapSimulationError> {
+        if token_in != self.token_a && token_in != self.token_b {
+            return Err(SwapSimulationError::InvalidToken(token_in));
+        }
+
+        if self.sqrt_price.is_zero() {
+            return Ok(U256::zero());
+        }
+
+        let zero_for_one = token_in == self.token_a;
+
+        //The price is sqrtP^2, so the boundary sqrt price moves by sqrt(1 - band), scaled by 1e8 to stay in integers
+        let sqrt_price_limit_x_96 = if band_bps >= 10000 {
+            if zero_for_one {
+                MIN_SQRT_RATIO + 1
+            } else {
+                MAX_SQRT_RATIO - 1
+            }
+        } else {
+            let scale = U256::from(100_000_000_u64);
+            let sqrt_band =
+                (U256::from(10000 - band_bps) * U256::from(1_000_000_000_000_u64)).integer_sqrt();
+
+            if zero_for_one {
+                (self.sqrt_price * sqrt_band / scale).max(MIN_SQRT_RATIO + 1)
+            } else {
+                (self.sqrt_price * scale / sqrt_band).min(MAX_SQRT_RATIO - 1)
+            }
+        };
+
+        //The boundary is the current price for an empty band or a price already at the bound
+        let price_can_move = if zero_for_one {
+            sqrt_price_limit_x_96 < self.sqrt_price
+        } else {
+            sqrt_price_limit_x_96 > self.sqrt_price
+        };
+        if !price_can_move {
+            return Ok(U256::zero());
+        }
+
+        //Swapping an unbounded amount stops at the band boundary, the amount consumed is the depth
+        let (depth, _, _) = self.simulate_swap_traced(
+            token_in,
+            I256::MAX.into_raw(),
+            (self.sqrt_price, self.liquidity, self.tick),
+            Some(sqrt_price_limit_x_96),
+            None,
+        )?;
+
+        Ok(depth)
+    }
+
+    /// Adds `amount` of liquidity to the `[tick_lower, tick_upper)` range as a mint would, updating the ticks, the tick bitmap and the active liquidity if the range contains the current tick.
+    pub fn add_liquidity(
+        &mut self,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: u128,
+    ) -> Result<(), ArithmeticError> {
+        self.check_ticks(tick_lower, tick_upper)?;
+        let liquidity_delta =
+            i128::try_from(amount).map_err(|_| ArithmeticError::LiquidityOverflow)?;
+
+        //Validate before modifying the position so that a failure does not leave the ticks partially updated
+        for tick in [tick_lower, tick_upper] {
+            let liquidity_gross = self.ticks.get(&tick).map_or(0, |info| info.liquidity_gross);
+            if liquidity_gross.checked_add(amount).is_none() {
+                return Err(ArithmeticError::LiquidityOverflow);
+            }
+        }
+
+        if self.tick >= tick_lower
+            && self.tick < tick_upper
+            && self.liquidity.checked_add(amount).is_none()
+        {
+            return Err(ArithmeticError::LiquidityOverflow);
+        }
+
+        self.modify_position(tick_lower, tick_upper, liquidity_delta)
+    }
+
+    /// Removes `amount` of liquidity from the `[tick_lower, tick_upper)` range as a burn would, updating the ticks, the tick bitmap and the active liquidity if the range contains the current tick.
+    pub fn remove_liquidity(
+        &mut self,
+        tick_lower: i32,
+        tick_upper: i32,
+        amount: u128,
+    ) -> Result<(), ArithmeticError> {
+        self.check_ticks(tick_lower, tick_upper)?;
+        let liquidity_delta =
+            i128::try_from(amount).map_err(|_| ArithmeticError::LiquidityOverflow)?;
+
+        //Validate before modifying the position so that a failure does not leave the ticks partially updated
+        for tick in [tick_lower, tick_upper] {
+            let liquidity_gross = self.ticks.get(&tick).map_or(0, |info| info.liquidity_gross);
+            if liquidity_gross < amount {
+                return Err(ArithmeticError::UniswapV3TickUnderflow);
+            }
+        }
+
+        if self.tick >= tick_lower && self.tick < tick_upper && self.liquidity < amount {
+            return Err(ArithmeticError::LiquidityUnderflow);
+        }
+
+        self.modify_position(tick_lower, tick_upper, -liquidity_delta)
+    }
+
+    fn check_ticks(&self, tick_lower: i32, tick_upper: i32) -> Result<(), ArithmeticError> {
+        if tick_lower >= tick_upper
+            || tick_lower < MIN_TICK
+            || tick_upper > MAX_TICK
+            || self.tick_spacing <= 0
+            || tick_lower % self.tick_spacing != 0
+            || tick_upper % self.tick_spacing != 0
+        {
+            return Err(ArithmeticError::InvalidTickRange(tick_lower, tick_upper));
+        }
+
+        Ok(())
+    }
+
     pub fn update_position(&mut self, tick_lower: i32, tick_upper: i32, liquidity_delta: i128) -> Result<(), ArithmeticError> {
         let mut flipped_lower = false;
         let mut flipped_upper = false;
@@ -1017,22 +1098,271 @@ impl UniswapV3Pool {
         //We cant right shift sqrt_price because it could move the value to 0, making divison by 0 to get reserve_x
         let liquidity = liquidity;
 
-        let (reserve_0, reserve_1) = if !sqrt_price.is_zero() {
-            let reserve_x = liquidity.div(&sqrt_price);
-            let reserve_y = liquidity.mul(&sqrt_price);
+        let (reserve_0, reserve_1) = if !sqrt_price.is_zero() {
+            let reserve_x = liquidity.div(&sqrt_price);
+            let reserve_y = liquidity.mul(&sqrt_price);
+
+            (reserve_x, reserve_y)
+        } else {
+            (BigFloat::from(0), BigFloat::from(0))
+        };
+
+        Ok((
+            reserve_0
+                .to_u128()
+                .ok_or(ArithmeticError::U128ConversionError)?,
+            reserve_1
+                .to_u128()
+                .ok_or(ArithmeticError::U128ConversionError)?,
+        ))
+    }
+
+    /// Simulates a swap against the provided `(sqrt_price, liquidity, tick)` state instead of the current pool state, without mutating the pool.
+    /// The tick bitmap and ticks of the pool are used to cross initialized ticks.
+    pub fn simulate_swap_at(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        state: (U256, u128, i32),
+    ) -> Result<U256, SwapSimulationError> {
+        let (_, amount_out, _) =
+            self.simulate_swap_traced(token_in, amount_in, state, None, None)?;
+
+        Ok(amount_out)
+    }
+
+    /// Simulates a swap that stops once the price reaches `sqrt_price_limit_x_96`, as the `sqrtPriceLimitX96` argument of the pool `swap`.
+    /// Returns the amount of `token_in` consumed, which is less than `amount_in` if the limit was reached, and the amount out.
+    /// The limit must be below the current price and above `MIN_SQRT_RATIO` when swapping token a, above the current price and below `MAX_SQRT_RATIO` when swapping token b.
+    pub fn simulate_swap_with_limit(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        sqrt_price_limit_x_96: U256,
+    ) -> Result<(U256, U256), SwapSimulationError> {
+        let (amount_in, amount_out, _) = self.simulate_swap_traced(
+            token_in,
+            amount_in,
+            (self.sqrt_price, self.liquidity, self.tick),
+            Some(sqrt_price_limit_x_96),
+            None,
+        )?;
+
+        Ok((amount_in, amount_out))
+    }
+
+    /// Simulates a swap from the current pool state like `simulate_swap`, also returning a record of each step of the swap.
+    /// A step ends at the next initialized tick (or the end of a bitmap word), so the records show where liquidity changes and how much of the input each range consumed.
+    pub fn simulate_swap_verbose(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<(U256, Vec<SwapStep>), SwapSimulationError> {
+        let mut steps = vec![];
+        let (_, amount_out, _) = self.simulate_swap_traced(
+            token_in,
+            amount_in,
+            (self.sqrt_price, self.liquidity, self.tick),
+            None,
+            Some(&mut steps),
+        )?;
+
+        Ok((amount_out, steps))
+    }
+
+    /// Returns a rough estimate of the gas used by swapping `amount_in` of `token_in`, the cost of a swap within one tick range plus `TICK_CROSS_GAS_ESTIMATE` for each initialized tick the swap crosses.
+    pub fn swap_gas_estimate_for(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+    ) -> Result<u64, SwapSimulationError> {
+        let (_, steps) = self.simulate_swap_verbose(token_in, amount_in)?;
+        let ticks_crossed = steps
+            .iter()
+            .filter(|step| step.initialized_tick_crossed)
+            .count() as u64;
+
+        Ok(SWAP_GAS_ESTIMATE + ticks_crossed * TICK_CROSS_GAS_ESTIMATE)
+    }
+
+    //Swap loop shared by the swap simulations, stopping at `sqrt_price_limit_x_96` if set and pushing a record of each step to `trace` when provided.
+    //Returns the amount in consumed, the amount out and the `(sqrt_price, liquidity, tick)` state of the pool after the swap
+    fn simulate_swap_traced(
+        &self,
+        token_in: H160,
+        amount_in: U256,
+        state: (U256, u128, i32),
+        sqrt_price_limit_x_96: Option<U256>,
+        mut trace: Option<&mut Vec<SwapStep>>,
+    ) -> Result<(U256, U256, (U256, u128, i32)), SwapSimulationError> {
+        let (sqrt_price, liquidity, tick) = state;
+
+        if token_in != self.token_a && token_in != self.token_b {
+            return Err(SwapSimulationError::InvalidToken(token_in));
+        }
+
+        let zero_for_one = token_in == self.token_a;
+
+        //Without a limit, set sqrt_price_limit_x_96 to the max or min sqrt price in the pool depending on zero_for_one
+        let sqrt_price_limit_x_96 = match sqrt_price_limit_x_96 {
+            Some(limit) => {
+                //Same checks as the pool, which reverts with `SPL` otherwise
+                let valid = if zero_for_one {
+                    limit < sqrt_price && limit > MIN_SQRT_RATIO
+                } else {
+                    limit > sqrt_price && limit < MAX_SQRT_RATIO
+                };
+
+                if !valid {
+                    return Err(SwapSimulationError::InvalidSqrtPriceLimit(limit));
+                }
+
+                limit
+            }
+            None if zero_for_one => MIN_SQRT_RATIO + 1,
+            None => MAX_SQRT_RATIO - 1,
+        };
+
+        //Initialize a mutable state state struct to hold the dynamic simulated state of the pool, a zero amount in leaves it untouched
+        let mut current_state = CurrentState {
+            sqrt_price_x_96: sqrt_price,     //Active price on the pool
+            amount_calculated: I256::zero(), //Amount of token_out that has been calculated
+            amount_specified_remaining: I256::from_raw(amount_in), //Amount of token_in that has not been swapped
+            tick,      //Current i24 tick of the pool
+            liquidity, //Current available liquidity in the tick range
+        };
+
+        while current_state.amount_specified_remaining != I256::zero()
+            && current_state.sqrt_price_x_96 != sqrt_price_limit_x_96
+        {
+            //Initialize a new step struct to hold the dynamic state of the pool at each step
+            let mut step = StepComputations {
+                sqrt_price_start_x_96: current_state.sqrt_price_x_96, //Set the sqrt_price_start_x_96 to the current sqrt_price_x_96
+                ..Default::default()
+            };
+
+            //Get the next tick from the current tick
+            (step.tick_next, step.initialized) =
+                uniswap_v3_math::tick_bitmap::next_initialized_tick_within_one_word(
+                    &self.tick_bitmap,
+                    current_state.tick,
+                    self.tick_spacing,
+                    zero_for_one,
+                )?;
+
+            // ensure that we do not overshoot the min/max tick, as the tick bitmap is not aware of these bounds
+            //Note: this could be removed as we are clamping in the batch contract
+            step.tick_next = step.tick_next.clamp(MIN_TICK, MAX_TICK);
+
+            //Get the next sqrt price from the input amount
+            step.sqrt_price_next_x96 =
+                uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(step.tick_next)?;
+
+            //Target spot price
+            let swap_target_sqrt_ratio = if zero_for_one {
+                if step.sqrt_price_next_x96 < sqrt_price_limit_x_96 {
+                    sqrt_price_limit_x_96
+                } else {
+                    step.sqrt_price_next_x96
+                }
+            } else if step.sqrt_price_next_x96 > sqrt_price_limit_x_96 {
+                sqrt_price_limit_x_96
+            } else {
+                step.sqrt_price_next_x96
+            };
+
+            let tick_start = current_state.tick;
+            let liquidity_start = current_state.liquidity;
+            let mut initialized_tick_crossed = false;
+
+            //Compute swap step and update the current state
+            (
+                current_state.sqrt_price_x_96,
+                step.amount_in,
+                step.amount_out,
+                step.fee_amount,
+            ) = uniswap_v3_math::swap_math::compute_swap_step(
+                current_state.sqrt_price_x_96,
+                swap_target_sqrt_ratio,
+                current_state.liquidity,
+                current_state.amount_specified_remaining,
+                self.fee,
+            )?;
+
+            //Decrement the amount remaining to be swapped and amount received from the step
+            current_state.amount_specified_remaining = current_state
+                .amount_specified_remaining
+                .overflowing_sub(I256::from_raw(
+                    step.amount_in.overflowing_add(step.fee_amount).0,
+                ))
+                .0;
+
+            current_state.amount_calculated -= I256::from_raw(step.amount_out);
+
+            //If the price moved all the way to the next price, recompute the liquidity change for the next iteration
+            if current_state.sqrt_price_x_96 == step.sqrt_price_next_x96 {
+                if step.initialized {
+                    initialized_tick_crossed = true;
+                    let mut liquidity_net = if let Some(info) = self.ticks.get(&step.tick_next) {
+                        info.liquidity_net
+                    } else {
+                        0
+                    };
+
+                    // we are on a tick boundary, and the next tick is initialized, so we must charge a protocol fee
+                    if zero_for_one {
+                        liquidity_net = -liquidity_net;
+                    }
+
+                    current_state.liquidity = if liquidity_net < 0 {
+                        if current_state.liquidity < (-liquidity_net as u128) {
+                            return Err(SwapSimulationError::LiquidityUnderflow);
+                        } else {
+                            current_state.liquidity - (-liquidity_net as u128)
+                        }
+                    } else {
+                        current_state.liquidity + (liquidity_net as u128)
+                    };
+                }
+                //Increment the current tick
+                current_state.tick = if zero_for_one {
+                    step.tick_next.wrapping_sub(1)
+                } else {
+                    step.tick_next
+                }
+                //If the current_state sqrt price is not equal to the step sqrt price, then we are not on the same tick.
+                //Update the current_state.tick to the tick at the current_state.sqrt_price_x_96
+            } else if current_state.sqrt_price_x_96 != step.sqrt_price_start_x_96 {
+                current_state.tick = uniswap_v3_math::tick_math::get_tick_at_sqrt_ratio(
+                    current_state.sqrt_price_x_96,
+                )?;
+            }
+
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.push(SwapStep {
+                    tick_start,
+                    tick_end: current_state.tick,
+                    sqrt_price_start_x_96: step.sqrt_price_start_x_96,
+                    sqrt_price_end_x_96: current_state.sqrt_price_x_96,
+                    liquidity: liquidity_start,
+                    amount_in: step.amount_in,
+                    fee_amount: step.fee_amount,
+                    amount_out: step.amount_out,
+                    initialized_tick_crossed,
+                });
+            }
+        }
 
-            (reserve_x, reserve_y)
-        } else {
-            (BigFloat::from(0), BigFloat::from(0))
-        };
+        let amount_in_consumed = amount_in - current_state.amount_specified_remaining.into_raw();
 
         Ok((
-            reserve_0
-                .to_u128()
-                .ok_or(ArithmeticError::U128ConversionError)?,
-            reserve_1
-                .to_u128()
-                .ok_or(ArithmeticError::U128ConversionError)?,
+            amount_in_consumed,
+            (-current_state.amount_calculated).into_raw(),
+            (
+                current_state.sqrt_price_x_96,
+                current_state.liquidity,
+                current_state.tick,
+            ),
         ))
     }
 
@@ -1089,6 +1419,25 @@ pub struct StepComputations {
     pub fee_amount: U256,
 }
 
+/// Record of a single step of a simulated V3 swap, see `UniswapV3Pool::simulate_swap_verbose`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SwapStep {
+    /// Tick of the pool before the step
+    pub tick_start: i32,
+    /// Tick of the pool after the step, past the next tick if it was crossed
+    pub tick_end: i32,
+    pub sqrt_price_start_x_96: U256,
+    pub sqrt_price_end_x_96: U256,
+    /// Liquidity active during the step, before crossing the next tick
+    pub liquidity: u128,
+    /// Amount of the input token consumed by the step, excluding the fee
+    pub amount_in: U256,
+    pub fee_amount: U256,
+    pub amount_out: U256,
+    /// True if the step ended by crossing an initialized tick, changing the active liquidity
+    pub initialized_tick_crossed: bool,
+}
+
 const MIN_TICK: i32 = -887272;
 const MAX_TICK: i32 = 887272;
 
@@ -1108,9 +1457,9 @@ mod test {
     use super::IUniswapV3Pool;
     #[allow(unused)]
     #[allow(unused)]
-    use super::UniswapV3Pool;
+    use super::{batch_request, UniswapV3Pool, SWAP_GAS_ESTIMATE, TICK_CROSS_GAS_ESTIMATE};
 
-    use crate::amm::AutomatedMarketMaker;
+    use crate::amm::{call_options::CallOptions, AutomatedMarketMaker};
 
     #[allow(unused)]
     use ethers::providers::Middleware;
@@ -1862,6 +2211,36 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_from_address() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let pool = UniswapV3Pool::from_address(
+            H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640")?,
+            middleware,
+        )
+        .await?;
+
+        assert_eq!(
+            pool.token_a,
+            H160::from_str("0xa0b86991c6218b36c1d19d4a2e9eb0ce3606eb48")?
+        );
+        assert_eq!(pool.token_a_decimals, 6);
+        assert_eq!(
+            pool.token_b,
+            H160::from_str("0xc02aaa39b223fe8d0a0e5c4f27ead9083c756cc2")?
+        );
+        assert_eq!(pool.token_b_decimals, 18);
+        assert_eq!(pool.fee, 500);
+        assert_eq!(pool.tick_spacing, 10);
+        assert!(!pool.sqrt_price.is_zero());
+        assert!(pool.last_active_at_block.is_some());
+        assert!(pool.ticks.is_empty());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_get_pool_data() -> eyre::Result<()> {
         let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
@@ -1886,6 +2265,22 @@ mod test {
         assert!(pool.tick != 0);
         assert_eq!(pool.tick_spacing, 10);
 
+        //The fee growth is only read when requested
+        assert!(pool.fee_growth_global_0_x_128.is_none());
+        let mut pool_with_fee_growth = UniswapV3Pool {
+            address: pool.address,
+            ..Default::default()
+        };
+        batch_request::get_v3_pool_data_batch_request(
+            &mut pool_with_fee_growth,
+            None,
+            &CallOptions::new().fee_growth_globals(true),
+            middleware,
+        )
+        .await?;
+        assert!(pool_with_fee_growth.fee_growth_global_0_x_128.is_some());
+        assert!(pool_with_fee_growth.fee_growth_global_1_x_128.is_some());
+
         Ok(())
     }
 
@@ -1960,9 +2355,509 @@ mod test {
         let float_price_a = pool.calculate_price(pool.token_a)?;
         let float_price_b = pool.calculate_price(pool.token_b)?;
 
-        assert_eq!(float_price_a, 0.0006081236083117488);
-        assert_eq!(float_price_b, 1644.4025299004006);
+        //The tick derived price 1.0001^tick is within one tick of the exact price
+        assert!((float_price_a - 0.0006081236083117488).abs() / float_price_a < 0.0001);
+        assert!((float_price_b - 1644.4025299004006).abs() / float_price_b < 0.0001);
+        assert!((float_price_a * float_price_b - 1.0).abs() < 1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_calculate_price_from_sqrt_price() -> eyre::Result<()> {
+        let mut pool = UniswapV3Pool {
+            token_a: H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?,
+            token_a_decimals: 6,
+            token_b: H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?,
+            token_b_decimals: 18,
+            sqrt_price: U256::from_dec_str("1951641336426498580326153227845296")?,
+            ..Default::default()
+        };
+
+        let price_a = pool.calculate_price(pool.token_a)?;
+        let price_b = pool.calculate_price(pool.token_b)?;
+
+        // (sqrtPriceX96 / 2^96)^2 * 10^(6 - 18)
+        assert!((price_a - 0.0006067934003006841626).abs() / price_a < 1e-12);
+        assert!((price_b - 1648.0073769827923010644).abs() / price_b < 1e-12);
+
+        // 1.5^2 with equal decimals
+        pool.token_b_decimals = 6;
+        pool.sqrt_price = U256::from(3) * U256::from(super::Q96) / 2;
+
+        assert_eq!(pool.calculate_price(pool.token_a)?, 2.25);
+        assert!((pool.calculate_price(pool.token_b)? - 1.0 / 2.25).abs() < 1e-15);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_swap_at() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let pool = UniswapV3Pool {
+            token_a,
+            token_a_decimals: 6,
+            token_b,
+            token_b_decimals: 18,
+            liquidity: 1000000000000000000,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0)?,
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+
+        let historical = UniswapV3Pool {
+            liquidity: 2000000000000000000,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(-120)?,
+            tick: -120,
+            ..pool.clone()
+        };
+
+        let amount_in = U256::from(1000000000000_u128);
+        let state = (historical.sqrt_price, historical.liquidity, historical.tick);
+
+        assert_eq!(
+            pool.simulate_swap_at(token_a, amount_in, state)?,
+            historical.simulate_swap(token_a, amount_in)?
+        );
+        assert_eq!(
+            pool.simulate_swap_at(token_b, amount_in, state)?,
+            historical.simulate_swap(token_b, amount_in)?
+        );
+
+        // The pool state is left untouched
+        assert_eq!(pool.tick, 0);
+        assert_eq!(pool.liquidity, 1000000000000000000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_swap_verbose() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let mut pool = UniswapV3Pool {
+            token_a,
+            token_b,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0)?,
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+
+        //Twice the liquidity below tick 120 than above it
+        pool.add_liquidity(0, 120, 1000000000000000000)?;
+        pool.add_liquidity(0, 240, 1000000000000000000)?;
+
+        let amount_in = U256::from(20000000000000000_u128);
+        let (amount_out, steps) = pool.simulate_swap_verbose(token_b, amount_in)?;
+
+        assert_eq!(amount_out, pool.simulate_swap(token_b, amount_in)?);
+
+        //The swap crosses tick 120 and stops before tick 240
+        assert_eq!(steps.len(), 2);
+        assert_eq!((steps[0].tick_start, steps[0].tick_end), (0, 120));
+        assert_eq!(steps[0].liquidity, 2000000000000000000);
+        assert_eq!(steps[1].tick_start, 120);
+        assert!(steps[1].tick_end > 120 && steps[1].tick_end < 240);
+        assert_eq!(steps[1].liquidity, 1000000000000000000);
+        assert_eq!(steps[1].sqrt_price_start_x_96, steps[0].sqrt_price_end_x_96);
+
+        //The steps account for the whole input and output
+        let consumed = steps
+            .iter()
+            .fold(U256::zero(), |acc, step| acc + step.amount_in + step.fee_amount);
+        let received = steps
+            .iter()
+            .fold(U256::zero(), |acc, step| acc + step.amount_out);
+        assert_eq!(consumed, amount_in);
+        assert_eq!(received, amount_out);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_compute_address() -> eyre::Result<()> {
+        let usdc = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let weth = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        assert_eq!(
+            UniswapV3Pool::compute_address(
+                super::UNISWAP_V3_FACTORY,
+                usdc,
+                weth,
+                500,
+                super::UNISWAP_V3_POOL_INIT_CODE_HASH
+            ),
+            H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640")?
+        );
+
+        //Token order does not matter
+        assert_eq!(
+            UniswapV3Pool::compute_address(
+                super::UNISWAP_V3_FACTORY,
+                weth,
+                usdc,
+                3000,
+                super::UNISWAP_V3_POOL_INIT_CODE_HASH
+            ),
+            H160::from_str("0x8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8")?
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_swap_with_limit() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let mut pool = UniswapV3Pool {
+            token_a,
+            token_b,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0)?,
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+        pool.add_liquidity(-240, 240, 1000000000000000000)?;
+
+        let amount_in = U256::from(20000000000000000_u128);
+
+        //The limit is reached before the whole input is swapped
+        let limit = uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(60)?;
+        let (consumed, amount_out) = pool.simulate_swap_with_limit(token_b, amount_in, limit)?;
+        assert!(consumed < amount_in);
+        assert!(amount_out < pool.simulate_swap(token_b, amount_in)?);
+
+        //A limit that is not reached does not change the swap
+        let amount_in = U256::from(1000000000000000_u128);
+        let limit = uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(-120)?;
+        assert_eq!(
+            pool.simulate_swap_with_limit(token_a, amount_in, limit)?,
+            (amount_in, pool.simulate_swap(token_a, amount_in)?)
+        );
+
+        //The limit must be on the side the price moves to
+        assert!(matches!(
+            pool.simulate_swap_with_limit(token_a, amount_in, pool.sqrt_price + 1),
+            Err(crate::errors::SwapSimulationError::InvalidSqrtPriceLimit(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate_swap_invalid_token() -> eyre::Result<()> {
+        let mut pool = UniswapV3Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_b: H160::from_low_u64_be(2),
+            liquidity: 1000000000000000000,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0)?,
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+
+        let token = H160::from_low_u64_be(3);
+        let amount_in = U256::from(1000000000000_u128);
+
+        assert!(matches!(
+            pool.simulate_swap(token, amount_in),
+            Err(crate::errors::SwapSimulationError::InvalidToken(t)) if t == token
+        ));
+        assert!(matches!(
+            pool.simulate_swap_mut(token, amount_in),
+            Err(crate::errors::SwapSimulationError::InvalidToken(t)) if t == token
+        ));
+        assert_eq!(pool.tick, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_and_remove_liquidity() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let mut pool = UniswapV3Pool {
+            token_a,
+            token_b,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0)?,
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+
+        //The range includes the current tick
+        pool.add_liquidity(0, 120, 1000000000000000000)?;
+        assert_eq!(pool.liquidity, 1000000000000000000);
+        assert_eq!(pool.ticks[&0].liquidity_net, 1000000000000000000);
+        assert_eq!(pool.ticks[&120].liquidity_net, -1000000000000000000);
+
+        //The range is above the current tick
+        pool.add_liquidity(120, 240, 1000000000000000000)?;
+        assert_eq!(pool.liquidity, 1000000000000000000);
+        assert_eq!(pool.ticks[&120].liquidity_net, 0);
+        assert_eq!(pool.ticks[&120].liquidity_gross, 2000000000000000000);
+
+        //Swaps can cross into the added range
+        let mut swapped_pool = pool.clone();
+        swapped_pool.simulate_swap_mut(token_b, U256::from(10000000000000000_u128))?;
+        assert!(swapped_pool.tick > 0);
+
+        //Invalid ranges and removing more than was added are rejected without modifying the pool
+        assert!(pool.add_liquidity(120, 0, 1).is_err());
+        assert!(pool.add_liquidity(0, 100, 1).is_err());
+        assert!(pool.remove_liquidity(0, 120, 2000000000000000000).is_err());
+        assert_eq!(pool.liquidity, 1000000000000000000);
+
+        pool.remove_liquidity(0, 120, 1000000000000000000)?;
+        pool.remove_liquidity(120, 240, 1000000000000000000)?;
+
+        assert_eq!(pool.liquidity, 0);
+        assert!(pool.ticks.is_empty());
+        assert!(pool.tick_bitmap.values().all(|word| word.is_zero()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_swap_gas_estimate_for() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let mut pool = UniswapV3Pool {
+            token_a,
+            token_b,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0)?,
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+        pool.add_liquidity(0, 120, 1000000000000000000)?;
+        pool.add_liquidity(0, 240, 1000000000000000000)?;
+
+        //A small swap stays within the current range
+        assert_eq!(
+            pool.swap_gas_estimate_for(token_b, U256::from(1000000000000000_u128))?,
+            SWAP_GAS_ESTIMATE
+        );
+        assert_eq!(pool.swap_gas_estimate(), SWAP_GAS_ESTIMATE);
+
+        //A larger swap crosses tick 120
+        let estimate = pool.swap_gas_estimate_for(token_b, U256::from(20000000000000000_u128))?;
+        assert!(estimate > SWAP_GAS_ESTIMATE);
+        assert_eq!(estimate, SWAP_GAS_ESTIMATE + TICK_CROSS_GAS_ESTIMATE);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fees_accrued_since() {
+        let earlier = UniswapV3Pool {
+            liquidity: 1000000000000000000,
+            fee_growth_global_0_x_128: Some(U256::one() << 128),
+            fee_growth_global_1_x_128: Some(U256::MAX),
+            ..Default::default()
+        };
+        //3 units of token a and 2 units of token b per unit of liquidity, the token b growth wraps around
+        let pool = UniswapV3Pool {
+            fee_growth_global_0_x_128: Some(U256::from(4) << 128),
+            fee_growth_global_1_x_128: Some((U256::from(2) << 128) - 1),
+            ..earlier.clone()
+        };
+
+        assert_eq!(
+            pool.fees_accrued_since(&earlier),
+            Some((
+                U256::from(3000000000000000000_u128),
+                U256::from(2000000000000000000_u128)
+            ))
+        );
+
+        //Fee growth that was never read
+        assert_eq!(pool.fees_accrued_since(&UniswapV3Pool::default()), None);
+    }
+
+    #[test]
+    fn test_depth_within() -> eyre::Result<()> {
+        let token_a = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let token_b = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+
+        let mut pool = UniswapV3Pool {
+            token_a,
+            token_b,
+            sqrt_price: uniswap_v3_math::tick_math::get_sqrt_ratio_at_tick(0)?,
+            fee: 3000,
+            tick: 0,
+            tick_spacing: 60,
+            ..Default::default()
+        };
+        pool.add_liquidity(-600, 600, 1000000000000000000)?;
+
+        //Swapping the depth moves the price by the band
+        for token_in in [token_a, token_b] {
+            let depth = pool.depth_within(100, token_in)?;
+            assert!(!depth.is_zero());
+
+            let mut swapped_pool = pool.clone();
+            swapped_pool.simulate_swap_mut(token_in, depth)?;
+
+            let price_ratio =
+                swapped_pool.calculate_price(token_in)? / pool.calculate_price(token_in)?;
+            assert!((price_ratio - 0.99).abs() < 1e-6);
+        }
+
+        //There is no liquidity past tick -600, about 6% below the current price
+        let depth = pool.depth_within(1000, token_a)?;
+        assert!(depth > pool.depth_within(100, token_a)?);
+        assert_eq!(depth, pool.depth_within(2000, token_a)?);
+
+        //Uninitialized pool
+        let empty_pool = UniswapV3Pool {
+            token_a,
+            token_b,
+            ..Default::default()
+        };
+        assert_eq!(empty_pool.depth_within(100, token_a)?, U256::zero());
+        assert_eq!(pool.depth_within(0, token_a)?, U256::zero());
+
+        let invalid_token = H160::from_low_u64_be(1);
+        assert!(matches!(
+            pool.depth_within(100, invalid_token),
+            Err(crate::errors::SwapSimulationError::InvalidToken(token)) if token == invalid_token
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_calculate_price_varying_decimals() -> eyre::Result<()> {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+
+        for token_a_decimals in [0_u8, 2, 6, 8, 18] {
+            for token_b_decimals in [0_u8, 2, 6, 8, 18] {
+                //Price of 2 token b per token a, ie. a raw price of 2 * 10^(b - a)
+                let raw_price =
+                    2.0 * 10_f64.powi(token_b_decimals as i32 - token_a_decimals as i32);
+
+                let pool = UniswapV3Pool {
+                    token_a,
+                    token_a_decimals,
+                    token_b,
+                    token_b_decimals,
+                    sqrt_price: U256::from((raw_price.sqrt() * 2_f64.powi(96)) as u128),
+                    ..Default::default()
+                };
+
+                assert!((pool.calculate_price(token_a)? - 2.0).abs() < 1e-9);
+                assert!((pool.calculate_price(token_b)? - 0.5).abs() < 1e-9);
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_calculate_price_both() -> eyre::Result<()> {
+        let token_a = H160::from_low_u64_be(1);
+        let token_b = H160::from_low_u64_be(2);
+
+        //Price of 4 token b per token a, with a decimal gap of 18
+        let pool = UniswapV3Pool {
+            token_a,
+            token_a_decimals: 0,
+            token_b,
+            token_b_decimals: 18,
+            sqrt_price: U256::from(2000000000_u64) * U256::from(super::Q96),
+            ..Default::default()
+        };
+
+        let (price_a, price_b) = pool.calculate_price_both()?;
+        assert!((price_a - 4.0).abs() < 1e-12);
+        assert!((price_b - 0.25).abs() < 1e-12);
+
+        assert_eq!(price_a, pool.calculate_price(token_a)?);
+        assert!((price_b - pool.calculate_price(token_b)?).abs() < 1e-15);
+
+        //Uninitialized pool
+        let pool = UniswapV3Pool {
+            token_a,
+            token_b,
+            ..Default::default()
+        };
+        assert!(pool.calculate_price(token_a).is_err());
+        assert!(pool.calculate_price(token_b).is_err());
+        assert!(pool.calculate_price_both().is_err());
 
         Ok(())
     }
+
+    #[test]
+    fn test_shift_decimals() {
+        //Powers of ten past 10^22 are not exact as f64
+        let price = num_bigfloat::BigFloat::from(3);
+
+        assert_eq!(
+            super::shift_decimals(price, 30),
+            num_bigfloat::BigFloat::parse("3e30").unwrap()
+        );
+        assert_eq!(
+            super::shift_decimals(price, -30),
+            num_bigfloat::BigFloat::parse("3e-30").unwrap()
+        );
+        assert_eq!(super::shift_decimals(price, 0), price);
+    }
+
+    #[test]
+    fn test_fee_bps() {
+        let mut pool = UniswapV3Pool::default();
+
+        for (fee, fee_bps) in [(100, 1), (500, 5), (3000, 30), (10000, 100)] {
+            pool.fee = fee;
+            assert_eq!(pool.fee_bps(), fee_bps);
+        }
+    }
+
+    #[test]
+    fn test_virtual_reserves() {
+        let mut pool = UniswapV3Pool {
+            liquidity: 1000000000000000000,
+            sqrt_price: U256::from(super::Q96),
+            ..Default::default()
+        };
+
+        //Price of 1
+        assert_eq!(
+            pool.virtual_reserves(),
+            (
+                U256::from(1000000000000000000_u128),
+                U256::from(1000000000000000000_u128)
+            )
+        );
+
+        //Price of 4
+        pool.sqrt_price = U256::from(super::Q96) * U256::from(2);
+        assert_eq!(
+            pool.virtual_reserves(),
+            (
+                U256::from(500000000000000000_u128),
+                U256::from(2000000000000000000_u128)
+            )
+        );
+
+        pool.sqrt_price = U256::zero();
+        assert_eq!(pool.virtual_reserves(), (U256::zero(), U256::zero()));
+    }
 }
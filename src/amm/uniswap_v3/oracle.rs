@@ -0,0 +1,257 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ethers::{
+    abi::Token,
+    contract::Multicall,
+    providers::Middleware,
+    types::{H160, I256},
+};
+use num_bigfloat::BigFloat;
+
+use crate::errors::AMMError;
+
+use super::{shift_decimals, IUniswapV3Pool, UniswapV3Pool};
+
+const TWAPS_MULTICALL_STEP: usize = 100;
+
+/// Returns the arithmetic mean tick between two tick cumulatives observed `window_secs` apart, rounded towards negative infinity as in the Uniswap V3 `OracleLibrary`.
+pub fn arithmetic_mean_tick(
+    tick_cumulative_start: i64,
+    tick_cumulative_end: i64,
+    window_secs: u32,
+) -> i32 {
+    let tick_cumulative_delta = tick_cumulative_end - tick_cumulative_start;
+    let window_secs = window_secs as i64;
+
+    let mut mean_tick = tick_cumulative_delta / window_secs;
+    if tick_cumulative_delta < 0 && tick_cumulative_delta % window_secs != 0 {
+        mean_tick -= 1;
+    }
+
+    mean_tick as i32
+}
+
+/// Returns the TWAP of token_a denominated in token_b over the last `window_secs` seconds for each pool, see `UniswapV3Pool::twap`.
+/// The `observe` calls are aggregated with Multicall3. Pools that do not have observations going back `window_secs` are left out of the map.
+pub async fn twaps<M: Middleware>(
+    pools: &[&UniswapV3Pool],
+    window_secs: u32,
+    middleware: Arc<M>,
+) -> Result<HashMap<H160, f64>, AMMError<M>> {
+    if window_secs == 0 {
+        return Err(AMMError::InvalidTwapWindow);
+    }
+
+    let mut twaps = HashMap::new();
+    let mut multicall = Multicall::new(middleware.clone(), None)
+        .await
+        .map_err(AMMError::MulticallError)?;
+
+    for pool_chunk in pools.chunks(TWAPS_MULTICALL_STEP) {
+        multicall.clear_calls();
+        for pool in pool_chunk {
+            let v3_pool = IUniswapV3Pool::new(pool.address, middleware.clone());
+            multicall.add_call(v3_pool.observe(vec![window_secs, 0]), true);
+        }
+
+        let results = multicall
+            .call_raw()
+            .await
+            .map_err(AMMError::MulticallError)?;
+
+        for (pool, result) in pool_chunk.iter().zip(results) {
+            //The call reverts if the observation cardinality does not cover the window
+            if let Ok(return_data) = result {
+                if let Some(mean_tick) = mean_tick_from_observe(return_data, window_secs) {
+                    twaps.insert(pool.address, pool.price_at_tick(mean_tick, pool.token_a));
+                }
+            }
+        }
+    }
+
+    Ok(twaps)
+}
+
+//Decodes the tick cumulatives of an `observe([window_secs, 0])` call into the arithmetic mean tick
+fn mean_tick_from_observe(return_data: Token, window_secs: u32) -> Option<i32> {
+    let tick_cumulatives = return_data.into_tuple()?.first()?.to_owned().into_array()?;
+
+    if tick_cumulatives.len() != 2 {
+        return None;
+    }
+
+    let tick_cumulative_start = I256::from_raw(tick_cumulatives[0].to_owned().into_int()?).as_i64();
+    let tick_cumulative_end = I256::from_raw(tick_cumulatives[1].to_owned().into_int()?).as_i64();
+
+    Some(arithmetic_mean_tick(
+        tick_cumulative_start,
+        tick_cumulative_end,
+        window_secs,
+    ))
+}
+
+impl UniswapV3Pool {
+    /// Returns the time weighted average price of token_a denominated in token_b over the last `window_secs` seconds, read from the pool oracle observations.
+    /// Fails if the pool does not have observations going back `window_secs`.
+    pub async fn twap<M: Middleware>(
+        &self,
+        window_secs: u32,
+        middleware: Arc<M>,
+    ) -> Result<f64, AMMError<M>> {
+        let mean_tick = self.get_arithmetic_mean_tick(window_secs, middleware).await?;
+
+        Ok(self.price_at_tick(mean_tick, self.token_a))
+    }
+
+    /// Returns the arithmetic mean tick over the last `window_secs` seconds by calling `observe` on the pool.
+    pub async fn get_arithmetic_mean_tick<M: Middleware>(
+        &self,
+        window_secs: u32,
+        middleware: Arc<M>,
+    ) -> Result<i32, AMMError<M>> {
+        if window_secs == 0 {
+            return Err(AMMError::InvalidTwapWindow);
+        }
+
+        let v3_pool = IUniswapV3Pool::new(self.address, middleware);
+        let (tick_cumulatives, _) = v3_pool
+            .observe(vec![window_secs, 0])
+            .call()
+            .await
+            .map_err(|e| AMMError::ContractError("get_arithmetic_mean_tick", self.address, e))?;
+
+        if tick_cumulatives.len() != 2 {
+            return Err(AMMError::BatchRequestError(self.address));
+        }
+
+        Ok(arithmetic_mean_tick(
+            tick_cumulatives[0],
+            tick_cumulatives[1],
+            window_secs,
+        ))
+    }
+
+    /// Returns the price of `base_token` at `tick`, adjusted for the decimals of each token.
+    pub fn price_at_tick(&self, tick: i32, base_token: H160) -> f64 {
+        //Price of token_a denominated in token_b, 1.0001^tick scaled as in `calculate_price`
+        let tick_base = BigFloat::from(10001).div(&BigFloat::from(10000));
+        let price = shift_decimals(
+            tick_base.pow(&BigFloat::from(tick)),
+            self.token_a_decimals as i32 - self.token_b_decimals as i32,
+        );
+
+        if base_token == self.token_a {
+            price.to_f64()
+        } else {
+            BigFloat::from(1).div(&price).to_f64()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        abi::Token,
+        providers::{Http, Provider},
+        types::{H160, I256, U256},
+    };
+
+    use crate::amm::{uniswap_v3::UniswapV3Pool, AutomatedMarketMaker};
+
+    use super::{arithmetic_mean_tick, mean_tick_from_observe, twaps};
+
+    #[test]
+    fn test_arithmetic_mean_tick() {
+        assert_eq!(arithmetic_mean_tick(0, 6000, 60), 100);
+        assert_eq!(arithmetic_mean_tick(6000, 0, 60), -100);
+        //Negative means round towards negative infinity
+        assert_eq!(arithmetic_mean_tick(0, -6001, 60), -101);
+        assert_eq!(arithmetic_mean_tick(0, 6001, 60), 100);
+    }
+
+    #[test]
+    fn test_price_at_tick() {
+        let pool = UniswapV3Pool {
+            token_a: H160::from_low_u64_be(1),
+            token_a_decimals: 6,
+            token_b: H160::from_low_u64_be(2),
+            token_b_decimals: 18,
+            ..Default::default()
+        };
+
+        let price = pool.price_at_tick(0, pool.token_a);
+        assert!((price - 1e-12).abs() < 1e-24);
+
+        let price = pool.price_at_tick(6932, pool.token_b);
+        assert!((price / (1e12 / (6932.0 * 0.0001_f64.ln_1p()).exp()) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_mean_tick_from_observe() {
+        let return_data = Token::Tuple(vec![
+            Token::Array(vec![
+                Token::Int(I256::from(-6000).into_raw()),
+                Token::Int(I256::from(0).into_raw()),
+            ]),
+            Token::Array(vec![Token::Uint(U256::zero()), Token::Uint(U256::zero())]),
+        ]);
+
+        assert_eq!(mean_tick_from_observe(return_data, 60), Some(100));
+        assert_eq!(mean_tick_from_observe(Token::Bool(true), 60), None);
+    }
+
+    #[tokio::test]
+    async fn test_twap() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //USDC/WETH 0.05% pool
+        let mut pool = UniswapV3Pool {
+            address: H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640")?,
+            ..Default::default()
+        };
+        pool.populate_data(None, middleware.clone()).await?;
+
+        let twap = pool.twap(600, middleware.clone()).await?;
+        let spot_price = pool.calculate_price(pool.token_a)?;
+
+        //The 10 minute average should be close to the spot price for a deep pool
+        assert!((twap / spot_price - 1.0).abs() < 0.05);
+
+        assert!(pool.twap(0, middleware).await.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_twaps() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //USDC/WETH 0.05% and 0.3% pools
+        let mut pools = vec![];
+        for address in [
+            "0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640",
+            "0x8ad599c3A0ff1De082011EFDDc58f1908eb6e6D8",
+        ] {
+            let mut pool = UniswapV3Pool {
+                address: H160::from_str(address)?,
+                ..Default::default()
+            };
+            pool.populate_data(None, middleware.clone()).await?;
+            pools.push(pool);
+        }
+
+        let twaps = twaps(&pools.iter().collect::<Vec<_>>(), 600, middleware.clone()).await?;
+
+        for pool in pools.iter() {
+            let twap = pool.twap(600, middleware.clone()).await?;
+            let batched_twap = twaps.get(&pool.address).expect("Pool should have a TWAP");
+            assert!((twap / batched_twap - 1.0).abs() < 1e-3);
+        }
+
+        Ok(())
+    }
+}
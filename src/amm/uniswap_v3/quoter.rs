@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use ethers::{
+    providers::Middleware,
+    types::{H160, U256},
+};
+
+use crate::errors::AMMError;
+
+use super::UniswapV3Pool;
+
+use ethers::prelude::abigen;
+
+abigen!(
+    IQuoterV2,
+    r#"[
+        struct QuoteExactInputSingleParams { address tokenIn; address tokenOut; uint256 amountIn; uint24 fee; uint160 sqrtPriceLimitX96; }
+        struct QuoteExactOutputSingleParams { address tokenIn; address tokenOut; uint256 amount; uint24 fee; uint160 sqrtPriceLimitX96; }
+        function quoteExactInputSingle(QuoteExactInputSingleParams memory params) external returns (uint256 amountOut, uint160 sqrtPriceX96After, uint32 initializedTicksCrossed, uint256 gasEstimate)
+        function quoteExactOutputSingle(QuoteExactOutputSingleParams memory params) external returns (uint256 amountIn, uint160 sqrtPriceX96After, uint32 initializedTicksCrossed, uint256 gasEstimate)
+    ]"#;
+);
+
+// QuoterV2 deployment on Ethereum mainnet
+pub const QUOTER_V2_ADDRESS: H160 = H160([
+    97, 255, 224, 20, 186, 23, 152, 158, 116, 60, 95, 108, 178, 27, 249, 105, 117, 48, 178, 30,
+]);
+
+/// Returns the amount of the other pool token received for `amount_in` of `token_in`, as quoted by the QuoterV2 contract at `quoter_address`.
+/// The quote is taken at `block_number`, or the latest block if `None`, so that it can be compared against `simulate_swap` on a pool synced to the same block.
+pub async fn quote_via_quoter<M: Middleware>(
+    pool: &UniswapV3Pool,
+    token_in: H160,
+    amount_in: U256,
+    quoter_address: H160,
+    block_number: Option<u64>,
+    middleware: Arc<M>,
+) -> Result<U256, AMMError<M>> {
+    let quoter = IQuoterV2::new(quoter_address, middleware);
+    let token_out = if token_in == pool.token_a {
+        pool.token_b
+    } else {
+        pool.token_a
+    };
+
+    let mut call = quoter.quote_exact_input_single(QuoteExactInputSingleParams {
+        token_in,
+        token_out,
+        amount_in,
+        fee: pool.fee,
+        sqrt_price_limit_x96: U256::zero(),
+    });
+
+    if let Some(block_number) = block_number {
+        call = call.block(block_number);
+    }
+
+    let (amount_out, _, _, _) = call
+        .call()
+        .await
+        .map_err(|e| AMMError::ContractError("quote_via_quoter", quoter_address, e))?;
+
+    Ok(amount_out)
+}
+
+/// Returns the amount of `token_in` required to receive `amount_out` of the other pool token, as quoted by the QuoterV2 contract at `quoter_address`.
+/// The quote is taken at `block_number`, or the latest block if `None`.
+pub async fn quote_exact_output_via_quoter<M: Middleware>(
+    pool: &UniswapV3Pool,
+    token_in: H160,
+    amount_out: U256,
+    quoter_address: H160,
+    block_number: Option<u64>,
+    middleware: Arc<M>,
+) -> Result<U256, AMMError<M>> {
+    let quoter = IQuoterV2::new(quoter_address, middleware);
+    let token_out = if token_in == pool.token_a {
+        pool.token_b
+    } else {
+        pool.token_a
+    };
+
+    let mut call = quoter.quote_exact_output_single(QuoteExactOutputSingleParams {
+        token_in,
+        token_out,
+        amount: amount_out,
+        fee: pool.fee,
+        sqrt_price_limit_x96: U256::zero(),
+    });
+
+    if let Some(block_number) = block_number {
+        call = call.block(block_number);
+    }
+
+    let (amount_in, _, _, _) = call
+        .call()
+        .await
+        .map_err(|e| AMMError::ContractError("quote_exact_output_via_quoter", quoter_address, e))?;
+
+    Ok(amount_in)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::{H160, U256},
+    };
+
+    use crate::amm::{uniswap_v3::UniswapV3Pool, AutomatedMarketMaker};
+
+    use super::{quote_exact_output_via_quoter, quote_via_quoter, QUOTER_V2_ADDRESS};
+
+    #[tokio::test]
+    async fn test_quote_via_quoter() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let mut pool = UniswapV3Pool {
+            address: H160::from_str("0x88e6A0c2dDD26FEEb64F039a2c41296FcB3f5640")?,
+            ..Default::default()
+        };
+
+        pool.tick_spacing = pool.get_tick_spacing(middleware.clone()).await?;
+        let synced_block = pool
+            .populate_tick_data(12369620, middleware.clone())
+            .await?;
+        pool.populate_data(Some(synced_block), middleware.clone())
+            .await?;
+
+        let amount_in = U256::from_dec_str("10000000000")?; // 10_000 USDC
+
+        let amount_out = pool.simulate_swap(pool.token_a, amount_in)?;
+        let quoted_amount_out = quote_via_quoter(
+            &pool,
+            pool.token_a,
+            amount_in,
+            QUOTER_V2_ADDRESS,
+            Some(synced_block),
+            middleware.clone(),
+        )
+        .await?;
+
+        assert_eq!(amount_out, quoted_amount_out);
+
+        //Exact output quote for the same amount out should require at most the original amount in
+        let quoted_amount_in = quote_exact_output_via_quoter(
+            &pool,
+            pool.token_a,
+            quoted_amount_out,
+            QUOTER_V2_ADDRESS,
+            Some(synced_block),
+            middleware,
+        )
+        .await?;
+
+        assert!(quoted_amount_in <= amount_in);
+
+        Ok(())
+    }
+}
@@ -12,17 +12,21 @@ use crate::{
 };
 
 pub enum DiscoverableFactory {
+    #[cfg(feature = "uniswap-v2")]
     UniswapV2Factory,
+    #[cfg(feature = "uniswap-v3")]
     UniswapV3Factory,
 }
 
 impl DiscoverableFactory {
     pub fn discovery_event_signature(&self) -> H256 {
         match self {
+            #[cfg(feature = "uniswap-v2")]
             DiscoverableFactory::UniswapV2Factory => {
                 amm::uniswap_v2::factory::PAIR_CREATED_EVENT_SIGNATURE
             }
 
+            #[cfg(feature = "uniswap-v3")]
             DiscoverableFactory::UniswapV3Factory => {
                 amm::uniswap_v3::factory::POOL_CREATED_EVENT_SIGNATURE
             }
@@ -81,13 +85,19 @@ pub async fn discover_factories<M: Middleware>(
                 let mut factory = Factory::try_from(log.topics[0])?;
 
                 match &mut factory {
+                    #[cfg(feature = "uniswap-v2")]
                     Factory::UniswapV2Factory(uniswap_v2_factory) => {
                         uniswap_v2_factory.address = log.address;
                         uniswap_v2_factory.creation_block = log
                             .block_number
                             .ok_or(AMMError::BlockNumberNotFound)?
                             .as_u64();
+                        //The fee getters of the known forks return the fee in basis points
+                        uniswap_v2_factory.fee = uniswap_v2_factory
+                            .get_fee(amm::uniswap_v2::BPS_FEE_DENOMINATOR, middleware.clone())
+                            .await?;
                     }
+                    #[cfg(feature = "uniswap-v3")]
                     Factory::UniswapV3Factory(uniswap_v3_factory) => {
                         uniswap_v3_factory.address = log.address;
                         uniswap_v3_factory.creation_block = log
@@ -1,9 +1,11 @@
-use ethers::prelude::{AbiError, ContractError};
-use ethers::providers::{Middleware, ProviderError};
+use ethers::abi::ParamType;
+use ethers::prelude::{AbiError, ContractError, MulticallError};
+use ethers::providers::{JsonRpcError, Middleware, ProviderError, RpcError};
 use ethers::types::{H160, U256, U64};
 use std::time::SystemTimeError;
 use thiserror::Error;
 use tokio::task::JoinError;
+#[cfg(feature = "uniswap-v3")]
 use uniswap_v3_math::error::UniswapV3MathError;
 
 #[derive(Error, Debug)]
@@ -15,12 +17,20 @@ where
     MiddlewareError(<M as Middleware>::Error), // [source] requires static lifetime here
     #[error("Provider error at {0} for {1:#x}")]
     ProviderError(&'static str, H160, #[source] ProviderError),
+    #[error("Batch request reverted at {0} for {1:#x}: {2}")]
+    BatchRequestReverted(&'static str, H160, String, #[source] ProviderError),
     #[error("Contract error at {0} for {1:#x}")]
     ContractError(&'static str, H160, #[source] ContractError<M>),
     #[error("ABI Codec error")]
     ABICodecError(#[from] AbiError),
     #[error("Eth ABI error")]
     EthABIError(#[from] ethers::abi::Error),
+    #[error("Could not decode return data at {context}")]
+    AbiDecodeError {
+        context: &'static str,
+        #[source]
+        source: ethers::abi::Error,
+    },
     #[error("Join error")]
     JoinError(#[from] JoinError),
     #[error("Serde json error")]
@@ -29,6 +39,7 @@ where
     IOError(#[from] std::io::Error),
     #[error("Error when converting from hex to U256")]
     FromHexError,
+    #[cfg(feature = "uniswap-v3")]
     #[error("Uniswap V3 math error")]
     UniswapV3MathError(#[from] UniswapV3MathError),
     #[error("Pair for token_a=`{0:#x}`/token_b=`{1:#x}` does not exist in provided dexes")]
@@ -49,6 +60,8 @@ where
     IncongruentAMMs,
     #[error("Invalid ERC4626 fee")]
     InvalidERC4626Fee,
+    #[error("Fee {1} of factory `{0:#x}` is not below the fee denominator")]
+    InvalidFactoryFee(H160, U256),
     #[error("Event log error")]
     EventLogError(#[from] EventLogError),
     #[error("Block number not found")]
@@ -57,8 +70,86 @@ where
     SwapSimulationError(#[from] SwapSimulationError),
     #[error("Invalid data from batch request `{0:#x}`")]
     BatchRequestError(H160),
+    #[error("Could not verify decimals of token `{0:#x}`")]
+    InvalidTokenDecimals(H160),
+    #[error("Could not find the init code hash of factory `{0:#x}`")]
+    InitCodeHashNotFound(H160),
+    #[error("Invalid reserve bit width {0}")]
+    InvalidReserveBits(u8),
+    #[error("Reserves of pool `{0:#x}` exceed {1} bits")]
+    ReserveOverflow(H160, u8),
+    #[error("TWAP window must be greater than zero")]
+    InvalidTwapWindow,
+    #[error("Multicall error")]
+    MulticallError(#[source] MulticallError<M>),
+    #[error("No middleware for chain {0}")]
+    ChainNotFound(u64),
     #[error("Checkpoint error")]
     CheckpointError(#[from] CheckpointError),
+    #[error("Could not buy token `{0:#x}` through pool `{1:#x}`")]
+    TokenBuyFailed(H160, H160),
+}
+
+impl<M: Middleware> AMMError<M> {
+    /// Wraps the error of a batch request call, decoding the revert reason when the call reverted with `Error(string)`.
+    pub fn batch_call_error(context: &'static str, address: H160, error: ProviderError) -> Self {
+        let reason = error
+            .as_error_response()
+            .and_then(|response| response.as_revert_data())
+            .and_then(|revert_data| decode_revert_reason(&revert_data));
+
+        match reason {
+            Some(reason) => AMMError::BatchRequestReverted(context, address, reason, error),
+            None => AMMError::ProviderError(context, address, error),
+        }
+    }
+
+    /// Returns true if a batch request was rejected by the node while executing it, ie. reverted, ran out of gas or exceeded the code size limit.
+    /// Such batches can usually be retried with fewer targets. Other error responses, ie. rate limits or authentication errors, are not batch rejections.
+    pub fn is_batch_rejected(&self) -> bool {
+        match self {
+            AMMError::BatchRequestReverted(..) => true,
+            AMMError::ProviderError(_, _, error) => {
+                matches!(error.as_error_response(), Some(response) if is_execution_error(response))
+            }
+            _ => false,
+        }
+    }
+}
+
+// Error code of reverts returned along with their revert data
+const EXECUTION_REVERTED_CODE: i64 = 3;
+// Messages of the errors returned by nodes when executing a call fails, matched case insensitively
+const EXECUTION_ERROR_MESSAGES: [&str; 6] = [
+    "execution reverted",
+    "out of gas",
+    "gas required exceeds allowance",
+    "exceeds block gas limit",
+    "max code size exceeded",
+    "contract creation code storage",
+];
+
+/// Returns true if the error response was caused by executing the call, as opposed to the node refusing to serve it.
+fn is_execution_error(response: &JsonRpcError) -> bool {
+    let message = response.message.to_lowercase();
+
+    response.code == EXECUTION_REVERTED_CODE
+        || EXECUTION_ERROR_MESSAGES
+            .iter()
+            .any(|execution_error| message.contains(execution_error))
+}
+
+// Selector of `Error(string)`, the revert data of `require` and `revert` with a message
+const ERROR_STRING_SELECTOR: [u8; 4] = [8, 195, 121, 160];
+
+/// Decodes the message of revert data encoded as `Error(string)`, returns `None` for any other revert data.
+pub fn decode_revert_reason(revert_data: &[u8]) -> Option<String> {
+    let encoded_reason = revert_data.strip_prefix(&ERROR_STRING_SELECTOR)?;
+
+    ethers::abi::decode(&[ParamType::String], encoded_reason)
+        .ok()?
+        .pop()?
+        .into_string()
 }
 
 #[derive(Error, Debug)]
@@ -73,10 +164,17 @@ pub enum ArithmeticError {
     SqrtPriceOverflow,
     #[error("U128 conversion error")]
     U128ConversionError,
+    #[cfg(feature = "uniswap-v3")]
     #[error("Uniswap v3 math error")]
     UniswapV3MathError(#[from] UniswapV3MathError),
     #[error("Uniswap v3 tick underflow")]
     UniswapV3TickUnderflow,
+    #[error("Invalid tick range [{0}, {1})")]
+    InvalidTickRange(i32, i32),
+    #[error("Liquidity underflow")]
+    LiquidityUnderflow,
+    #[error("Liquidity overflow")]
+    LiquidityOverflow,
 }
 
 #[derive(Error, Debug)]
@@ -97,10 +195,27 @@ pub enum EventLogError {
 pub enum SwapSimulationError {
     #[error("Could not get next tick")]
     InvalidTick,
+    #[cfg(feature = "uniswap-v3")]
     #[error("Uniswap v3 math error")]
     UniswapV3MathError(#[from] UniswapV3MathError),
     #[error("Liquidity underflow")]
     LiquidityUnderflow,
+    #[error("Arithmetic error")]
+    ArithmeticError(#[from] ArithmeticError),
+    #[error("Token `{0:#x}` is not a token of the pool")]
+    InvalidToken(H160),
+    #[error("Invalid sqrt price limit {0}")]
+    InvalidSqrtPriceLimit(U256),
+    #[error("Invalid target price {0}")]
+    InvalidTargetPrice(f64),
+}
+
+#[derive(Error, Debug)]
+pub enum PoolValidationError {
+    #[error("Reserve {1} of pool `{0:#x}` does not fit in a uint112")]
+    ReserveOverflow(H160, u128),
+    #[error("Decimals {1} of token `{0:#x}` are greater than 77")]
+    InvalidDecimals(H160, u8),
 }
 
 #[derive(Error, Debug)]
@@ -111,4 +226,65 @@ pub enum CheckpointError {
     SerdeJsonError(#[from] serde_json::error::Error),
     #[error("IO error")]
     IOError(#[from] std::io::Error),
+    #[error("Invalid pool in checkpoint")]
+    PoolValidationError(#[from] PoolValidationError),
+    #[cfg(feature = "binary-checkpoint")]
+    #[error("Bincode error")]
+    BincodeError(#[from] bincode::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{abi::Token, providers::JsonRpcError};
+
+    use super::{decode_revert_reason, is_execution_error, ERROR_STRING_SELECTOR};
+
+    fn error_response(code: i64, message: &str) -> JsonRpcError {
+        JsonRpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+
+    #[test]
+    fn test_decode_revert_reason() {
+        let mut revert_data = ERROR_STRING_SELECTOR.to_vec();
+        revert_data.extend(ethers::abi::encode(&[Token::String(
+            "Pool does not exist".to_string(),
+        )]));
+
+        assert_eq!(
+            decode_revert_reason(&revert_data),
+            Some("Pool does not exist".to_string())
+        );
+
+        //Custom errors and empty reverts are not decoded
+        assert_eq!(decode_revert_reason(&[1, 2, 3, 4]), None);
+        assert_eq!(decode_revert_reason(&[]), None);
+        assert_eq!(decode_revert_reason(&ERROR_STRING_SELECTOR), None);
+    }
+
+    #[test]
+    fn test_is_execution_error() {
+        let execution_errors = [
+            (3, "execution reverted"),
+            (-32000, "execution reverted"),
+            (-32000, "out of gas"),
+            (-32000, "Max code size exceeded"),
+        ];
+        for (code, message) in execution_errors {
+            assert!(is_execution_error(&error_response(code, message)));
+        }
+
+        //Rate limits and authentication errors are not caused by the batch
+        let other_errors = [
+            (429, "Too Many Requests"),
+            (-32005, "limit exceeded"),
+            (-32001, "unauthorized"),
+        ];
+        for (code, message) in other_errors {
+            assert!(!is_execution_error(&error_response(code, message)));
+        }
+    }
 }
@@ -66,3 +66,57 @@ pub fn filter_blacklisted_addresses(amms: Vec<AMM>, blacklisted_addresses: Vec<H
 
     filtered_amms
 }
+
+//Splits AMMs into (connected, isolated), where connected AMMs contain at least one of the base tokens
+pub fn partition_by_base_tokens(amms: Vec<AMM>, bases: &HashSet<H160>) -> (Vec<AMM>, Vec<AMM>) {
+    amms.into_iter()
+        .partition(|amm| amm.tokens().iter().any(|token| bases.contains(token)))
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use std::collections::HashSet;
+
+    use ethers::types::H160;
+
+    use crate::amm::{uniswap_v2::UniswapV2Pool, AutomatedMarketMaker, AMM};
+
+    use super::partition_by_base_tokens;
+
+    #[test]
+    fn test_partition_by_base_tokens() {
+        let weth = H160::from_low_u64_be(1);
+        let usdc = H160::from_low_u64_be(2);
+        let token_x = H160::from_low_u64_be(3);
+        let token_y = H160::from_low_u64_be(4);
+
+        let pool = |address: u64, token_a: H160, token_b: H160| {
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: H160::from_low_u64_be(address),
+                token_a,
+                token_b,
+                ..Default::default()
+            })
+        };
+
+        let amms = vec![
+            pool(10, weth, token_x),
+            pool(11, token_x, token_y),
+            pool(12, token_y, usdc),
+            pool(13, weth, usdc),
+        ];
+
+        let (connected, isolated) = partition_by_base_tokens(amms, &HashSet::from([weth, usdc]));
+
+        let addresses = |amms: &[AMM]| amms.iter().map(|amm| amm.address()).collect::<Vec<_>>();
+        assert_eq!(
+            addresses(&connected),
+            vec![
+                H160::from_low_u64_be(10),
+                H160::from_low_u64_be(12),
+                H160::from_low_u64_be(13)
+            ]
+        );
+        assert_eq!(addresses(&isolated), vec![H160::from_low_u64_be(11)]);
+    }
+}
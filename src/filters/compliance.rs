@@ -0,0 +1,138 @@
+use std::{collections::BTreeSet, sync::Arc};
+
+use ethers::{
+    abi::ParamType,
+    providers::{Middleware, MiddlewareError},
+    types::{Bytes, TransactionRequest, H160},
+};
+use futures::stream::{self, StreamExt, TryStreamExt};
+
+use crate::{
+    amm::{factory::TASK_LIMIT, AutomatedMarketMaker, AMM},
+    errors::AMMError,
+};
+
+// Selectors of `decimals()` and `symbol()`
+const DECIMALS_SELECTOR: [u8; 4] = [49, 60, 229, 103];
+const SYMBOL_SELECTOR: [u8; 4] = [149, 216, 155, 65];
+
+/// Returns the tokens of `amms` whose `decimals()` or `symbol()` reverts or returns data that does not decode as the ERC-20 return type, sorted by address.
+/// `symbol()` returning a `bytes32`, as some early tokens do, is accepted. Transport errors are returned rather than flagging the token.
+pub async fn flag_noncompliant_tokens<M: Middleware>(
+    amms: &[AMM],
+    middleware: Arc<M>,
+) -> Result<Vec<H160>, AMMError<M>> {
+    let tokens = amms
+        .iter()
+        .flat_map(|amm| amm.tokens())
+        .collect::<BTreeSet<H160>>();
+
+    let flagged = stream::iter(tokens)
+        .map(|token| {
+            let middleware = middleware.clone();
+            async move {
+                let decimals = probe(token, DECIMALS_SELECTOR, middleware.clone()).await?;
+                let symbol = probe(token, SYMBOL_SELECTOR, middleware).await?;
+
+                let compliant = decimals.is_some_and(|data| is_valid_decimals(&data))
+                    && symbol.is_some_and(|data| is_valid_symbol(&data));
+
+                Ok::<_, AMMError<M>>((!compliant).then_some(token))
+            }
+        })
+        .buffered(TASK_LIMIT)
+        .try_collect::<Vec<Option<H160>>>()
+        .await?;
+
+    Ok(flagged.into_iter().flatten().collect())
+}
+
+//Returns `None` if the call reverted
+async fn probe<M: Middleware>(
+    token: H160,
+    selector: [u8; 4],
+    middleware: Arc<M>,
+) -> Result<Option<Bytes>, AMMError<M>> {
+    let tx = TransactionRequest::new().to(token).data(selector.to_vec());
+
+    match middleware.call(&tx.into(), None).await {
+        Ok(return_data) => Ok(Some(return_data)),
+        Err(e) if e.as_error_response().is_some() => Ok(None),
+        Err(e) => Err(AMMError::MiddlewareError(e)),
+    }
+}
+
+//A uint8 padded to a single word
+fn is_valid_decimals(return_data: &[u8]) -> bool {
+    return_data.len() == 32 && return_data[..31].iter().all(|byte| *byte == 0)
+}
+
+fn is_valid_symbol(return_data: &[u8]) -> bool {
+    return_data.len() == 32 || ethers::abi::decode(&[ParamType::String], return_data).is_ok()
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        abi::Token,
+        providers::{Http, Provider},
+        types::H160,
+    };
+
+    use crate::amm::{uniswap_v2::UniswapV2Pool, AMM};
+
+    use super::{flag_noncompliant_tokens, is_valid_decimals, is_valid_symbol};
+
+    #[test]
+    fn test_validate_return_data() {
+        let mut decimals = [0_u8; 32];
+        decimals[31] = 18;
+        assert!(is_valid_decimals(&decimals));
+
+        decimals[0] = 1;
+        assert!(!is_valid_decimals(&decimals));
+        assert!(!is_valid_decimals(&[]));
+        assert!(!is_valid_decimals(&[18]));
+
+        let symbol = ethers::abi::encode(&[Token::String("WETH".to_string())]);
+        assert!(is_valid_symbol(&symbol));
+
+        //bytes32 symbol, ie. MKR
+        assert!(is_valid_symbol(&[0_u8; 32]));
+        assert!(!is_valid_symbol(&symbol[..40]));
+        assert!(!is_valid_symbol(&[]));
+    }
+
+    #[tokio::test]
+    async fn test_flag_noncompliant_tokens() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let weth = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+        let mkr = H160::from_str("0x9f8F72aA9304c8B593d555F12eF6589cC3A579A2")?;
+        //Uniswap V2 factory, which implements neither function
+        let not_a_token = H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f")?;
+
+        let amms = vec![
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                token_a: weth,
+                token_b: mkr,
+                ..Default::default()
+            }),
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                token_a: weth,
+                token_b: not_a_token,
+                ..Default::default()
+            }),
+        ];
+
+        assert_eq!(
+            flag_noncompliant_tokens(&amms, middleware).await?,
+            vec![not_a_token]
+        );
+
+        Ok(())
+    }
+}
@@ -1,2 +1,5 @@
 pub mod address;
+pub mod compliance;
+#[cfg(feature = "uniswap-v2")]
+pub mod tax;
 pub mod value;
@@ -0,0 +1,283 @@
+use std::sync::Arc;
+
+use ethers::{
+    abi::{ParamType, Token},
+    providers::{
+        call_raw::{spoof, RawCall},
+        Middleware,
+    },
+    types::{transaction::eip2718::TypedTransaction, Bytes, TransactionRequest, H160, H256, U256},
+};
+
+use crate::{
+    amm::{
+        uniswap_v2::{IErc20, UniswapV2Pool, U112_MAX},
+        AutomatedMarketMaker,
+    },
+    errors::{AMMError, SwapSimulationError},
+};
+
+/// Tax returned by `measure_token_tax` for tokens that can be bought but not sold.
+pub const SELL_BLOCKED_TAX_BPS: u32 = u32::MAX;
+// Fraction of the quote token reserve bought with, small enough to keep the price impact negligible
+pub const TAX_PROBE_FRACTION: u64 = 1000;
+
+// Multicall3, deployed at the same address on every chain
+const MULTICALL_3: H160 = H160([
+    202, 17, 189, 224, 89, 119, 179, 99, 17, 103, 2, 136, 98, 190, 42, 23, 57, 118, 202, 17,
+]);
+// Storage slot of `reserve0`, `reserve1` and `blockTimestampLast` in a Uniswap V2 pair
+const RESERVES_SLOT: u64 = 8;
+
+// Selectors of `aggregate3((address,bool,bytes)[])`, `balanceOf(address)` and `transfer(address,uint256)`
+const AGGREGATE_3_SELECTOR: [u8; 4] = [130, 173, 86, 203];
+const BALANCE_OF_SELECTOR: [u8; 4] = [112, 160, 130, 49];
+const TRANSFER_SELECTOR: [u8; 4] = [169, 5, 156, 187];
+
+/// Returns the loss in basis points of buying `token` through `pool` and selling it back, excluding the pool fee, ie. the combined buy and sell transfer tax.
+/// The swaps are simulated with `eth_call`s through Multicall3, with the pair reserves overridden so that the pair sees the quote token as sent in. The pair storage must follow the Uniswap V2 layout.
+/// The sell leg is measured as the amount of `token` received by the pair when the bought amount is transferred back.
+/// Returns `SELL_BLOCKED_TAX_BPS` if the transfer back to the pair fails, and `AMMError::TokenBuyFailed` if the token can not be bought at all.
+pub async fn measure_token_tax<M: Middleware>(
+    token: H160,
+    pool: &UniswapV2Pool,
+    middleware: Arc<M>,
+) -> Result<u32, AMMError<M>> {
+    if !pool.contains_token(token) {
+        return Err(SwapSimulationError::InvalidToken(token).into());
+    }
+    let quote_token = pool.get_token_out(token);
+
+    //Every call is made at the same block so that the overridden reserves match the balances of the pair
+    let block_number = middleware
+        .get_block_number()
+        .await
+        .map_err(AMMError::MiddlewareError)?
+        .as_u64();
+
+    let token_balance = IErc20::new(token, middleware.clone())
+        .balance_of(pool.address)
+        .block(block_number)
+        .call()
+        .await
+        .map_err(|e| AMMError::ContractError("measure_token_tax", token, e))?;
+    let quote_balance = IErc20::new(quote_token, middleware.clone())
+        .balance_of(pool.address)
+        .block(block_number)
+        .call()
+        .await
+        .map_err(|e| AMMError::ContractError("measure_token_tax", quote_token, e))?;
+
+    let amount_in = quote_balance / U256::from(TAX_PROBE_FRACTION);
+    if amount_in.is_zero()
+        || token_balance > U256::from(U112_MAX)
+        || quote_balance > U256::from(U112_MAX)
+    {
+        return Err(AMMError::TokenBuyFailed(token, pool.address));
+    }
+
+    //The pair computes the amount in as its balance minus its reserve, so lowering the reserve simulates the transfer in
+    let (reserve_token, reserve_quote) = (token_balance, quote_balance - amount_in);
+    let amount_out = pool.get_amount_out(amount_in, reserve_quote, reserve_token);
+    let (amount_0_out, amount_1_out, reserve_0, reserve_1) = if pool.token_a == token {
+        (amount_out, U256::zero(), reserve_token, reserve_quote)
+    } else {
+        (U256::zero(), amount_out, reserve_quote, reserve_token)
+    };
+
+    let mut reserves = [0_u8; 32];
+    (reserve_0 | (reserve_1 << 112)).to_big_endian(&mut reserves);
+    let mut state = spoof::state();
+    state
+        .account(pool.address)
+        .store(H256::from_low_u64_be(RESERVES_SLOT), H256(reserves));
+
+    let swap = pool.swap_calldata(amount_0_out, amount_1_out, MULTICALL_3, vec![])?;
+
+    //Buy, measuring the amount received by Multicall3
+    let results = aggregate_3(
+        vec![
+            (token, balance_of_calldata(MULTICALL_3)),
+            (pool.address, swap.clone()),
+            (token, balance_of_calldata(MULTICALL_3)),
+        ],
+        &state,
+        block_number,
+        pool.address,
+        middleware.clone(),
+    )
+    .await?;
+
+    let (Some(balance_before), true, Some(balance_after)) = (
+        decode_uint(&results[0]),
+        results[1].is_some(),
+        decode_uint(&results[2]),
+    ) else {
+        return Err(AMMError::TokenBuyFailed(token, pool.address));
+    };
+    let amount_bought = balance_after.saturating_sub(balance_before);
+    //The whole amount bought is taxed
+    if amount_bought.is_zero() {
+        return Ok(10000);
+    }
+
+    //Buy again and transfer the amount bought back to the pair, measuring the amount received by the pair
+    let results = aggregate_3(
+        vec![
+            (pool.address, swap),
+            (token, balance_of_calldata(pool.address)),
+            (token, transfer_calldata(pool.address, amount_bought)),
+            (token, balance_of_calldata(pool.address)),
+        ],
+        &state,
+        block_number,
+        pool.address,
+        middleware,
+    )
+    .await?;
+
+    //Tokens not reverting on failure return false instead
+    let transferred = match &results[2] {
+        Some(return_data) => {
+            return_data.is_empty()
+                || ethers::abi::decode(&[ParamType::Bool], return_data)
+                    .ok()
+                    .and_then(|tokens| tokens[0].clone().into_bool())
+                    .unwrap_or(false)
+        }
+        None => false,
+    };
+    let (Some(pair_balance_before), Some(pair_balance_after)) =
+        (decode_uint(&results[1]), decode_uint(&results[3]))
+    else {
+        return Err(AMMError::TokenBuyFailed(token, pool.address));
+    };
+    let amount_sold = pair_balance_after.saturating_sub(pair_balance_before);
+    if !transferred || amount_sold.is_zero() {
+        return Ok(SELL_BLOCKED_TAX_BPS);
+    }
+
+    //Both legs are measured against the amount out of the pool, so that the pool fee is excluded
+    let retained_bps = (amount_sold * U256::from(10000) / amount_out).min(U256::from(10000));
+
+    Ok(10000 - retained_bps.as_u32())
+}
+
+//Makes the calls through Multicall3 in a single `eth_call`, returning the return data of each call or `None` if it reverted
+async fn aggregate_3<M: Middleware>(
+    calls: Vec<(H160, Bytes)>,
+    state: &spoof::State,
+    block_number: u64,
+    pool: H160,
+    middleware: Arc<M>,
+) -> Result<Vec<Option<Bytes>>, AMMError<M>> {
+    let calls = calls
+        .into_iter()
+        .map(|(target, calldata)| {
+            Token::Tuple(vec![
+                Token::Address(target),
+                Token::Bool(true),
+                Token::Bytes(calldata.to_vec()),
+            ])
+        })
+        .collect::<Vec<Token>>();
+
+    let mut data = AGGREGATE_3_SELECTOR.to_vec();
+    data.extend(ethers::abi::encode(&[Token::Array(calls)]));
+    let tx: TypedTransaction = TransactionRequest::new().to(MULTICALL_3).data(data).into();
+
+    let return_data = middleware
+        .provider()
+        .call_raw(&tx)
+        .block(block_number.into())
+        .state(state)
+        .await
+        .map_err(|e| AMMError::ProviderError("measure_token_tax", pool, e))?;
+
+    let return_types = [ParamType::Array(Box::new(ParamType::Tuple(vec![
+        ParamType::Bool,
+        ParamType::Bytes,
+    ])))];
+    let results = ethers::abi::decode(&return_types, &return_data)
+        .map_err(|source| AMMError::AbiDecodeError {
+            context: "measure_token_tax",
+            source,
+        })?
+        .pop()
+        .and_then(|results| results.into_array())
+        .ok_or(AMMError::BatchRequestError(pool))?;
+
+    Ok(results
+        .into_iter()
+        .map(|result| {
+            let mut result = result.into_tuple()?;
+            let return_data = result.pop()?.into_bytes()?;
+
+            result.pop()?.into_bool()?.then(|| return_data.into())
+        })
+        .collect())
+}
+
+fn balance_of_calldata(owner: H160) -> Bytes {
+    let mut calldata = BALANCE_OF_SELECTOR.to_vec();
+    calldata.extend(ethers::abi::encode(&[Token::Address(owner)]));
+    calldata.into()
+}
+
+fn transfer_calldata(to: H160, amount: U256) -> Bytes {
+    let mut calldata = TRANSFER_SELECTOR.to_vec();
+    calldata.extend(ethers::abi::encode(&[
+        Token::Address(to),
+        Token::Uint(amount),
+    ]));
+    calldata.into()
+}
+
+fn decode_uint(return_data: &Option<Bytes>) -> Option<U256> {
+    ethers::abi::decode(&[ParamType::Uint(256)], return_data.as_ref()?)
+        .ok()?
+        .pop()?
+        .into_uint()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::H160,
+    };
+
+    use crate::amm::uniswap_v2::UniswapV2Pool;
+
+    use super::measure_token_tax;
+
+    #[tokio::test]
+    async fn test_measure_token_tax() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let usdc = H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?;
+        let weth = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?;
+        let pool = UniswapV2Pool {
+            address: H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?,
+            token_a: usdc,
+            token_a_decimals: 6,
+            token_b: weth,
+            token_b_decimals: 18,
+            fee: 300,
+            ..Default::default()
+        };
+
+        //Neither token charges a transfer tax
+        assert_eq!(measure_token_tax(usdc, &pool, middleware.clone()).await?, 0);
+        assert_eq!(measure_token_tax(weth, &pool, middleware.clone()).await?, 0);
+
+        assert!(measure_token_tax(H160::zero(), &pool, middleware)
+            .await
+            .is_err());
+
+        Ok(())
+    }
+}
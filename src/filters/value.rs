@@ -146,6 +146,11 @@ abigen!(
     "src/filters/batch_requests/GetWethValueInAMMBatchRequest.json";
 );
 
+lazy_static::lazy_static! {
+    static ref WETH_VALUE_RETURN_TYPES: Vec<ParamType> =
+        vec![ParamType::Array(Box::new(ParamType::Uint(256)))];
+}
+
 async fn get_weth_value_in_amm_batch_request<M: Middleware>(
     amms: &[AMM],
     factories: &[Factory],
@@ -163,7 +168,9 @@ async fn get_weth_value_in_amm_batch_request<M: Middleware>(
     let factory_is_uni_v3 = factories
         .iter()
         .map(|d| match d {
+            #[cfg(feature = "uniswap-v2")]
             Factory::UniswapV2Factory(_) => Token::Bool(false),
+            #[cfg(feature = "uniswap-v3")]
             Factory::UniswapV3Factory(_) => Token::Bool(true),
         })
         .collect::<Vec<Token>>();
@@ -186,12 +193,13 @@ async fn get_weth_value_in_amm_batch_request<M: Middleware>(
     let return_data: Bytes = deployer
         .call_raw()
         .await
-        .map_err(|e| AMMError::ProviderError("get_weth_value_in_amm_batch_request", batch_start, e))?;
+        .map_err(|e| AMMError::batch_call_error("get_weth_value_in_amm_batch_request", batch_start, e))?;
 
-    let return_data_tokens = ethers::abi::decode(
-        &[ParamType::Array(Box::new(ParamType::Uint(256)))],
-        &return_data,
-    )?;
+    let return_data_tokens = ethers::abi::decode(&WETH_VALUE_RETURN_TYPES, &return_data)
+        .map_err(|source| AMMError::AbiDecodeError {
+            context: "get_weth_value_in_amm_batch_request",
+            source,
+        })?;
 
     let mut weth_values_in_pools = vec![];
     for token_array in return_data_tokens {
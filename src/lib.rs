@@ -1,6 +1,12 @@
+#[cfg(not(any(feature = "uniswap-v2", feature = "uniswap-v3")))]
+compile_error!("At least one of the `uniswap-v2` and `uniswap-v3` features must be enabled");
+
 pub mod amm;
 pub mod discovery;
 pub mod errors;
 pub mod filters;
+pub mod middleware;
+pub mod routing;
+#[cfg(feature = "state-space")]
 pub mod state_space;
 pub mod sync;
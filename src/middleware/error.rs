@@ -0,0 +1,57 @@
+use ethers::providers::{Middleware, MiddlewareError};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum RateLimitedProviderError<M>
+where
+    M: Middleware,
+{
+    #[error("Middleware error: {0}")]
+    MiddlewareError(<M as Middleware>::Error),
+}
+
+impl<M: Middleware> MiddlewareError for RateLimitedProviderError<M> {
+    type Inner = <M as Middleware>::Error;
+
+    fn from_err(src: Self::Inner) -> Self {
+        RateLimitedProviderError::MiddlewareError(src)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            RateLimitedProviderError::MiddlewareError(e) => Some(e),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FailoverProviderError<M>
+where
+    M: Middleware,
+{
+    #[error("Middleware error: {0}")]
+    MiddlewareError(<M as Middleware>::Error),
+    #[error("No providers configured")]
+    NoProviders,
+}
+
+impl<M: Middleware> MiddlewareError for FailoverProviderError<M> {
+    type Inner = <M as Middleware>::Error;
+
+    fn from_err(src: Self::Inner) -> Self {
+        FailoverProviderError::MiddlewareError(src)
+    }
+
+    fn as_inner(&self) -> Option<&Self::Inner> {
+        match self {
+            FailoverProviderError::MiddlewareError(e) => Some(e),
+            FailoverProviderError::NoProviders => None,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum FailoverClientError {
+    #[error("No clients configured")]
+    NoClients,
+}
@@ -0,0 +1,301 @@
+use std::{fmt::Debug, future::Future};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, Middleware, MiddlewareError, RpcError},
+    types::{
+        transaction::eip2718::TypedTransaction, Block, BlockId, Bytes, Filter, Log, NameOrAddress,
+        Transaction, TransactionReceipt, TxHash, H256, U256, U64,
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::error::{FailoverClientError, FailoverProviderError};
+
+/// Middleware that holds an ordered list of middlewares and retries a request against the next middleware when the request fails with a transport error.
+/// Errors returned by the node, such as reverts, are returned without failing over.
+/// Methods that are not overridden below, including sending transactions, are delegated to the primary middleware.
+#[derive(Debug)]
+pub struct FailoverProvider<M> {
+    middlewares: Vec<M>,
+}
+
+impl<M: Middleware> FailoverProvider<M> {
+    /// Creates a new failover provider, the first middleware is the primary endpoint.
+    pub fn new(middlewares: Vec<M>) -> Result<Self, FailoverProviderError<M>> {
+        if middlewares.is_empty() {
+            return Err(FailoverProviderError::NoProviders);
+        }
+
+        Ok(FailoverProvider { middlewares })
+    }
+
+    pub fn middlewares(&self) -> &[M] {
+        &self.middlewares
+    }
+
+    async fn with_failover<'a, T, F, Fut>(
+        &'a self,
+        request: F,
+    ) -> Result<T, FailoverProviderError<M>>
+    where
+        F: Fn(&'a M) -> Fut,
+        Fut: Future<Output = Result<T, M::Error>>,
+    {
+        let (primary, fallbacks) = self
+            .middlewares
+            .split_first()
+            .expect("Failover provider has at least one middleware");
+
+        let mut result = request(primary).await;
+        for middleware in fallbacks {
+            //An error response means the node handled the request, retrying against another endpoint would produce the same result
+            if !matches!(&result, Err(e) if e.as_error_response().is_none()) {
+                break;
+            }
+
+            result = request(middleware).await;
+        }
+
+        result.map_err(FailoverProviderError::MiddlewareError)
+    }
+}
+
+#[async_trait]
+impl<M: Middleware> Middleware for FailoverProvider<M> {
+    type Error = FailoverProviderError<M>;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.middlewares[0]
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        self.with_failover(|middleware| middleware.call(tx, block))
+            .await
+    }
+
+    async fn estimate_gas(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.with_failover(|middleware| middleware.estimate_gas(tx, block))
+            .await
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Self::Error> {
+        self.with_failover(|middleware| middleware.get_logs(filter))
+            .await
+    }
+
+    async fn get_block_number(&self) -> Result<U64, Self::Error> {
+        self.with_failover(|middleware| middleware.get_block_number())
+            .await
+    }
+
+    async fn get_block<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<TxHash>>, Self::Error> {
+        let block: BlockId = block_hash_or_number.into();
+
+        self.with_failover(|middleware| middleware.get_block(block))
+            .await
+    }
+
+    async fn get_block_with_txs<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<Transaction>>, Self::Error> {
+        let block: BlockId = block_hash_or_number.into();
+
+        self.with_failover(|middleware| middleware.get_block_with_txs(block))
+            .await
+    }
+
+    async fn get_chainid(&self) -> Result<U256, Self::Error> {
+        self.with_failover(|middleware| middleware.get_chainid())
+            .await
+    }
+
+    async fn get_gas_price(&self) -> Result<U256, Self::Error> {
+        self.with_failover(|middleware| middleware.get_gas_price())
+            .await
+    }
+
+    async fn get_transaction_count<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        let from: NameOrAddress = from.into();
+
+        self.with_failover(|middleware| middleware.get_transaction_count(from.clone(), block))
+            .await
+    }
+
+    async fn get_balance<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        let from: NameOrAddress = from.into();
+
+        self.with_failover(|middleware| middleware.get_balance(from.clone(), block))
+            .await
+    }
+
+    async fn get_transaction<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<Transaction>, Self::Error> {
+        let transaction_hash: TxHash = transaction_hash.into();
+
+        self.with_failover(|middleware| middleware.get_transaction(transaction_hash))
+            .await
+    }
+
+    async fn get_transaction_receipt<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<TransactionReceipt>, Self::Error> {
+        let transaction_hash: TxHash = transaction_hash.into();
+
+        self.with_failover(|middleware| middleware.get_transaction_receipt(transaction_hash))
+            .await
+    }
+
+    async fn get_code<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        at: T,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        let at: NameOrAddress = at.into();
+
+        self.with_failover(|middleware| middleware.get_code(at.clone(), block))
+            .await
+    }
+
+    async fn get_storage_at<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        location: H256,
+        block: Option<BlockId>,
+    ) -> Result<H256, Self::Error> {
+        let from: NameOrAddress = from.into();
+
+        self.with_failover(|middleware| middleware.get_storage_at(from.clone(), location, block))
+            .await
+    }
+}
+
+/// Transport that holds an ordered list of clients and retries a request against the next client when the request fails with a transport error.
+/// Unlike `FailoverProvider`, this covers every RPC method. Errors returned by the node, such as reverts, are returned without failing over.
+#[derive(Debug)]
+pub struct FailoverClient<C> {
+    clients: Vec<C>,
+}
+
+impl<C: JsonRpcClient> FailoverClient<C> {
+    /// Creates a new failover client, the first client is the primary endpoint.
+    pub fn new(clients: Vec<C>) -> Result<Self, FailoverClientError> {
+        if clients.is_empty() {
+            return Err(FailoverClientError::NoClients);
+        }
+
+        Ok(FailoverClient { clients })
+    }
+
+    pub fn clients(&self) -> &[C] {
+        &self.clients
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for FailoverClient<C> {
+    type Error = C::Error;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        let (primary, fallbacks) = self
+            .clients
+            .split_first()
+            .expect("Failover client has at least one client");
+
+        let mut result = primary.request(method, &params).await;
+        for client in fallbacks {
+            //An error response means the node handled the request, retrying against another endpoint would produce the same result
+            if !matches!(&result, Err(e) if e.as_error_response().is_none()) {
+                break;
+            }
+
+            result = client.request(method, &params).await;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        providers::{Middleware, MockProvider, Provider},
+        types::U64,
+    };
+
+    use super::{FailoverClient, FailoverProvider};
+    use crate::middleware::error::{FailoverClientError, FailoverProviderError};
+
+    #[test]
+    fn test_failover_provider_requires_a_middleware() {
+        assert!(matches!(
+            FailoverProvider::<Provider<MockProvider>>::new(vec![]),
+            Err(FailoverProviderError::NoProviders)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failover_provider() -> eyre::Result<()> {
+        //The primary endpoint has no response queued, which fails as a transport error
+        let (primary, _) = Provider::mocked();
+        let (fallback, mock) = Provider::mocked();
+        mock.push(U64::from(17))?;
+
+        let provider = FailoverProvider::new(vec![primary, fallback])?;
+
+        assert_eq!(provider.get_block_number().await?, U64::from(17));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failover_client_requires_a_client() {
+        assert!(matches!(
+            FailoverClient::<MockProvider>::new(vec![]),
+            Err(FailoverClientError::NoClients)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failover_client() -> eyre::Result<()> {
+        //The primary endpoint has no response queued, which fails as a transport error
+        let primary = MockProvider::new();
+        let fallback = MockProvider::new();
+        fallback.push(U64::from(17))?;
+
+        let provider = Provider::new(FailoverClient::new(vec![primary, fallback])?);
+
+        assert_eq!(provider.get_block_number().await?, U64::from(17));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,3 @@
+pub mod error;
+pub mod failover;
+pub mod rate_limit;
@@ -0,0 +1,416 @@
+use std::{
+    fmt::Debug,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use ethers::{
+    providers::{JsonRpcClient, Middleware, PendingTransaction},
+    types::{
+        transaction::eip2718::TypedTransaction, Block, BlockId, Bytes, Filter, Log, NameOrAddress,
+        Transaction, TransactionReceipt, TxHash, H256, U256, U64,
+    },
+};
+use serde::{de::DeserializeOwned, Serialize};
+use tokio::sync::Mutex;
+
+use super::error::RateLimitedProviderError;
+
+/// Token bucket that allows up to `requests_per_second` requests per second, with bursts of up to one second worth of requests.
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: u32,
+    bucket: Mutex<Bucket>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    /// Creates a full bucket. A rate of 0 requests per second is raised to 1, as a bucket that never refills would block forever.
+    pub fn new(requests_per_second: u32) -> Self {
+        let requests_per_second = requests_per_second.max(1);
+
+        RateLimiter {
+            requests_per_second,
+            bucket: Mutex::new(Bucket {
+                tokens: requests_per_second as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn requests_per_second(&self) -> u32 {
+        self.requests_per_second
+    }
+
+    /// Waits until a token is available and consumes it.
+    pub async fn acquire(&self) {
+        let rate = self.requests_per_second as f64;
+
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().await;
+
+                //Refill the bucket with the tokens accrued since the last refill, capped at the bucket capacity
+                let now = Instant::now();
+                let accrued = now.duration_since(bucket.last_refill).as_secs_f64() * rate;
+                bucket.tokens = (bucket.tokens + accrued).min(rate);
+                bucket.last_refill = now;
+
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+
+                Duration::from_secs_f64((1.0 - bucket.tokens) / rate)
+            };
+
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Middleware that throttles the RPC requests made through it to a configurable number of requests per second.
+/// Methods that are not overridden below are delegated to the inner middleware without being throttled, use a `RateLimitedClient` to throttle every request at the transport.
+#[derive(Debug)]
+pub struct RateLimitedProvider<M> {
+    inner: M,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl<M: Middleware> RateLimitedProvider<M> {
+    pub fn new(inner: M, requests_per_second: u32) -> Self {
+        Self::with_rate_limiter(inner, Arc::new(RateLimiter::new(requests_per_second)))
+    }
+
+    /// Creates a middleware throttled by `rate_limiter`, which can be shared with other middlewares or the call options of batch requests.
+    pub fn with_rate_limiter(inner: M, rate_limiter: Arc<RateLimiter>) -> Self {
+        RateLimitedProvider {
+            inner,
+            rate_limiter,
+        }
+    }
+
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.rate_limiter
+    }
+}
+
+#[async_trait]
+impl<M: Middleware> Middleware for RateLimitedProvider<M> {
+    type Error = RateLimitedProviderError<M>;
+    type Provider = M::Provider;
+    type Inner = M;
+
+    fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    async fn fill_transaction(
+        &self,
+        tx: &mut TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<(), Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .fill_transaction(tx, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn send_transaction<T: Into<TypedTransaction> + Send + Sync>(
+        &self,
+        tx: T,
+        block: Option<BlockId>,
+    ) -> Result<PendingTransaction<'_, Self::Provider>, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .send_transaction(tx, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn send_raw_transaction<'a>(
+        &'a self,
+        tx: Bytes,
+    ) -> Result<PendingTransaction<'a, Self::Provider>, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .send_raw_transaction(tx)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn call(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .call(tx, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn estimate_gas(
+        &self,
+        tx: &TypedTransaction,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .estimate_gas(tx, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_logs(filter)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_block_number(&self) -> Result<U64, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_block_number()
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_block<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<TxHash>>, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_block(block_hash_or_number)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_block_with_txs<T: Into<BlockId> + Send + Sync>(
+        &self,
+        block_hash_or_number: T,
+    ) -> Result<Option<Block<Transaction>>, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_block_with_txs(block_hash_or_number)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_chainid(&self) -> Result<U256, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_chainid()
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_gas_price(&self) -> Result<U256, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_gas_price()
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_transaction_count<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_transaction_count(from, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_balance<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        block: Option<BlockId>,
+    ) -> Result<U256, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_balance(from, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_transaction<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<Transaction>, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_transaction(transaction_hash)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_transaction_receipt<T: Send + Sync + Into<TxHash>>(
+        &self,
+        transaction_hash: T,
+    ) -> Result<Option<TransactionReceipt>, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_transaction_receipt(transaction_hash)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_code<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        at: T,
+        block: Option<BlockId>,
+    ) -> Result<Bytes, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_code(at, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+
+    async fn get_storage_at<T: Into<NameOrAddress> + Send + Sync>(
+        &self,
+        from: T,
+        location: H256,
+        block: Option<BlockId>,
+    ) -> Result<H256, Self::Error> {
+        self.rate_limiter.acquire().await;
+        self.inner
+            .get_storage_at(from, location, block)
+            .await
+            .map_err(RateLimitedProviderError::MiddlewareError)
+    }
+}
+
+/// Transport that throttles every request sent through it to a configurable number of requests per second.
+/// Unlike `RateLimitedProvider`, this covers every RPC method, including requests made by middleware stacked on top, ie. `Provider::new(RateLimitedClient::new(Http::from_str(url)?, 10))`.
+#[derive(Debug)]
+pub struct RateLimitedClient<C> {
+    inner: C,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl<C: JsonRpcClient> RateLimitedClient<C> {
+    pub fn new(inner: C, requests_per_second: u32) -> Self {
+        Self::with_rate_limiter(inner, Arc::new(RateLimiter::new(requests_per_second)))
+    }
+
+    /// Creates a client throttled by `rate_limiter`, which can be shared with other clients or the call options of batch requests.
+    pub fn with_rate_limiter(inner: C, rate_limiter: Arc<RateLimiter>) -> Self {
+        RateLimitedClient {
+            inner,
+            rate_limiter,
+        }
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.rate_limiter
+    }
+}
+
+#[async_trait]
+impl<C: JsonRpcClient> JsonRpcClient for RateLimitedClient<C> {
+    type Error = C::Error;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        self.rate_limiter.acquire().await;
+        self.inner.request(method, params).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use ethers::{
+        providers::{Middleware, MockProvider, Provider},
+        types::U64,
+    };
+
+    use super::{RateLimitedClient, RateLimitedProvider, RateLimiter};
+
+    #[tokio::test]
+    async fn test_rate_limiter() {
+        let rate_limiter = RateLimiter::new(20);
+
+        let start = Instant::now();
+
+        //The first 20 requests are served from the full bucket, the next 5 wait for tokens to accrue
+        for _ in 0..25 {
+            rate_limiter.acquire().await;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn test_rate_limiter_zero_requests_per_second() {
+        assert_eq!(RateLimiter::new(0).requests_per_second(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_provider() -> eyre::Result<()> {
+        let (provider, mock) = Provider::mocked();
+        let provider = RateLimitedProvider::new(provider, 10);
+        for _ in 0..15 {
+            mock.push(U64::one())?;
+        }
+
+        let start = Instant::now();
+
+        //The first 10 requests are served from the full bucket
+        for _ in 0..5 {
+            provider.get_block_number().await?;
+            provider.get_gas_price().await?;
+            provider.get_chainid().await?;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_client() -> eyre::Result<()> {
+        let mock = MockProvider::new();
+        let provider = Provider::new(RateLimitedClient::new(mock.clone(), 10));
+        for _ in 0..15 {
+            mock.push(U64::one())?;
+        }
+
+        let start = Instant::now();
+
+        //Every method goes through the transport, the first 10 requests are served from the full bucket
+        for _ in 0..5 {
+            provider.get_block_number().await?;
+            provider.get_gas_price().await?;
+            provider.get_chainid().await?;
+        }
+
+        assert!(start.elapsed() >= Duration::from_millis(400));
+
+        Ok(())
+    }
+}
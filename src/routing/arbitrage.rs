@@ -0,0 +1,317 @@
+use std::collections::HashMap;
+
+use ethers::types::{H160, U256, U512};
+
+use crate::amm::{uniswap_v2::UniswapV2Pool, AutomatedMarketMaker};
+
+/// Returns the amount of `token_in` received by swapping `amount_in` of `token_in` through `pool_a` and back through `pool_b`.
+fn simulate_arb(
+    pool_a: &UniswapV2Pool,
+    pool_b: &UniswapV2Pool,
+    token_in: H160,
+    amount_in: U256,
+) -> U256 {
+    let amount_mid = pool_a.simulate_swap(token_in, amount_in).unwrap_or_default();
+    let token_mid = pool_a.get_token_out(token_in);
+
+    pool_b.simulate_swap(token_mid, amount_mid).unwrap_or_default()
+}
+
+//Both pools must be a pair of `token_in` and the same other token
+fn is_arb_pair(pool_a: &UniswapV2Pool, pool_b: &UniswapV2Pool, token_in: H160) -> bool {
+    pool_a.contains_token(token_in)
+        && pool_b.contains_token(token_in)
+        && pool_a.get_token_out(token_in) == pool_b.get_token_out(token_in)
+}
+
+/// Returns the amount of `token_in` that maximizes the profit of buying through `pool_a` and selling back through `pool_b`.
+/// Profit is concave in the amount in, so the maximum is found with a ternary search. Returns `None` if no amount is profitable.
+pub fn optimal_arb_amount(
+    pool_a: &UniswapV2Pool,
+    pool_b: &UniswapV2Pool,
+    token_in: H160,
+) -> Option<U256> {
+    if !is_arb_pair(pool_a, pool_b, token_in) {
+        return None;
+    }
+
+    //Pool B can never return more than its reserve of `token_in`, bounding any profitable amount in
+    let mut low = U256::zero();
+    let mut high = U256::from(if pool_b.token_a == token_in {
+        pool_b.reserve_0
+    } else {
+        pool_b.reserve_1
+    });
+
+    //profit(x) = out(x) - x, compared as out(m1) + m2 < out(m2) + m1 to stay unsigned
+    while high - low > U256::from(2) {
+        let third = (high - low) / 3;
+        let mid_low = low + third;
+        let mid_high = high - third;
+
+        if simulate_arb(pool_a, pool_b, token_in, mid_low) + mid_high
+            < simulate_arb(pool_a, pool_b, token_in, mid_high) + mid_low
+        {
+            low = mid_low;
+        } else {
+            high = mid_high;
+        }
+    }
+
+    let mut optimal = low;
+    let mut amount_in = low;
+    while amount_in < high {
+        amount_in += U256::one();
+        if simulate_arb(pool_a, pool_b, token_in, amount_in) + optimal
+            > simulate_arb(pool_a, pool_b, token_in, optimal) + amount_in
+        {
+            optimal = amount_in;
+        }
+    }
+
+    if simulate_arb(pool_a, pool_b, token_in, optimal) > optimal {
+        Some(optimal)
+    } else {
+        None
+    }
+}
+
+/// Returns the smallest amount of `token_in` whose profit from buying through `pool_a` and selling back through `pool_b` exceeds `gas_cost_wei`.
+/// When `token_in` is not `weth`, the other token of the pools must be `weth`, and the gas cost is converted to `token_in` at the spot price of `pool_a`.
+/// Returns `None` if no amount is profitable after gas, or if the gas cost can not be priced in `token_in`.
+pub fn min_profitable_size(
+    pool_a: &UniswapV2Pool,
+    pool_b: &UniswapV2Pool,
+    token_in: H160,
+    gas_cost_wei: U256,
+    weth: H160,
+) -> Option<U256> {
+    let gas_cost = if token_in == weth {
+        gas_cost_wei
+    } else if pool_a.get_token_out(token_in) == weth {
+        let (reserve_in, reserve_weth) = if pool_a.token_a == token_in {
+            (pool_a.reserve_0, pool_a.reserve_1)
+        } else {
+            (pool_a.reserve_1, pool_a.reserve_0)
+        };
+
+        if reserve_weth == 0 {
+            return None;
+        }
+
+        //A gas cost that does not fit in a U256 once priced in token_in can not be covered by any amount
+        U256::try_from(gas_cost_wei.full_mul(U256::from(reserve_in)) / U512::from(reserve_weth))
+            .ok()?
+    } else {
+        return None;
+    };
+
+    let optimal = optimal_arb_amount(pool_a, pool_b, token_in)?;
+
+    //Profit increases up to the optimal amount, so the breakeven amount is found with a binary search below it
+    let is_profitable = |amount_in: U256| {
+        simulate_arb(pool_a, pool_b, token_in, amount_in) > amount_in.saturating_add(gas_cost)
+    };
+
+    if !is_profitable(optimal) {
+        return None;
+    }
+
+    let mut low = U256::zero();
+    let mut high = optimal;
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if is_profitable(mid) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+
+    Some(high)
+}
+
+/// Price gap of a token pair between two pools.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArbOpportunity {
+    /// Pool where `token` is cheaper, to buy it from
+    pub buy_pool: H160,
+    /// Pool where `token` is more expensive, to sell it to
+    pub sell_pool: H160,
+    /// Token bought in `buy_pool` and sold in `sell_pool`
+    pub token: H160,
+    /// Token `token` is priced in
+    pub quote_token: H160,
+    /// Price gap between the pools before fees, in basis points of the lower price
+    pub spread_bps: f64,
+}
+
+/// Returns every pair of pools trading the same tokens whose price gap, after paying the fee of both pools, exceeds `min_profit_bps`.
+/// Prices are spot prices, so the spread is the upper bound of the profit and does not account for price impact. Opportunities are sorted by decreasing spread.
+pub fn find_arb_opportunities(pools: &[UniswapV2Pool], min_profit_bps: u32) -> Vec<ArbOpportunity> {
+    //Group the pools by token pair, pricing the lower token address in the higher one
+    let mut prices_by_pair: HashMap<(H160, H160), Vec<(&UniswapV2Pool, f64)>> = HashMap::new();
+    for pool in pools {
+        let (token, quote_token) = if pool.token_a < pool.token_b {
+            (pool.token_a, pool.token_b)
+        } else {
+            (pool.token_b, pool.token_a)
+        };
+
+        match pool.calculate_price(token) {
+            Ok(price) if price.is_finite() && price > 0.0 => prices_by_pair
+                .entry((token, quote_token))
+                .or_default()
+                .push((pool, price)),
+            _ => continue,
+        }
+    }
+
+    let mut opportunities = vec![];
+    for ((token, quote_token), prices) in prices_by_pair {
+        for (i, (pool_a, price_a)) in prices.iter().enumerate() {
+            for (pool_b, price_b) in prices.iter().skip(i + 1) {
+                let ((buy_pool, buy_price), (sell_pool, sell_price)) = if price_a < price_b {
+                    ((pool_a, price_a), (pool_b, price_b))
+                } else {
+                    ((pool_b, price_b), (pool_a, price_a))
+                };
+
+                let fee_factor = (1.0 - buy_pool.fee_bps() as f64 / 10000.0)
+                    * (1.0 - sell_pool.fee_bps() as f64 / 10000.0);
+                let net_profit_bps = (sell_price * fee_factor / buy_price - 1.0) * 10000.0;
+
+                if net_profit_bps > min_profit_bps as f64 {
+                    opportunities.push(ArbOpportunity {
+                        buy_pool: buy_pool.address,
+                        sell_pool: sell_pool.address,
+                        token,
+                        quote_token,
+                        spread_bps: (sell_price / buy_price - 1.0) * 10000.0,
+                    });
+                }
+            }
+        }
+    }
+
+    opportunities.sort_by(|a, b| b.spread_bps.total_cmp(&a.spread_bps));
+
+    opportunities
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{H160, U256};
+
+    use crate::amm::uniswap_v2::UniswapV2Pool;
+
+    use super::{find_arb_opportunities, min_profitable_size, optimal_arb_amount, simulate_arb};
+
+    fn pool(address: u64, reserve_0: u128, reserve_1: u128) -> UniswapV2Pool {
+        UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            token_a: H160::from_low_u64_be(1),
+            token_a_decimals: 18,
+            token_b: H160::from_low_u64_be(2),
+            token_b_decimals: 18,
+            reserve_0,
+            reserve_1,
+            fee: 300,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_optimal_arb_amount() {
+        let weth = H160::from_low_u64_be(1);
+
+        //Token 2 is cheaper in pool A than in pool B
+        let pool_a = pool(10, 1_000_000_000, 2_000_000_000);
+        let pool_b = pool(11, 1_000_000_000, 1_900_000_000);
+
+        let optimal = optimal_arb_amount(&pool_a, &pool_b, weth).unwrap();
+        let profit = |amount_in: U256| {
+            simulate_arb(&pool_a, &pool_b, weth, amount_in).as_u128() as i128
+                - amount_in.as_u128() as i128
+        };
+
+        assert!(profit(optimal) > 0);
+        assert!(profit(optimal) >= profit(optimal - U256::from(1000)));
+        assert!(profit(optimal) >= profit(optimal + U256::from(1000)));
+
+        //No arbitrage in the other direction, nor between identical pools
+        assert_eq!(optimal_arb_amount(&pool_b, &pool_a, weth), None);
+        assert_eq!(optimal_arb_amount(&pool_a, &pool_a, weth), None);
+    }
+
+    #[test]
+    fn test_min_profitable_size() {
+        let weth = H160::from_low_u64_be(1);
+        let token = H160::from_low_u64_be(2);
+
+        let pool_a = pool(10, 1_000_000_000, 2_000_000_000);
+        let pool_b = pool(11, 1_000_000_000, 1_900_000_000);
+        let gas_cost = U256::from(10_000);
+
+        let size = min_profitable_size(&pool_a, &pool_b, weth, gas_cost, weth).unwrap();
+
+        assert!(simulate_arb(&pool_a, &pool_b, weth, size) > size + gas_cost);
+        assert!(
+            simulate_arb(&pool_a, &pool_b, weth, size - 1) <= size - 1 + gas_cost,
+            "A smaller size should not be profitable after gas"
+        );
+
+        //Gas exceeding the maximum profit
+        assert_eq!(
+            min_profitable_size(&pool_a, &pool_b, weth, U256::from(1_000_000_000), weth),
+            None
+        );
+
+        //Starting from token 2, gas is priced in token 2 at the spot price of pool A
+        let size = min_profitable_size(&pool_b, &pool_a, token, gas_cost, weth).unwrap();
+        assert!(simulate_arb(&pool_b, &pool_a, token, size) > size + gas_cost * 19 / 10);
+
+        //Gas overflowing a U256 once priced in token 2
+        assert_eq!(
+            min_profitable_size(&pool_b, &pool_a, token, U256::MAX, weth),
+            None
+        );
+
+        //Gas can not be priced without a WETH leg
+        assert_eq!(
+            min_profitable_size(&pool_a, &pool_b, weth, gas_cost, H160::from_low_u64_be(3)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_find_arb_opportunities() {
+        let token_1 = H160::from_low_u64_be(1);
+        let token_2 = H160::from_low_u64_be(2);
+
+        //Token 1 priced at 2, 1.9 and 2.002 in token 2
+        let pool_a = pool(10, 1_000_000_000, 2_000_000_000);
+        let pool_b = pool(11, 1_000_000_000, 1_900_000_000);
+        let pool_c = pool(12, 1_000_000_000, 2_002_000_000);
+
+        //Pool for a different pair is ignored
+        let mut other_pool = pool(13, 1_000_000_000, 1_000_000_000);
+        other_pool.token_b = H160::from_low_u64_be(3);
+
+        let opportunities =
+            find_arb_opportunities(&[pool_a.clone(), pool_b.clone(), pool_c, other_pool], 50);
+
+        //A and C are 10 bps apart, less than the 60 bps of fees
+        assert_eq!(opportunities.len(), 2);
+        assert_eq!(opportunities[0].buy_pool, pool_b.address);
+        assert_eq!(opportunities[0].sell_pool, H160::from_low_u64_be(12));
+        assert_eq!(opportunities[0].token, token_1);
+        assert_eq!(opportunities[0].quote_token, token_2);
+        assert!((opportunities[0].spread_bps - 536.84).abs() < 0.01);
+        assert_eq!(opportunities[1].buy_pool, pool_b.address);
+        assert_eq!(opportunities[1].sell_pool, pool_a.address);
+
+        //The gap between A and B is about 463 bps after fees
+        assert!(find_arb_opportunities(&[pool_a, pool_b], 470).is_empty());
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use ethers::types::{H160, U256};
+
+use crate::amm::{AutomatedMarketMaker, AMM};
+
+/// A directed edge of the token graph, swapping `token_in` for `token_out` through `pool`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PoolEdge {
+    pub pool: H160,
+    pub token_in: H160,
+    pub token_out: H160,
+}
+
+/// Adjacency list of tokens to the pools they can be swapped through.
+#[derive(Debug, Clone, Default)]
+pub struct TokenGraph {
+    pub edges: HashMap<H160, Vec<PoolEdge>>,
+}
+
+impl TokenGraph {
+    /// Returns the edges leaving `token`, or an empty slice if the token is not in the graph.
+    pub fn edges_from(&self, token: H160) -> &[PoolEdge] {
+        self.edges
+            .get(&token)
+            .map(|edges| edges.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn contains_token(&self, token: H160) -> bool {
+        self.edges.contains_key(&token)
+    }
+
+    pub fn token_count(&self) -> usize {
+        self.edges.len()
+    }
+}
+
+/// Builds the token graph of `amms`, with an edge in each direction between every pair of tokens of an AMM.
+pub fn build_token_graph(amms: &[AMM]) -> TokenGraph {
+    let mut graph = TokenGraph::default();
+
+    for amm in amms {
+        let pool = amm.address();
+        let tokens = amm.tokens();
+
+        for &token_in in tokens.iter() {
+            for &token_out in tokens.iter() {
+                if token_in == token_out {
+                    continue;
+                }
+
+                graph.edges.entry(token_in).or_default().push(PoolEdge {
+                    pool,
+                    token_in,
+                    token_out,
+                });
+            }
+        }
+    }
+
+    graph
+}
+
+/// Returns every path of at most `max_hops` edges from `from` to `to`, ordered by hop count. Paths never revisit a token, so each pool is used at most once per path.
+/// Paths are expanded with a breadth first search bounded by `max_hops`, each queued path carrying its own set of visited tokens.
+/// Tokens that can not reach `to` within the remaining hops are pruned, using hop distances to `to` computed by a first search in `O(V + E)`.
+/// Every simple path within the hop limit is still returned, so with `d` edges per token the output, and the time to build it, grows as `O(d^max_hops)`. Keep `max_hops` small on dense graphs.
+pub fn find_paths(graph: &TokenGraph, from: H160, to: H160, max_hops: usize) -> Vec<Vec<PoolEdge>> {
+    let mut paths = vec![];
+
+    if from == to {
+        return paths;
+    }
+
+    let distances = hop_distances(graph, to, max_hops);
+
+    let mut queue = VecDeque::from([(from, vec![], HashSet::from([from]))]);
+    while let Some((token, path, visited_tokens)) = queue.pop_front() {
+        let remaining_hops = max_hops - path.len();
+
+        for edge in graph.edges_from(token) {
+            if visited_tokens.contains(&edge.token_out) {
+                continue;
+            }
+
+            //Skip tokens that can not reach `to` with the hops left after this edge
+            match distances.get(&edge.token_out) {
+                Some(distance) if *distance < remaining_hops => {}
+                _ => continue,
+            }
+
+            let mut next_path = path.clone();
+            next_path.push(*edge);
+
+            if edge.token_out == to {
+                paths.push(next_path);
+            } else {
+                let mut next_visited_tokens = visited_tokens.clone();
+                next_visited_tokens.insert(edge.token_out);
+                queue.push_back((edge.token_out, next_path, next_visited_tokens));
+            }
+        }
+    }
+
+    paths
+}
+
+//Returns the number of hops from each token to `to`, for tokens within `max_hops` of it
+//Edges are added in both directions, so the distances from `to` are also the distances to it
+fn hop_distances(graph: &TokenGraph, to: H160, max_hops: usize) -> HashMap<H160, usize> {
+    let mut distances = HashMap::from([(to, 0)]);
+    let mut queue = VecDeque::from([to]);
+
+    while let Some(token) = queue.pop_front() {
+        let distance = distances[&token];
+        if distance == max_hops {
+            continue;
+        }
+
+        for edge in graph.edges_from(token) {
+            if !distances.contains_key(&edge.token_out) {
+                distances.insert(edge.token_out, distance + 1);
+                queue.push_back(edge.token_out);
+            }
+        }
+    }
+
+    distances
+}
+
+/// Simulates `amount_in` through each of `paths` and returns the path with the highest output along with the output amount.
+/// Paths through pools missing from `pools`, or that fail to simulate, are skipped. Returns `None` if no path can be simulated.
+pub fn best_route<A: AutomatedMarketMaker>(
+    paths: &[Vec<PoolEdge>],
+    pools: &HashMap<H160, A>,
+    amount_in: U256,
+) -> Option<(Vec<PoolEdge>, U256)> {
+    best_path(paths, |pool| pools.get(&pool), amount_in)
+}
+
+//Same as `best_route`, with the pools looked up through `get_pool`
+pub(crate) fn best_path<'a, A: AutomatedMarketMaker + 'a>(
+    paths: &[Vec<PoolEdge>],
+    get_pool: impl Fn(H160) -> Option<&'a A>,
+    amount_in: U256,
+) -> Option<(Vec<PoolEdge>, U256)> {
+    let mut best_route: Option<(Vec<PoolEdge>, U256)> = None;
+
+    for path in paths {
+        let amount_out = match simulate_path(path, &get_pool, amount_in) {
+            Some(amount_out) => amount_out,
+            None => continue,
+        };
+
+        let is_better = match &best_route {
+            Some((_, best_amount_out)) => amount_out > *best_amount_out,
+            None => true,
+        };
+
+        if is_better {
+            best_route = Some((path.clone(), amount_out));
+        }
+    }
+
+    best_route
+}
+
+//Swaps through each edge in the direction of the edge, returns None if the path can not be simulated
+fn simulate_path<'a, A: AutomatedMarketMaker + 'a>(
+    path: &[PoolEdge],
+    get_pool: &impl Fn(H160) -> Option<&'a A>,
+    amount_in: U256,
+) -> Option<U256> {
+    if path.is_empty() {
+        return None;
+    }
+
+    let mut amount = amount_in;
+    for edge in path {
+        let pool = get_pool(edge.pool)?;
+
+        if !pool.contains_token(edge.token_in)
+            || pool.get_token_out(edge.token_in) != edge.token_out
+        {
+            return None;
+        }
+
+        amount = pool.simulate_swap(edge.token_in, amount).ok()?;
+        if amount.is_zero() {
+            return None;
+        }
+    }
+
+    Some(amount)
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use std::collections::HashMap;
+
+    use ethers::types::{H160, U256};
+
+    use crate::amm::{uniswap_v2::UniswapV2Pool, AutomatedMarketMaker, AMM};
+
+    use super::{best_route, build_token_graph, find_paths, PoolEdge};
+
+    #[test]
+    fn test_build_token_graph() {
+        let token_a = H160::from_low_u64_be(100);
+        let token_b = H160::from_low_u64_be(200);
+        let token_c = H160::from_low_u64_be(300);
+        let pool_ab = H160::from_low_u64_be(1);
+        let pool_bc = H160::from_low_u64_be(2);
+
+        let amms = vec![
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: pool_ab,
+                token_a,
+                token_b,
+                ..Default::default()
+            }),
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: pool_bc,
+                token_a: token_b,
+                token_b: token_c,
+                ..Default::default()
+            }),
+        ];
+
+        let graph = build_token_graph(&amms);
+
+        assert_eq!(graph.token_count(), 3);
+        assert_eq!(
+            graph.edges_from(token_a),
+            &[PoolEdge {
+                pool: pool_ab,
+                token_in: token_a,
+                token_out: token_b,
+            }]
+        );
+        assert_eq!(
+            graph.edges_from(token_b),
+            &[
+                PoolEdge {
+                    pool: pool_ab,
+                    token_in: token_b,
+                    token_out: token_a,
+                },
+                PoolEdge {
+                    pool: pool_bc,
+                    token_in: token_b,
+                    token_out: token_c,
+                },
+            ]
+        );
+        assert!(graph.edges_from(H160::from_low_u64_be(400)).is_empty());
+    }
+
+    #[test]
+    fn test_find_paths() {
+        let token_a = H160::from_low_u64_be(100);
+        let token_b = H160::from_low_u64_be(200);
+        let token_c = H160::from_low_u64_be(300);
+
+        let pool = |address: u64, token_a: H160, token_b: H160| {
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: H160::from_low_u64_be(address),
+                token_a,
+                token_b,
+                ..Default::default()
+            })
+        };
+
+        let graph = build_token_graph(&[
+            pool(1, token_a, token_b),
+            pool(2, token_a, token_c),
+            pool(3, token_c, token_b),
+        ]);
+
+        let edge = |pool: u64, token_in: H160, token_out: H160| PoolEdge {
+            pool: H160::from_low_u64_be(pool),
+            token_in,
+            token_out,
+        };
+
+        let paths = find_paths(&graph, token_a, token_b, 2);
+        assert_eq!(
+            paths,
+            vec![
+                vec![edge(1, token_a, token_b)],
+                vec![edge(2, token_a, token_c), edge(3, token_c, token_b)],
+            ]
+        );
+
+        assert_eq!(find_paths(&graph, token_a, token_b, 1).len(), 1);
+        assert_eq!(
+            find_paths(&graph, token_a, token_b, 0),
+            Vec::<Vec<PoolEdge>>::new()
+        );
+        assert!(find_paths(&graph, token_a, token_a, 3).is_empty());
+        assert!(find_paths(&graph, token_a, H160::from_low_u64_be(400), 3).is_empty());
+    }
+
+    #[test]
+    fn test_best_route() {
+        let token_a = H160::from_low_u64_be(100);
+        let token_b = H160::from_low_u64_be(200);
+        let token_c = H160::from_low_u64_be(300);
+
+        let pool =
+            |address: u64, token_a: H160, token_b: H160, reserve_0: u128, reserve_1: u128| {
+                UniswapV2Pool {
+                    address: H160::from_low_u64_be(address),
+                    token_a,
+                    token_a_decimals: 18,
+                    token_b,
+                    token_b_decimals: 18,
+                    reserve_0,
+                    reserve_1,
+                    fee: 300,
+                    ..Default::default()
+                }
+            };
+
+        let pools = vec![
+            //Direct pool at a price of 1 B per A
+            pool(
+                1,
+                token_a,
+                token_b,
+                1000000000000000000000,
+                1000000000000000000000,
+            ),
+            //Route through C at a price of 4 B per A, with pool 3 quoted as B/C to exercise the edge direction
+            pool(
+                2,
+                token_a,
+                token_c,
+                1000000000000000000000,
+                2000000000000000000000,
+            ),
+            pool(
+                3,
+                token_b,
+                token_c,
+                2000000000000000000000,
+                1000000000000000000000,
+            ),
+        ];
+
+        let graph = build_token_graph(
+            &pools
+                .iter()
+                .cloned()
+                .map(AMM::UniswapV2Pool)
+                .collect::<Vec<AMM>>(),
+        );
+        let paths = find_paths(&graph, token_a, token_b, 2);
+        let pools = pools
+            .into_iter()
+            .map(|pool| (pool.address, pool))
+            .collect::<HashMap<H160, UniswapV2Pool>>();
+
+        let amount_in = U256::from(1000000000000000000_u128);
+        let (path, amount_out) = best_route(&paths, &pools, amount_in).expect("Route should exist");
+
+        let amount_c = pools[&H160::from_low_u64_be(2)]
+            .simulate_swap(token_a, amount_in)
+            .expect("Swap should simulate");
+        let expected_amount_out = pools[&H160::from_low_u64_be(3)]
+            .simulate_swap(token_c, amount_c)
+            .expect("Swap should simulate");
+
+        assert_eq!(
+            path.iter().map(|edge| edge.pool).collect::<Vec<H160>>(),
+            vec![H160::from_low_u64_be(2), H160::from_low_u64_be(3)]
+        );
+        assert_eq!(amount_out, expected_amount_out);
+
+        //Paths through unknown pools are skipped
+        let mut pools = pools;
+        pools.remove(&H160::from_low_u64_be(1));
+        pools.remove(&H160::from_low_u64_be(3));
+        assert!(best_route(&paths, &pools, amount_in).is_none());
+    }
+}
@@ -0,0 +1,300 @@
+#[cfg(feature = "uniswap-v2")]
+pub mod arbitrage;
+pub mod graph;
+#[cfg(feature = "uniswap-v2")]
+pub mod split;
+
+use std::collections::HashMap;
+
+use ethers::types::{H160, U256};
+
+use crate::{
+    amm::{AutomatedMarketMaker, AMM},
+    errors::SwapSimulationError,
+};
+
+use self::graph::{best_path, build_token_graph, find_paths};
+
+/// Placeholder address for the native token of the chain (ie. ETH), following the convention used by most aggregators.
+pub const NATIVE_TOKEN: H160 = H160([
+    238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238, 238,
+    238,
+]);
+
+/// A path of swaps through one or more AMMs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    /// Addresses of the AMMs swapped through, in order
+    pub pools: Vec<H160>,
+    /// Tokens along the route, starting with the input token and ending with the output token
+    pub tokens: Vec<H160>,
+    /// Simulated amount of the output token
+    pub amount_out: U256,
+}
+
+/// Returns the route from `token_in` to `token_out` that maximizes the amount out for `amount_in`, using at most `max_hops` AMMs.
+/// Candidate paths are found with `find_paths` over the token graph of `amms`, then each path is simulated against the current AMM state.
+/// Each token and AMM is visited at most once per path. Returns `None` if no path exists.
+pub fn best_route(
+    amms: &[AMM],
+    token_in: H160,
+    token_out: H160,
+    amount_in: U256,
+    max_hops: usize,
+) -> Option<Route> {
+    let graph = build_token_graph(amms);
+    let paths = find_paths(&graph, token_in, token_out, max_hops);
+
+    let amms_by_address = amms
+        .iter()
+        .map(|amm| (amm.address(), amm))
+        .collect::<HashMap<H160, &AMM>>();
+    let (path, amount_out) = best_path(
+        &paths,
+        |pool| amms_by_address.get(&pool).copied(),
+        amount_in,
+    )?;
+
+    let mut tokens = vec![token_in];
+    tokens.extend(path.iter().map(|edge| edge.token_out));
+
+    Some(Route {
+        pools: path.iter().map(|edge| edge.pool).collect(),
+        tokens,
+        amount_out,
+    })
+}
+
+/// Simulates swapping `amount_in` of `token_in` through `amms` in order, returning the amount of the final output token.
+pub fn simulate_route(
+    amms: &[AMM],
+    token_in: H160,
+    amount_in: U256,
+) -> Result<U256, SwapSimulationError> {
+    let mut token_in = token_in;
+    let mut amount_in = amount_in;
+
+    for amm in amms {
+        amount_in = amm.simulate_swap(token_in, amount_in)?;
+        token_in = amm.get_token_out(token_in);
+    }
+
+    Ok(amount_in)
+}
+
+/// Same as `simulate_route`, but `token_in` can be `NATIVE_TOKEN`, in which case it is wrapped into `weth` at 1:1 with no fee before the first swap.
+/// Unwrapping a WETH output is also 1:1, so the amount out is the same whether or not the caller unwraps it.
+pub fn simulate_route_with_native(
+    amms: &[AMM],
+    weth: H160,
+    token_in: H160,
+    amount_in: U256,
+) -> Result<U256, SwapSimulationError> {
+    simulate_route(amms, wrap_native(token_in, weth), amount_in)
+}
+
+/// Same as `best_route`, but `token_in` and `token_out` can be `NATIVE_TOKEN`, which is treated as `weth` with a 1:1 wrap or unwrap step.
+/// The tokens of the returned route start or end with `NATIVE_TOKEN` accordingly, while the pools only contain the AMMs swapped through.
+pub fn best_route_with_native(
+    amms: &[AMM],
+    weth: H160,
+    token_in: H160,
+    token_out: H160,
+    amount_in: U256,
+    max_hops: usize,
+) -> Option<Route> {
+    let mut route = best_route(
+        amms,
+        wrap_native(token_in, weth),
+        wrap_native(token_out, weth),
+        amount_in,
+        max_hops,
+    )?;
+
+    if let Some(first_token) = route.tokens.first_mut() {
+        *first_token = token_in;
+    }
+    if let Some(last_token) = route.tokens.last_mut() {
+        *last_token = token_out;
+    }
+
+    Some(route)
+}
+
+fn wrap_native(token: H160, weth: H160) -> H160 {
+    if token == NATIVE_TOKEN {
+        weth
+    } else {
+        token
+    }
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use ethers::types::{H160, U256};
+
+    use crate::amm::{erc_4626::ERC4626Vault, uniswap_v2::UniswapV2Pool, AMM};
+
+    use super::{
+        best_route, best_route_with_native, simulate_route, simulate_route_with_native,
+        NATIVE_TOKEN,
+    };
+
+    fn v2_pool(
+        address: u64,
+        token_a: H160,
+        token_b: H160,
+        reserve_0: u128,
+        reserve_1: u128,
+    ) -> AMM {
+        AMM::UniswapV2Pool(UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            token_a,
+            token_a_decimals: 18,
+            token_b,
+            token_b_decimals: 18,
+            reserve_0,
+            reserve_1,
+            fee: 300,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn test_best_route() -> eyre::Result<()> {
+        let token_a = H160::from_low_u64_be(100);
+        let token_b = H160::from_low_u64_be(200);
+        let token_c = H160::from_low_u64_be(300);
+
+        let amms = vec![
+            //Direct pool at a price of 1 B per A
+            v2_pool(
+                1,
+                token_a,
+                token_b,
+                1000000000000000000000,
+                1000000000000000000000,
+            ),
+            //Route through C at a price of 4 B per A
+            v2_pool(
+                2,
+                token_a,
+                token_c,
+                1000000000000000000000,
+                2000000000000000000000,
+            ),
+            v2_pool(
+                3,
+                token_c,
+                token_b,
+                1000000000000000000000,
+                2000000000000000000000,
+            ),
+        ];
+
+        let amount_in = U256::from(1000000000000000000_u128);
+
+        let route = best_route(&amms, token_a, token_b, amount_in, 3).expect("Route should exist");
+        assert_eq!(
+            route.pools,
+            vec![H160::from_low_u64_be(2), H160::from_low_u64_be(3)]
+        );
+        assert_eq!(route.tokens, vec![token_a, token_c, token_b]);
+        assert_eq!(
+            route.amount_out,
+            simulate_route(&amms[1..], token_a, amount_in)?
+        );
+
+        //With a single hop only the direct pool can be used
+        let route = best_route(&amms, token_a, token_b, amount_in, 1).expect("Route should exist");
+        assert_eq!(route.pools, vec![H160::from_low_u64_be(1)]);
+
+        assert!(best_route(&amms, token_a, H160::from_low_u64_be(400), amount_in, 3).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_best_route_through_vault() -> eyre::Result<()> {
+        let token_a = H160::from_low_u64_be(100);
+        let asset = H160::from_low_u64_be(200);
+        let vault_token = H160::from_low_u64_be(300);
+
+        //Buying the vault shares takes a swap into the asset and a deposit into the vault
+        let amms = vec![
+            v2_pool(
+                1,
+                token_a,
+                asset,
+                1000000000000000000000,
+                1000000000000000000000,
+            ),
+            AMM::ERC4626Vault(ERC4626Vault {
+                vault_token,
+                vault_token_decimals: 18,
+                asset_token: asset,
+                asset_token_decimals: 18,
+                vault_reserve: U256::from(1000000000000000000000_u128),
+                asset_reserve: U256::from(2000000000000000000000_u128),
+                ..Default::default()
+            }),
+        ];
+
+        let amount_in = U256::from(1000000000000000000_u128);
+
+        let route =
+            best_route(&amms, token_a, vault_token, amount_in, 2).expect("Route should exist");
+        assert_eq!(route.pools, vec![H160::from_low_u64_be(1), vault_token]);
+        assert_eq!(route.tokens, vec![token_a, asset, vault_token]);
+        assert_eq!(route.amount_out, simulate_route(&amms, token_a, amount_in)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_route_with_native() -> eyre::Result<()> {
+        let weth = H160::from_low_u64_be(100);
+        let token_b = H160::from_low_u64_be(200);
+        let token_c = H160::from_low_u64_be(300);
+
+        let amms = vec![
+            v2_pool(
+                1,
+                weth,
+                token_b,
+                1000000000000000000000,
+                2000000000000000000000,
+            ),
+            v2_pool(
+                2,
+                token_b,
+                token_c,
+                1000000000000000000000,
+                1000000000000000000000,
+            ),
+        ];
+
+        let amount_in = U256::from(1000000000000000000_u128);
+
+        //Native to token to token is the same as starting from WETH
+        assert_eq!(
+            simulate_route_with_native(&amms, weth, NATIVE_TOKEN, amount_in)?,
+            simulate_route(&amms, weth, amount_in)?
+        );
+
+        let route = best_route_with_native(&amms, weth, NATIVE_TOKEN, token_c, amount_in, 3)
+            .expect("Route should exist");
+        assert_eq!(route.tokens, vec![NATIVE_TOKEN, token_b, token_c]);
+        assert_eq!(
+            route.pools,
+            vec![H160::from_low_u64_be(1), H160::from_low_u64_be(2)]
+        );
+
+        //Selling into native unwraps the WETH output
+        let route = best_route_with_native(&amms, weth, token_c, NATIVE_TOKEN, amount_in, 3)
+            .expect("Route should exist");
+        assert_eq!(route.tokens, vec![token_c, token_b, NATIVE_TOKEN]);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use ethers::types::{H160, U256};
+
+use crate::amm::{math::u256_to_f64, uniswap_v2::UniswapV2Pool, AutomatedMarketMaker};
+
+/// Returns the split of `total_amount_in` of `token_in` across `pools` that maximizes the total amount out, as `(pool, amount_in)` for each pool receiving a part of the input.
+/// The pools are assumed to trade the same pair, pools not containing `token_in` or without reserves are skipped.
+/// The output of a constant product pool is concave in its input, so the optimum equalizes the marginal price of every pool used, pools whose spot price is worse than the resulting marginal price receive nothing.
+/// The split is computed in floating point, the rounding remainder is assigned to the largest part so that the parts sum to `total_amount_in`.
+pub fn optimal_split(
+    pools: &[UniswapV2Pool],
+    token_in: H160,
+    total_amount_in: U256,
+) -> Vec<(H160, U256)> {
+    //(address, reserve in, reserve out, 1 - fee)
+    let mut candidates = pools
+        .iter()
+        .filter(|pool| pool.contains_token(token_in) && pool.fee < pool.fee_denominator)
+        .map(|pool| {
+            let (reserve_in, reserve_out) = if pool.token_a == token_in {
+                (pool.reserve_0, pool.reserve_1)
+            } else {
+                (pool.reserve_1, pool.reserve_0)
+            };
+            let gamma = (pool.fee_denominator - pool.fee) as f64 / pool.fee_denominator as f64;
+
+            (pool.address, reserve_in as f64, reserve_out as f64, gamma)
+        })
+        .filter(|(_, reserve_in, reserve_out, _)| *reserve_in > 0.0 && *reserve_out > 0.0)
+        .collect::<Vec<(H160, f64, f64, f64)>>();
+
+    if total_amount_in.is_zero() || candidates.is_empty() {
+        return vec![];
+    }
+
+    //Sort by marginal price at zero input, best first
+    let marginal_price = |(_, reserve_in, reserve_out, gamma): &(H160, f64, f64, f64)| {
+        gamma * reserve_out / reserve_in
+    };
+    candidates.sort_by(|a, b| marginal_price(b).total_cmp(&marginal_price(a)));
+
+    //With a marginal price `p` common to the pools used, the input of each pool is `sqrt(r_in * r_out / (gamma * p)) - r_in / gamma`
+    //Summing over the pools used and solving for the total input gives `1 / sqrt(p)`
+    let total = u256_to_f64(total_amount_in);
+    let inverse_sqrt_price = |used: &[(H160, f64, f64, f64)]| {
+        let (offset, scale) = used.iter().fold(
+            (0.0, 0.0),
+            |(offset, scale), (_, reserve_in, reserve_out, gamma)| {
+                (
+                    offset + reserve_in / gamma,
+                    scale + (reserve_in * reserve_out / gamma).sqrt(),
+                )
+            },
+        );
+
+        (total + offset) / scale
+    };
+
+    //Add pools while their spot price beats the marginal price of the pools already used
+    let mut used = 1;
+    while used < candidates.len() {
+        let price = inverse_sqrt_price(&candidates[..used]).powi(-2);
+        if marginal_price(&candidates[used]) <= price {
+            break;
+        }
+        used += 1;
+    }
+
+    let inverse_sqrt_price = inverse_sqrt_price(&candidates[..used]);
+    let mut split = candidates[..used]
+        .iter()
+        .map(|(address, reserve_in, reserve_out, gamma)| {
+            let amount_in =
+                (reserve_in * reserve_out / gamma).sqrt() * inverse_sqrt_price - reserve_in / gamma;
+
+            (
+                *address,
+                U256::from(amount_in.max(0.0) as u128).min(total_amount_in),
+            )
+        })
+        .collect::<Vec<(H160, U256)>>();
+
+    //Assign the rounding remainder to the largest part, parts can add up to slightly more than the total if the float math overshoots
+    let allocated = split.iter().fold(U256::zero(), |acc, (_, amount_in)| {
+        acc.saturating_add(*amount_in)
+    });
+    let largest = split
+        .iter()
+        .enumerate()
+        .max_by_key(|(_, (_, amount_in))| *amount_in)
+        .map(|(idx, _)| idx)
+        .unwrap_or_default();
+    if allocated <= total_amount_in {
+        split[largest].1 += total_amount_in - allocated;
+    } else {
+        split[largest].1 -= (allocated - total_amount_in).min(split[largest].1);
+    }
+
+    split.retain(|(_, amount_in)| !amount_in.is_zero());
+    split
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{H160, U256};
+
+    use crate::amm::{uniswap_v2::UniswapV2Pool, AutomatedMarketMaker};
+
+    use super::optimal_split;
+
+    fn pool(address: u64, reserve_0: u128, reserve_1: u128) -> UniswapV2Pool {
+        UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            token_a: H160::from_low_u64_be(100),
+            token_a_decimals: 18,
+            token_b: H160::from_low_u64_be(200),
+            token_b_decimals: 18,
+            reserve_0,
+            reserve_1,
+            fee: 300,
+            ..Default::default()
+        }
+    }
+
+    fn total_amount_out(pools: &[UniswapV2Pool], split: &[(H160, U256)]) -> U256 {
+        split
+            .iter()
+            .map(|(address, amount_in)| {
+                let pool = pools.iter().find(|pool| pool.address == *address).unwrap();
+                pool.simulate_swap(pool.token_a, *amount_in).unwrap()
+            })
+            .fold(U256::zero(), |acc, amount_out| acc + amount_out)
+    }
+
+    #[test]
+    fn test_optimal_split() {
+        let token_in = H160::from_low_u64_be(100);
+        let total_amount_in = U256::from(100000000000000000000_u128);
+
+        //Same price, the input is split in proportion to the reserves
+        let pools = vec![
+            pool(1, 1000000000000000000000, 2000000000000000000000),
+            pool(2, 3000000000000000000000, 6000000000000000000000),
+        ];
+        let split = optimal_split(&pools, token_in, total_amount_in);
+        assert_eq!(split.len(), 2);
+        assert_eq!(split[0].1 + split[1].1, total_amount_in);
+        let small = split
+            .iter()
+            .find(|(address, _)| address.to_low_u64_be() == 1)
+            .unwrap();
+        let (expected, tolerance) = (total_amount_in / 4, U256::from(100000000));
+        assert!(small.1 > expected - tolerance && small.1 < expected + tolerance);
+
+        //Splitting beats routing everything through either pool, and small shifts between the pools
+        let split_out = total_amount_out(&pools, &split);
+        for address in [1, 2] {
+            let single = vec![(H160::from_low_u64_be(address), total_amount_in)];
+            assert!(split_out > total_amount_out(&pools, &single));
+        }
+        let shift = total_amount_in / 100;
+        let shifted = vec![
+            (split[0].0, split[0].1 + shift),
+            (split[1].0, split[1].1 - shift),
+        ];
+        assert!(split_out >= total_amount_out(&pools, &shifted));
+
+        //A pool priced far worse than the marginal price gets nothing
+        let pools = vec![
+            pool(1, 1000000000000000000000, 2000000000000000000000),
+            pool(3, 1000000000000000000000, 1000000000000000000000),
+        ];
+        let split = optimal_split(&pools, token_in, U256::from(1000000000000000000_u128));
+        assert_eq!(
+            split,
+            vec![(
+                H160::from_low_u64_be(1),
+                U256::from(1000000000000000000_u128)
+            )]
+        );
+
+        //Pools without the input token are skipped
+        assert!(optimal_split(&pools, H160::from_low_u64_be(300), total_amount_in).is_empty());
+        assert!(optimal_split(&pools, token_in, U256::zero()).is_empty());
+    }
+}
@@ -8,7 +8,7 @@ use ethers::signers::WalletError;
 use ethers::types::{Block, H160, H256};
 use thiserror::Error;
 
-use super::state::MiddlewarePubsub;
+use super::state::{MiddlewarePubsub, StateSpaceUpdate};
 
 #[derive(Error, Debug)]
 pub enum StateSpaceError<M, P>
@@ -46,6 +46,10 @@ where
     StateChangeSendError(#[from] tokio::sync::mpsc::error::SendError<Vec<H160>>),
     #[error("Could not send block through channel")]
     BlockSendError(#[from] tokio::sync::mpsc::error::SendError<Block<H256>>),
+    #[error("Could not send block through channel")]
+    FlaggedBlockSendError(#[from] tokio::sync::mpsc::error::SendError<(Block<H256>, bool)>),
+    #[error("Could not send state space update through channel")]
+    StateSpaceUpdateSendError(#[from] tokio::sync::mpsc::error::SendError<StateSpaceUpdate>),
     #[error("Already listening for state changes")]
     AlreadyListeningForStateChanges,
 }
@@ -1,6 +1,8 @@
 use std::{
     collections::{HashMap, HashSet},
+    future::Future,
     sync::Arc,
+    time::Duration,
 };
 
 use crate::{
@@ -9,7 +11,7 @@ use crate::{
 };
 use arraydeque::ArrayDeque;
 use ethers::{
-    providers::{Middleware, PubsubClient, StreamExt},
+    providers::{Middleware, ProviderError, PubsubClient, StreamExt},
     types::{Block, Filter, Log, H160, H256},
 };
 use tokio::{
@@ -74,7 +76,9 @@ where
 
         for amm in self.state.read().await.values() {
             let variant = match amm {
+                #[cfg(feature = "uniswap-v2")]
                 AMM::UniswapV2Pool(_) => 0,
+                #[cfg(feature = "uniswap-v3")]
                 AMM::UniswapV3Pool(_) => 1,
                 AMM::ERC4626Vault(_) => 2,
             };
@@ -131,56 +135,18 @@ where
         let new_block_handle: JoinHandle<Result<(), StateSpaceError<M, P>>> =
             tokio::spawn(async move {
                 while let Some(block) = stream_rx.recv().await {
-                    if let Some(chain_head_block_number) = block.number {
-                        let chain_head_block_number = chain_head_block_number.as_u64();
-
-                        //If there is a reorg, unwind state changes from last_synced block to the chain head block number
-                        if chain_head_block_number <= last_synced_block {
-                            unwind_state_changes(
-                                state.clone(),
-                                state_change_cache.clone(),
-                                chain_head_block_number,
-                            )
-                            .await?;
-
-                            last_synced_block = chain_head_block_number - 1;
-                        }
-
-                        let from_block: u64 = last_synced_block + 1;
-                        let logs = middleware
-                            .get_logs(
-                                &filter
-                                    .clone()
-                                    .from_block(from_block)
-                                    .to_block(chain_head_block_number),
-                            )
-                            .await
-                            .map_err(StateSpaceError::MiddlewareError)?;
-
-                        if logs.is_empty() {
-                            for block_number in from_block..=chain_head_block_number {
-                                add_state_change_to_cache(
-                                    state_change_cache.clone(),
-                                    StateChange::new(None, block_number),
-                                )
-                                .await?;
-                            }
-                        } else {
-                            handle_state_changes_from_logs(
-                                state.clone(),
-                                state_change_cache.clone(),
-                                logs,
-                                middleware.clone(),
-                            )
-                            .await?;
-                        }
-
-                        last_synced_block = chain_head_block_number;
-
-                        new_block_tx.send(block).await?;
-                    } else {
-                        return Err(StateSpaceError::BlockNumberNotFound);
-                    }
+                    Self::handle_block(
+                        &block,
+                        &mut last_synced_block,
+                        &filter,
+                        u64::MAX,
+                        state.clone(),
+                        state_change_cache.clone(),
+                        middleware.clone(),
+                    )
+                    .await?;
+
+                    new_block_tx.send(block).await?;
                 }
 
                 Ok::<(), StateSpaceError<M, P>>(())
@@ -232,56 +198,18 @@ where
         let updated_amms_handle: JoinHandle<Result<(), StateSpaceError<M, P>>> =
             tokio::spawn(async move {
                 while let Some(block) = stream_rx.recv().await {
-                    if let Some(chain_head_block_number) = block.number {
-                        let chain_head_block_number = chain_head_block_number.as_u64();
-
-                        //If there is a reorg, unwind state changes from last_synced block to the chain head block number
-                        if chain_head_block_number <= last_synced_block {
-                            unwind_state_changes(
-                                state.clone(),
-                                state_change_cache.clone(),
-                                chain_head_block_number,
-                            )
-                            .await?;
-
-                            //set the last synced block to the head block number
-                            last_synced_block = chain_head_block_number - 1;
-                        }
-
-                        let from_block: u64 = last_synced_block + 1;
-                        let logs = middleware
-                            .get_logs(
-                                &filter
-                                    .clone()
-                                    .from_block(from_block)
-                                    .to_block(chain_head_block_number),
-                            )
-                            .await
-                            .map_err(StateSpaceError::MiddlewareError)?;
-
-                        if logs.is_empty() {
-                            for block_number in from_block..=chain_head_block_number {
-                                add_state_change_to_cache(
-                                    state_change_cache.clone(),
-                                    StateChange::new(None, block_number),
-                                )
-                                .await?;
-                            }
-                        } else {
-                            let amms_updated = handle_state_changes_from_logs(
-                                state.clone(),
-                                state_change_cache.clone(),
-                                logs,
-                                middleware.clone(),
-                            )
-                            .await?;
-
-                            amms_updated_tx.send(amms_updated).await?;
-                        }
-
-                        last_synced_block = chain_head_block_number;
-                    } else {
-                        return Err(StateSpaceError::BlockNumberNotFound);
+                    if let Some(amms_updated) = Self::handle_block(
+                        &block,
+                        &mut last_synced_block,
+                        &filter,
+                        u64::MAX,
+                        state.clone(),
+                        state_change_cache.clone(),
+                        middleware.clone(),
+                    )
+                    .await?
+                    {
+                        amms_updated_tx.send(amms_updated).await?;
                     }
                 }
 
@@ -325,61 +253,214 @@ where
         let new_block_handle: JoinHandle<Result<(), StateSpaceError<M, P>>> =
             tokio::spawn(async move {
                 while let Some(block) = stream_rx.recv().await {
-                    if let Some(chain_head_block_number) = block.number {
-                        let chain_head_block_number = chain_head_block_number.as_u64();
-
-                        //If there is a reorg, unwind state changes from last_synced block to the chain head block number
-                        if chain_head_block_number <= last_synced_block {
-                            unwind_state_changes(
-                                state.clone(),
-                                state_change_cache.clone(),
-                                chain_head_block_number,
-                            )
-                            .await?;
+                    Self::handle_block(
+                        &block,
+                        &mut last_synced_block,
+                        &filter,
+                        u64::MAX,
+                        state.clone(),
+                        state_change_cache.clone(),
+                        middleware.clone(),
+                    )
+                    .await?;
+                }
+
+                Ok::<(), StateSpaceError<M, P>>(())
+            });
+
+        Ok(vec![stream_handle, new_block_handle])
+    }
 
-                            last_synced_block = chain_head_block_number - 1;
+    /// Same as `listen_for_state_changes`, but reconnects up to `max_reconnects` times in a row when the block stream ends, waiting `reconnect_delay` between attempts.
+    /// Each attempt builds a new stream middleware with `connect` and resubscribes to new blocks on it, so a dropped connection is replaced rather than reused.
+    /// Logs of the blocks missed while disconnected are fetched in ranges of at most `step` blocks with the first block after the reconnection, which is preceded by a `StateSpaceUpdate::Reconnected` marker.
+    pub async fn listen_for_state_changes_with_reconnect<F, Fut>(
+        &self,
+        mut last_synced_block: u64,
+        channel_buffer: usize,
+        step: u64,
+        max_reconnects: usize,
+        reconnect_delay: Duration,
+        connect: F,
+    ) -> Result<
+        (
+            Receiver<StateSpaceUpdate>,
+            Vec<JoinHandle<Result<(), StateSpaceError<M, P>>>>,
+        ),
+        StateSpaceError<M, P>,
+    >
+    where
+        <P as Middleware>::Provider: PubsubClient,
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Arc<P>, ProviderError>> + Send,
+    {
+        let state = self.state.clone();
+        let middleware = self.middleware.clone();
+        let mut stream_middleware: Arc<P> = self.stream_middleware.clone();
+        let filter = self.get_block_filter().await;
+
+        //Blocks are sent along with whether they are the first block after a reconnection
+        let (stream_tx, mut stream_rx): (
+            Sender<(Block<H256>, bool)>,
+            Receiver<(Block<H256>, bool)>,
+        ) = tokio::sync::mpsc::channel(channel_buffer);
+
+        let stream_handle = tokio::spawn(async move {
+            let mut reconnects = 0;
+            let mut reconnected = false;
+
+            loop {
+                match stream_middleware.subscribe_blocks().await {
+                    Ok(mut block_stream) => {
+                        while let Some(block) = block_stream.next().await {
+                            stream_tx.send((block, reconnected)).await?;
+                            reconnected = false;
+                            reconnects = 0;
                         }
+                    }
 
-                        let from_block: u64 = last_synced_block + 1;
-                        let logs = middleware
-                            .get_logs(
-                                &filter
-                                    .clone()
-                                    .from_block(from_block)
-                                    .to_block(chain_head_block_number),
-                            )
-                            .await
-                            .map_err(StateSpaceError::MiddlewareError)?;
-
-                        if logs.is_empty() {
-                            for block_number in from_block..=chain_head_block_number {
-                                add_state_change_to_cache(
-                                    state_change_cache.clone(),
-                                    StateChange::new(None, block_number),
-                                )
-                                .await?;
-                            }
-                        } else {
-                            handle_state_changes_from_logs(
-                                state.clone(),
-                                state_change_cache.clone(),
-                                logs,
-                                middleware.clone(),
-                            )
+                    //Only the first subscription has to succeed, failed resubscriptions count as reconnection attempts
+                    Err(e) if !reconnected => return Err(StateSpaceError::PubsubClientError(e)),
+                    Err(_) => {}
+                }
+
+                //Replace the stream middleware, retrying until a connection succeeds or the attempts run out
+                loop {
+                    if reconnects >= max_reconnects {
+                        return Ok::<(), StateSpaceError<M, P>>(());
+                    }
+
+                    reconnects += 1;
+                    reconnected = true;
+                    tokio::time::sleep(reconnect_delay).await;
+
+                    if let Ok(new_stream_middleware) = connect().await {
+                        stream_middleware = new_stream_middleware;
+                        break;
+                    }
+                }
+            }
+        });
+
+        let (updates_tx, updates_rx) = tokio::sync::mpsc::channel(channel_buffer);
+
+        let state_change_cache = self.state_change_cache.clone();
+
+        let updated_amms_handle: JoinHandle<Result<(), StateSpaceError<M, P>>> =
+            tokio::spawn(async move {
+                while let Some((block, reconnected)) = stream_rx.recv().await {
+                    if reconnected {
+                        let chain_head_block_number = block
+                            .number
+                            .ok_or(StateSpaceError::BlockNumberNotFound)?
+                            .as_u64();
+
+                        updates_tx
+                            .send(StateSpaceUpdate::Reconnected {
+                                missed_blocks: chain_head_block_number
+                                    .saturating_sub(last_synced_block + 1),
+                            })
                             .await?;
-                        }
+                    }
 
-                        last_synced_block = chain_head_block_number;
-                    } else {
-                        return Err(StateSpaceError::BlockNumberNotFound);
+                    //After a reconnection, the range also covers the blocks missed during the outage
+                    if let Some(amms_updated) = Self::handle_block(
+                        &block,
+                        &mut last_synced_block,
+                        &filter,
+                        step,
+                        state.clone(),
+                        state_change_cache.clone(),
+                        middleware.clone(),
+                    )
+                    .await?
+                    {
+                        updates_tx
+                            .send(StateSpaceUpdate::AMMsUpdated(amms_updated))
+                            .await?;
                     }
                 }
 
                 Ok::<(), StateSpaceError<M, P>>(())
             });
 
-        Ok(vec![stream_handle, new_block_handle])
+        Ok((updates_rx, vec![stream_handle, updated_amms_handle]))
     }
+
+    //Unwinds the state changes if the block reorged the chain, then applies the logs from the block after `last_synced_block` up to the block and moves `last_synced_block` to it
+    //Logs are fetched in ranges of at most `step` blocks. Returns the AMMs updated by the logs, or `None` if there were no logs
+    async fn handle_block(
+        block: &Block<H256>,
+        last_synced_block: &mut u64,
+        filter: &Filter,
+        step: u64,
+        state: Arc<RwLock<StateSpace>>,
+        state_change_cache: Arc<RwLock<StateChangeCache>>,
+        middleware: Arc<M>,
+    ) -> Result<Option<Vec<H160>>, StateSpaceError<M, P>> {
+        let chain_head_block_number = block
+            .number
+            .ok_or(StateSpaceError::BlockNumberNotFound)?
+            .as_u64();
+
+        //If there is a reorg, unwind state changes from last_synced block to the chain head block number
+        if chain_head_block_number <= *last_synced_block {
+            unwind_state_changes(
+                state.clone(),
+                state_change_cache.clone(),
+                chain_head_block_number,
+            )
+            .await?;
+
+            //set the last synced block to the head block number
+            *last_synced_block = chain_head_block_number - 1;
+        }
+
+        let from_block: u64 = *last_synced_block + 1;
+        let mut logs = vec![];
+        let mut range_start = from_block;
+        while range_start <= chain_head_block_number {
+            let range_end =
+                chain_head_block_number.min(range_start.saturating_add(step.max(1) - 1));
+
+            logs.extend(
+                middleware
+                    .get_logs(&filter.clone().from_block(range_start).to_block(range_end))
+                    .await
+                    .map_err(StateSpaceError::MiddlewareError)?,
+            );
+
+            range_start = range_end + 1;
+        }
+
+        *last_synced_block = chain_head_block_number;
+
+        if logs.is_empty() {
+            for block_number in from_block..=chain_head_block_number {
+                add_state_change_to_cache(
+                    state_change_cache.clone(),
+                    StateChange::new(None, block_number),
+                )
+                .await?;
+            }
+
+            Ok(None)
+        } else {
+            let amms_updated =
+                handle_state_changes_from_logs(state, state_change_cache, logs, middleware).await?;
+
+            Ok(Some(amms_updated))
+        }
+    }
+}
+
+/// Update sent by `listen_for_state_changes_with_reconnect`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateSpaceUpdate {
+    /// Addresses of the AMMs that incurred a state change in the block.
+    AMMsUpdated(Vec<H160>),
+    /// The block stream was resubscribed after it ended, `missed_blocks` being the number of blocks produced while disconnected.
+    Reconnected { missed_blocks: u64 },
 }
 
 pub fn initialize_state_space(amms: Vec<AMM>) -> StateSpace {
@@ -532,20 +613,20 @@ pub fn get_block_number_from_log(log: &Log) -> Result<u64, EventLogError> {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "uniswap-v2"))]
 mod tests {
     use std::{default, sync::Arc};
 
     use crate::amm::{uniswap_v2::UniswapV2Pool, AMM};
     use ethers::{
-        providers::{Http, Provider, Ws},
-        types::H160,
+        providers::{Http, JsonRpcClient, MockProvider, Provider, Ws},
+        types::{Block, Filter, Log, H160, H256, U64},
     };
     use tokio::sync::RwLock;
 
     use super::StateSpaceManager;
     use crate::state_space::state::{
-        add_state_change_to_cache, unwind_state_changes, StateChange, StateChangeCache,
+        add_state_change_to_cache, unwind_state_changes, StateChange, StateChangeCache, StateSpace,
     };
 
     #[tokio::test]
@@ -622,6 +703,45 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_handle_block_fetches_logs_in_steps() -> eyre::Result<()> {
+        let (middleware, mock) = Provider::mocked();
+        let state = Arc::new(RwLock::new(StateSpace::new()));
+        let state_change_cache = Arc::new(RwLock::new(StateChangeCache::new()));
+
+        //Blocks 1 to 250 are fetched in ranges of 100 blocks, each range without logs
+        for _ in 0..3 {
+            mock.push::<Vec<Log>, _>(vec![])?;
+        }
+
+        let block = Block::<H256> {
+            number: Some(U64::from(250)),
+            ..Default::default()
+        };
+        let mut last_synced_block = 0;
+        let amms_updated = StateSpaceManager::<Provider<MockProvider>, Provider<Ws>>::handle_block(
+            &block,
+            &mut last_synced_block,
+            &Filter::new(),
+            100,
+            state,
+            state_change_cache,
+            Arc::new(middleware),
+        )
+        .await?;
+
+        assert_eq!(amms_updated, None);
+        assert_eq!(last_synced_block, 250);
+
+        //Every queued response was consumed by a range
+        assert!(mock
+            .request::<_, Vec<Log>>("eth_getLogs", ())
+            .await
+            .is_err());
+
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_add_empty_state_changes() -> eyre::Result<()> {
         let last_synced_block = 0;
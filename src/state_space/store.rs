@@ -0,0 +1,152 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ethers::types::{H160, U256};
+use tokio::sync::RwLock;
+
+use crate::amm::{AutomatedMarketMaker, AMM};
+
+use super::state::StateSpace;
+
+/// A pool set that can be shared between tasks, reads and updates of each pool are applied atomically.
+#[derive(Debug, Clone, Default)]
+pub struct SharedPoolStore {
+    state: Arc<RwLock<StateSpace>>,
+}
+
+impl SharedPoolStore {
+    pub fn new(amms: Vec<AMM>) -> Self {
+        let state = amms
+            .into_iter()
+            .map(|amm| (amm.address(), amm))
+            .collect::<HashMap<H160, AMM>>();
+
+        SharedPoolStore {
+            state: Arc::new(RwLock::new(state)),
+        }
+    }
+
+    /// Creates a store backed by an existing state, ie. the state of a `StateSpaceManager`.
+    pub fn from_state(state: Arc<RwLock<StateSpace>>) -> Self {
+        SharedPoolStore { state }
+    }
+
+    pub fn state(&self) -> Arc<RwLock<StateSpace>> {
+        self.state.clone()
+    }
+
+    pub async fn get(&self, address: H160) -> Option<AMM> {
+        self.state.read().await.get(&address).cloned()
+    }
+
+    pub async fn insert(&self, amm: AMM) -> Option<AMM> {
+        self.state.write().await.insert(amm.address(), amm)
+    }
+
+    /// Applies `update` to the AMM at `address` while holding the write lock. Returns false if the AMM is not in the store.
+    pub async fn update<F>(&self, address: H160, update: F) -> bool
+    where
+        F: FnOnce(&mut AMM),
+    {
+        if let Some(amm) = self.state.write().await.get_mut(&address) {
+            update(amm);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Sets the reserves of the UniswapV2 pool or ERC4626 vault at `address`.
+    /// Returns false if the AMM is not in the store, does not track reserves (UniswapV3) or the reserves do not fit the pool.
+    pub async fn update_reserves(&self, address: H160, reserve_0: U256, reserve_1: U256) -> bool {
+        let mut state = self.state.write().await;
+
+        match state.get_mut(&address) {
+            #[cfg(feature = "uniswap-v2")]
+            Some(AMM::UniswapV2Pool(pool)) => {
+                if reserve_0 > U256::from(u128::MAX) || reserve_1 > U256::from(u128::MAX) {
+                    return false;
+                }
+
+                pool.reserve_0 = reserve_0.as_u128();
+                pool.reserve_1 = reserve_1.as_u128();
+                true
+            }
+            Some(AMM::ERC4626Vault(vault)) => {
+                vault.vault_reserve = reserve_0;
+                vault.asset_reserve = reserve_1;
+                true
+            }
+            #[cfg(feature = "uniswap-v3")]
+            Some(AMM::UniswapV3Pool(_)) => false,
+            None => false,
+        }
+    }
+
+    /// Returns a consistent copy of every AMM in the store.
+    pub async fn snapshot_all(&self) -> Vec<AMM> {
+        self.state.read().await.values().cloned().collect()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.state.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.state.read().await.is_empty()
+    }
+}
+
+#[cfg(all(test, feature = "uniswap-v2", feature = "uniswap-v3"))]
+mod tests {
+    use ethers::types::{H160, U256};
+
+    use crate::amm::{uniswap_v2::UniswapV2Pool, uniswap_v3::UniswapV3Pool, AMM};
+
+    use super::SharedPoolStore;
+
+    #[tokio::test]
+    async fn test_update_reserves() {
+        let v2_address = H160::from_low_u64_be(1);
+        let v3_address = H160::from_low_u64_be(2);
+
+        let store = SharedPoolStore::new(vec![
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: v2_address,
+                ..Default::default()
+            }),
+            AMM::UniswapV3Pool(UniswapV3Pool {
+                address: v3_address,
+                ..Default::default()
+            }),
+        ]);
+
+        let shared_store = store.clone();
+        tokio::spawn(async move {
+            shared_store
+                .update_reserves(v2_address, U256::from(100), U256::from(200))
+                .await
+        })
+        .await
+        .expect("Could not join update task");
+
+        if let Some(AMM::UniswapV2Pool(pool)) = store.get(v2_address).await {
+            assert_eq!(pool.reserve_0, 100);
+            assert_eq!(pool.reserve_1, 200);
+        } else {
+            panic!("Pool not found in store");
+        }
+
+        assert!(
+            !store
+                .update_reserves(v3_address, U256::from(100), U256::from(200))
+                .await
+        );
+        assert!(
+            !store
+                .update_reserves(H160::from_low_u64_be(3), U256::from(100), U256::from(200))
+                .await
+        );
+
+        assert_eq!(store.snapshot_all().await.len(), 2);
+    }
+}
@@ -11,11 +11,13 @@ use serde::{Deserialize, Serialize};
 
 use tokio::task::JoinHandle;
 
+#[cfg(feature = "uniswap-v2")]
+use crate::amm::uniswap_v2::factory::UniswapV2Factory;
+#[cfg(feature = "uniswap-v3")]
+use crate::amm::uniswap_v3::factory::UniswapV3Factory;
 use crate::{
     amm::{
         factory::{AutomatedMarketMakerFactory, Factory},
-        uniswap_v2::factory::UniswapV2Factory,
-        uniswap_v3::factory::UniswapV3Factory,
         AMM,
     },
     errors::{AMMError, CheckpointError},
@@ -46,6 +48,35 @@ impl Checkpoint {
             amms,
         }
     }
+
+    /// Reads the checkpoint at `path_to_checkpoint`, returning an error if any of its Uniswap V2 pools fails validation instead of loading corrupted state.
+    pub fn from_checkpoint(path_to_checkpoint: &str) -> Result<Checkpoint, CheckpointError> {
+        let checkpoint: Checkpoint =
+            serde_json::from_str(read_to_string(path_to_checkpoint)?.as_str())?;
+        checkpoint.validate()?;
+
+        Ok(checkpoint)
+    }
+
+    /// Same as `from_checkpoint`, for a checkpoint written by `save_checkpoint_binary`.
+    #[cfg(feature = "binary-checkpoint")]
+    pub fn from_binary_checkpoint(path_to_checkpoint: &str) -> Result<Checkpoint, CheckpointError> {
+        let checkpoint: Checkpoint = bincode::deserialize(&std::fs::read(path_to_checkpoint)?)?;
+        checkpoint.validate()?;
+
+        Ok(checkpoint)
+    }
+
+    fn validate(&self) -> Result<(), CheckpointError> {
+        #[cfg(feature = "uniswap-v2")]
+        for amm in self.amms.iter() {
+            if let AMM::UniswapV2Pool(pool) = amm {
+                pool.validate()?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 //Get all pairs from last synced block and sync reserve values for each Dex in the `dexes` vec.
@@ -61,8 +92,7 @@ pub async fn sync_amms_from_checkpoint<M: 'static + Middleware>(
         .map_err(AMMError::MiddlewareError)?
         .as_u64();
 
-    let checkpoint: Checkpoint =
-        serde_json::from_str(read_to_string(path_to_checkpoint)?.as_str())?;
+    let checkpoint = Checkpoint::from_checkpoint(path_to_checkpoint)?;
 
     //Sort all of the pools from the checkpoint into uniswap_v2_pools and uniswap_v3_pools pools so we can sync them concurrently
     let (uniswap_v2_pools, uniswap_v3_pools, erc_4626_pools) = sort_amms(checkpoint.amms);
@@ -187,12 +217,14 @@ pub async fn batch_sync_amms_from_checkpoint<M: 'static + Middleware>(
     block_threshold: u64,
 ) -> JoinHandle<Result<Vec<AMM>, AMMError<M>>> {
     let factory = match amms[0] {
+        #[cfg(feature = "uniswap-v2")]
         AMM::UniswapV2Pool(_) => Some(Factory::UniswapV2Factory(UniswapV2Factory::new(
             H160::zero(),
             0,
             0,
         ))),
 
+        #[cfg(feature = "uniswap-v3")]
         AMM::UniswapV3Pool(_) => Some(Factory::UniswapV3Factory(UniswapV3Factory::new(
             H160::zero(),
             0,
@@ -230,13 +262,19 @@ pub async fn batch_sync_amms_from_checkpoint<M: 'static + Middleware>(
     })
 }
 
+#[cfg_attr(
+    not(all(feature = "uniswap-v2", feature = "uniswap-v3")),
+    allow(unused_mut)
+)]
 pub fn sort_amms(amms: Vec<AMM>) -> (Vec<AMM>, Vec<AMM>, Vec<AMM>) {
     let mut uniswap_v2_pools = vec![];
     let mut uniswap_v3_pools = vec![];
     let mut erc_4626_vaults = vec![];
     for amm in amms {
         match amm {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(_) => uniswap_v2_pools.push(amm),
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(_) => uniswap_v3_pools.push(amm),
             AMM::ERC4626Vault(_) => erc_4626_vaults.push(amm),
         }
@@ -299,6 +337,113 @@ pub fn construct_checkpoint(
 
 //Deconstructs the checkpoint into a Vec<AMM>
 pub fn deconstruct_checkpoint(checkpoint_path: &str) -> Result<(Vec<AMM>, u64), CheckpointError> {
-    let checkpoint: Checkpoint = serde_json::from_str(read_to_string(checkpoint_path)?.as_str())?;
+    let checkpoint = Checkpoint::from_checkpoint(checkpoint_path)?;
     Ok((checkpoint.amms, checkpoint.block_number))
 }
+
+/// Same as `construct_checkpoint`, but writes the checkpoint with bincode instead of JSON.
+/// The file is much smaller and faster to load than its JSON equivalent, but is not human readable.
+#[cfg(feature = "binary-checkpoint")]
+pub fn save_checkpoint_binary(
+    factories: Vec<Factory>,
+    amms: &[AMM],
+    latest_block: u64,
+    checkpoint_path: &str,
+) -> Result<(), CheckpointError> {
+    let checkpoint = Checkpoint::new(
+        SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64() as usize,
+        latest_block,
+        factories,
+        amms.to_vec(),
+    );
+
+    std::fs::write(checkpoint_path, bincode::serialize(&checkpoint)?)?;
+
+    Ok(())
+}
+
+/// Same as `deconstruct_checkpoint`, for a checkpoint written by `save_checkpoint_binary`.
+#[cfg(feature = "binary-checkpoint")]
+pub fn load_checkpoint_binary(checkpoint_path: &str) -> Result<(Vec<AMM>, u64), CheckpointError> {
+    let checkpoint = Checkpoint::from_binary_checkpoint(checkpoint_path)?;
+    Ok((checkpoint.amms, checkpoint.block_number))
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use crate::{
+        amm::{
+            uniswap_v2::{UniswapV2Pool, U112_MAX},
+            AMM,
+        },
+        errors::CheckpointError,
+    };
+
+    use super::{construct_checkpoint, Checkpoint};
+
+    #[test]
+    fn test_from_checkpoint_rejects_invalid_pools() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join("amms_invalid_checkpoint.json");
+        let path = path.to_str().expect("Temp dir should be valid utf-8");
+
+        let pool = UniswapV2Pool {
+            reserve_0: U112_MAX + 1,
+            reserve_1: 1000000,
+            ..Default::default()
+        };
+        construct_checkpoint(vec![], &[AMM::UniswapV2Pool(pool)], 0, path)?;
+
+        assert!(matches!(
+            Checkpoint::from_checkpoint(path),
+            Err(CheckpointError::PoolValidationError(_))
+        ));
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "binary-checkpoint", feature = "uniswap-v3"))]
+    #[test]
+    fn test_binary_checkpoint_round_trip() -> eyre::Result<()> {
+        use std::collections::HashMap;
+
+        use ethers::types::{H160, U256};
+
+        use crate::amm::uniswap_v3::{Info, UniswapV3Pool};
+
+        use super::{load_checkpoint_binary, save_checkpoint_binary};
+
+        let path = std::env::temp_dir().join("amms_binary_checkpoint.bin");
+        let path = path.to_str().expect("Temp dir should be valid utf-8");
+
+        let amms = vec![
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: H160::from_low_u64_be(1),
+                reserve_0: 1000000,
+                reserve_1: 2000000,
+                fee: 300,
+                ..Default::default()
+            }),
+            AMM::UniswapV3Pool(UniswapV3Pool {
+                address: H160::from_low_u64_be(2),
+                sqrt_price: U256::from(1) << 96,
+                tick_bitmap: HashMap::from([(0, U256::one())]),
+                ticks: HashMap::from([(0, Info::new(1000, 1000, true))]),
+                ..Default::default()
+            }),
+        ];
+        save_checkpoint_binary(vec![], &amms, 100, path)?;
+
+        let (loaded_amms, block_number) = load_checkpoint_binary(path)?;
+        assert_eq!(block_number, 100);
+        assert_eq!(
+            serde_json::to_string(&loaded_amms)?,
+            serde_json::to_string(&amms)?
+        );
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+
+use ethers::types::H160;
+
+#[cfg(feature = "uniswap-v2")]
+use crate::amm::uniswap_v2::UniswapV2Pool;
+use crate::amm::{AutomatedMarketMaker, AMM};
+
+/// Differences between two sets of AMMs, keyed by address.
+#[derive(Debug, Clone, Default)]
+pub struct PoolSetDiff {
+    /// AMMs present in the new set but not in the old set
+    pub added: Vec<AMM>,
+    /// AMMs present in the old set but not in the new set
+    pub removed: Vec<AMM>,
+    /// AMMs present in both sets whose reserves changed, as `(old, new)`
+    pub reserve_changed: Vec<(AMM, AMM)>,
+}
+
+/// Compares two snapshots of AMMs, returning the AMMs that were added, removed or had their reserves changed between `old` and `new`.
+pub fn diff_pool_sets(old: &[AMM], new: &[AMM]) -> PoolSetDiff {
+    let old_amms: HashMap<H160, &AMM> = old.iter().map(|amm| (amm.address(), amm)).collect();
+    let new_amms: HashMap<H160, &AMM> = new.iter().map(|amm| (amm.address(), amm)).collect();
+
+    let mut diff = PoolSetDiff::default();
+
+    for amm in new {
+        match old_amms.get(&amm.address()) {
+            Some(old_amm) => {
+                if reserves_changed(old_amm, amm) {
+                    diff.reserve_changed.push(((*old_amm).clone(), amm.clone()));
+                }
+            }
+            None => diff.added.push(amm.clone()),
+        }
+    }
+
+    for amm in old {
+        if !new_amms.contains_key(&amm.address()) {
+            diff.removed.push(amm.clone());
+        }
+    }
+
+    diff
+}
+
+/// Returns the addresses of the pools in `new` whose reserves differ from the pool with the same address in `old`, in the order of `new`.
+/// Pools that are not in `old` are not considered changed, see `diff_pool_sets` to also get added and removed pools.
+#[cfg(feature = "uniswap-v2")]
+pub fn changed_reserves(old: &[UniswapV2Pool], new: &[UniswapV2Pool]) -> Vec<H160> {
+    let old_pools: HashMap<H160, &UniswapV2Pool> =
+        old.iter().map(|pool| (pool.address, pool)).collect();
+
+    new.iter()
+        .filter(|pool| {
+            old_pools.get(&pool.address).is_some_and(|old_pool| {
+                old_pool.reserve_0 != pool.reserve_0 || old_pool.reserve_1 != pool.reserve_1
+            })
+        })
+        .map(|pool| pool.address)
+        .collect()
+}
+
+/// Merges two sets of AMMs into a deduplicated set sorted by address, keeping the entry with the higher last synced block on address collisions.
+/// On equal blocks the entry from `updates` is kept, so the result does not depend on the order of either vector.
+pub fn merge_amms(base: Vec<AMM>, updates: Vec<AMM>) -> Vec<AMM> {
+    let mut merged: HashMap<H160, AMM> = HashMap::new();
+
+    for amm in base {
+        insert_if_fresher(&mut merged, amm, false);
+    }
+
+    for amm in updates {
+        insert_if_fresher(&mut merged, amm, true);
+    }
+
+    let mut merged = merged.into_values().collect::<Vec<AMM>>();
+    merged.sort_by_key(|amm| amm.address());
+
+    merged
+}
+
+/// Merges the AMMs of several checkpoints, ie. from syncs sharded across workers, into a deduplicated set sorted by address.
+/// On address collisions the entry with the higher last synced block is kept, and on equal blocks the entry from the later checkpoint, as in `merge_amms`.
+pub fn merge_checkpoints(checkpoints: Vec<Vec<AMM>>) -> Vec<AMM> {
+    checkpoints
+        .into_iter()
+        .fold(vec![], |merged, amms| merge_amms(merged, amms))
+}
+
+fn insert_if_fresher(merged: &mut HashMap<H160, AMM>, amm: AMM, keep_on_tie: bool) {
+    match merged.get(&amm.address()) {
+        Some(existing)
+            if last_synced_block(existing) > last_synced_block(&amm)
+                || (!keep_on_tie && last_synced_block(existing) == last_synced_block(&amm)) => {}
+        _ => {
+            merged.insert(amm.address(), amm);
+        }
+    }
+}
+
+//AMMs that were never synced are treated as synced at block 0
+fn last_synced_block(amm: &AMM) -> u64 {
+    match amm {
+        #[cfg(feature = "uniswap-v2")]
+        AMM::UniswapV2Pool(pool) => pool.last_active_at_block,
+        #[cfg(feature = "uniswap-v3")]
+        AMM::UniswapV3Pool(pool) => pool.last_active_at_block.unwrap_or_default(),
+        AMM::ERC4626Vault(vault) => vault.last_active_at_block.unwrap_or_default(),
+    }
+}
+
+fn reserves_changed(old: &AMM, new: &AMM) -> bool {
+    match (old, new) {
+        #[cfg(feature = "uniswap-v2")]
+        (AMM::UniswapV2Pool(old), AMM::UniswapV2Pool(new)) => {
+            old.reserve_0 != new.reserve_0 || old.reserve_1 != new.reserve_1
+        }
+        #[cfg(feature = "uniswap-v3")]
+        (AMM::UniswapV3Pool(old), AMM::UniswapV3Pool(new)) => {
+            old.liquidity != new.liquidity || old.sqrt_price != new.sqrt_price
+        }
+        (AMM::ERC4626Vault(old), AMM::ERC4626Vault(new)) => {
+            old.vault_reserve != new.vault_reserve || old.asset_reserve != new.asset_reserve
+        }
+        _ => true,
+    }
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use ethers::types::H160;
+
+    use crate::amm::{uniswap_v2::UniswapV2Pool, AutomatedMarketMaker, AMM};
+
+    use super::{changed_reserves, diff_pool_sets, merge_amms, merge_checkpoints};
+
+    fn v2_pool(address: u64, reserve_0: u128, reserve_1: u128) -> AMM {
+        AMM::UniswapV2Pool(UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            reserve_0,
+            reserve_1,
+            ..Default::default()
+        })
+    }
+
+    fn synced_v2_pool(address: u64, reserve_0: u128, last_active_at_block: u64) -> AMM {
+        AMM::UniswapV2Pool(UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            reserve_0,
+            last_active_at_block,
+            ..Default::default()
+        })
+    }
+
+    fn reserve_0(amm: &AMM) -> u128 {
+        match amm {
+            AMM::UniswapV2Pool(pool) => pool.reserve_0,
+            _ => panic!("Unexpected AMM variant"),
+        }
+    }
+
+    #[test]
+    fn test_changed_pools() {
+        let pool = |address: u64, reserve_0: u128, reserve_1: u128| UniswapV2Pool {
+            address: H160::from_low_u64_be(address),
+            reserve_0,
+            reserve_1,
+            ..Default::default()
+        };
+
+        let old = vec![pool(1, 100, 100), pool(2, 100, 100), pool(3, 100, 100)];
+        let new = vec![
+            pool(3, 100, 101),
+            pool(2, 100, 100),
+            pool(1, 99, 100),
+            pool(4, 1, 1),
+        ];
+
+        assert_eq!(
+            changed_reserves(&old, &new),
+            vec![H160::from_low_u64_be(3), H160::from_low_u64_be(1)]
+        );
+        assert!(changed_reserves(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_merge_amms() {
+        let base = vec![
+            synced_v2_pool(3, 30, 100),
+            synced_v2_pool(1, 10, 100),
+            synced_v2_pool(2, 20, 200),
+        ];
+        let updates = vec![
+            synced_v2_pool(1, 11, 150),
+            synced_v2_pool(2, 21, 150),
+            synced_v2_pool(4, 40, 150),
+            synced_v2_pool(3, 31, 100),
+        ];
+
+        let merged = merge_amms(base.clone(), updates.clone());
+
+        let addresses = merged
+            .iter()
+            .map(|amm| amm.address())
+            .collect::<Vec<H160>>();
+        assert_eq!(
+            addresses,
+            (1..=4).map(H160::from_low_u64_be).collect::<Vec<H160>>()
+        );
+
+        //Fresher update, staler update, new pool and tie kept from the updates
+        assert_eq!(reserve_0(&merged[0]), 11);
+        assert_eq!(reserve_0(&merged[1]), 20);
+        assert_eq!(reserve_0(&merged[2]), 31);
+        assert_eq!(reserve_0(&merged[3]), 40);
+
+        //The order of the entries does not change the result
+        let reversed = merge_amms(
+            base.into_iter().rev().collect(),
+            updates.into_iter().rev().collect(),
+        );
+        assert_eq!(
+            reversed.iter().map(reserve_0).collect::<Vec<u128>>(),
+            merged.iter().map(reserve_0).collect::<Vec<u128>>()
+        );
+    }
+
+    #[test]
+    fn test_merge_checkpoints() {
+        let checkpoints = vec![
+            vec![synced_v2_pool(1, 10, 100), synced_v2_pool(2, 20, 100)],
+            vec![synced_v2_pool(3, 30, 120), synced_v2_pool(1, 11, 120)],
+            vec![synced_v2_pool(2, 21, 90), synced_v2_pool(3, 31, 120)],
+        ];
+
+        let merged = merge_checkpoints(checkpoints);
+
+        assert_eq!(
+            merged
+                .iter()
+                .map(|amm| amm.address())
+                .collect::<Vec<H160>>(),
+            (1..=3).map(H160::from_low_u64_be).collect::<Vec<H160>>()
+        );
+
+        //Newest entry of each pool, the later checkpoint on ties
+        assert_eq!(
+            merged.iter().map(reserve_0).collect::<Vec<u128>>(),
+            vec![11, 20, 31]
+        );
+
+        assert!(merge_checkpoints(vec![]).is_empty());
+    }
+
+    #[test]
+    fn test_diff_pool_sets() {
+        let old = vec![
+            v2_pool(1, 100, 100),
+            v2_pool(2, 100, 100),
+            v2_pool(3, 100, 100),
+        ];
+        let new = vec![
+            v2_pool(2, 100, 100),
+            v2_pool(3, 150, 70),
+            v2_pool(4, 100, 100),
+        ];
+
+        let diff = diff_pool_sets(&old, &new);
+
+        assert_eq!(diff.added.len(), 1);
+        assert_eq!(diff.added[0].address(), H160::from_low_u64_be(4));
+
+        assert_eq!(diff.removed.len(), 1);
+        assert_eq!(diff.removed[0].address(), H160::from_low_u64_be(1));
+
+        assert_eq!(diff.reserve_changed.len(), 1);
+        let (old_amm, new_amm) = &diff.reserve_changed[0];
+        assert_eq!(old_amm.address(), H160::from_low_u64_be(3));
+        if let (AMM::UniswapV2Pool(old_pool), AMM::UniswapV2Pool(new_pool)) = (old_amm, new_amm) {
+            assert_eq!(old_pool.reserve_0, 100);
+            assert_eq!(new_pool.reserve_0, 150);
+        } else {
+            panic!("Unexpected AMM variant");
+        }
+    }
+}
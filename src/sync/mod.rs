@@ -1,16 +1,64 @@
+#[cfg(feature = "uniswap-v3")]
+use crate::amm::uniswap_v3;
+#[cfg(feature = "uniswap-v2")]
+use crate::amm::{
+    factory::TASK_LIMIT,
+    uniswap_v2::{
+        self,
+        batch_request::DecimalsPolicy,
+        factory::{IUniswapV2Factory, UniswapV2Factory},
+        IUniswapV2Pair, UniswapV2Pool,
+    },
+};
 use crate::{
     amm::{
+        call_options::CallOptions,
         factory::{AutomatedMarketMakerFactory, Factory},
-        uniswap_v2, uniswap_v3, AutomatedMarketMaker, AMM,
+        AutomatedMarketMaker, AMM,
     },
     errors::AMMError,
 };
 
-use ethers::providers::Middleware;
+#[cfg(feature = "uniswap-v2")]
+use ethers::{
+    prelude::ContractError,
+    providers::{MiddlewareError, RpcError},
+};
+use ethers::{
+    providers::Middleware,
+    types::{Filter, H160, H256},
+};
 
+#[cfg(feature = "uniswap-v2")]
+use futures::stream::{self, StreamExt};
 use spinoff::{spinners, Color, Spinner};
-use std::{panic::resume_unwind, sync::Arc};
+use std::{collections::HashSet, panic::resume_unwind, sync::Arc};
 pub mod checkpoint;
+pub mod diff;
+pub mod multichain;
+pub mod refresh;
+pub mod stream;
+
+const CHANGED_POOLS_BLOCK_STEP: u64 = 2000;
+const CHANGED_POOLS_ADDRESS_STEP: usize = 500;
+
+/// Options applied by `sync_amms_with_options`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncOptions {
+    /// Drops AMMs whose tokens are the same address, see `is_degenerate`. Off by default.
+    pub remove_degenerate_pools: bool,
+}
+
+impl SyncOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn remove_degenerate_pools(mut self, remove_degenerate_pools: bool) -> Self {
+        self.remove_degenerate_pools = remove_degenerate_pools;
+        self
+    }
+}
 
 pub async fn sync_amms<M: 'static + Middleware>(
     factories: Vec<Factory>,
@@ -18,6 +66,26 @@ pub async fn sync_amms<M: 'static + Middleware>(
     checkpoint_path: Option<&str>,
     step: u64,
     block_threshold: u64,
+) -> Result<(Vec<AMM>, u64), AMMError<M>> {
+    sync_amms_with_options(
+        factories,
+        middleware,
+        checkpoint_path,
+        step,
+        block_threshold,
+        SyncOptions::default(),
+    )
+    .await
+}
+
+/// Discovers and populates the AMMs of every factory as `sync_amms` does, applying `options` to the synced AMMs.
+pub async fn sync_amms_with_options<M: 'static + Middleware>(
+    factories: Vec<Factory>,
+    middleware: Arc<M>,
+    checkpoint_path: Option<&str>,
+    step: u64,
+    block_threshold: u64,
+    options: SyncOptions,
 ) -> Result<(Vec<AMM>, u64), AMMError<M>> {
     let spinner = Spinner::new(spinners::Dots, "Syncing AMMs...", Color::Blue);
 
@@ -46,10 +114,16 @@ pub async fn sync_amms<M: 'static + Middleware>(
             //Clean empty pools
             amms = remove_empty_amms(amms);
 
+            if options.remove_degenerate_pools {
+                amms = remove_degenerate_amms(amms);
+            }
+
             //Clean outdated pools
             amms = remove_outdated_amms(amms, current_block - block_threshold, middleware).await?;
 
             // If the factory is UniswapV2, set the fee for each pool according to the factory fee
+            #[cfg(feature = "uniswap-v2")]
+            #[allow(irrefutable_let_patterns)]
             if let Factory::UniswapV2Factory(factory) = factory {
                 for amm in amms.iter_mut() {
                     if let AMM::UniswapV2Pool(ref mut pool) = amm {
@@ -76,6 +150,9 @@ pub async fn sync_amms<M: 'static + Middleware>(
         }
     }
 
+    //Sort by address so that the output does not depend on the order the tasks completed in
+    aggregated_amms.sort_by_key(|amm| amm.address());
+
     //Save a checkpoint if a path is provided
 
     if let Some(checkpoint_path) = checkpoint_path {
@@ -92,6 +169,200 @@ pub async fn sync_amms<M: 'static + Middleware>(
     Ok((aggregated_amms, current_block))
 }
 
+/// Syncs the AMMs of `factories` as `sync_amms` does, skipping the factories in `deny`, ie. known fake or malicious deployers.
+/// AMMs are discovered per factory, so none of the returned AMMs originate from a denied factory. The checkpoint only records the factories that were synced.
+pub async fn sync_amms_excluding_factories<M: 'static + Middleware>(
+    factories: Vec<Factory>,
+    deny: &[H160],
+    middleware: Arc<M>,
+    checkpoint_path: Option<&str>,
+    step: u64,
+    block_threshold: u64,
+) -> Result<(Vec<AMM>, u64), AMMError<M>> {
+    sync_amms(
+        exclude_factories(factories, deny),
+        middleware,
+        checkpoint_path,
+        step,
+        block_threshold,
+    )
+    .await
+}
+
+/// Returns `factories` without the factories whose address is in `deny`.
+pub fn exclude_factories(factories: Vec<Factory>, deny: &[H160]) -> Vec<Factory> {
+    let deny = deny.iter().collect::<HashSet<&H160>>();
+
+    factories
+        .into_iter()
+        .filter(|factory| !deny.contains(&factory.address()))
+        .collect()
+}
+
+/// Validates a list of candidate UniswapV2 pair addresses against `expected_factory` and populates the valid pairs in one pass.
+/// Returns the populated pools along with the rejected addresses (non-pairs, pairs from a different factory or pairs with invalid tokens).
+#[cfg(feature = "uniswap-v2")]
+pub async fn sync_and_validate<M: Middleware>(
+    addresses: &[H160],
+    expected_factory: H160,
+    middleware: Arc<M>,
+) -> Result<(Vec<AMM>, Vec<H160>), AMMError<M>> {
+    let factory = UniswapV2Factory {
+        address: expected_factory,
+        ..Default::default()
+    };
+    let fee = factory
+        .get_fee(uniswap_v2::BPS_FEE_DENOMINATOR, middleware.clone())
+        .await?;
+
+    let results = stream::iter(addresses.iter().copied())
+        .map(|address| {
+            let middleware = middleware.clone();
+            async move {
+                let is_valid = is_pair_from_factory(address, expected_factory, middleware).await?;
+                Ok::<_, AMMError<M>>((address, is_valid))
+            }
+        })
+        .buffered(TASK_LIMIT)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut amms = vec![];
+    let mut rejected = vec![];
+    for result in results {
+        let (address, is_valid) = result?;
+
+        if is_valid {
+            amms.push(AMM::UniswapV2Pool(UniswapV2Pool {
+                address,
+                fee,
+                ..Default::default()
+            }));
+        } else {
+            rejected.push(address);
+        }
+    }
+
+    //The block is resolved once so that every chunk is read at the same block
+    let block_number = CallOptions::default()
+        .resolve_block(middleware.clone())
+        .await?;
+    let call_options = CallOptions::new().block(block_number);
+
+    let step = 109; //Max batch size for call
+    for amm_chunk in amms.chunks_mut(step) {
+        uniswap_v2::batch_request::get_amm_data_batch_request(
+            amm_chunk,
+            DecimalsPolicy::Skip,
+            &call_options,
+            middleware.clone(),
+        )
+        .await?;
+    }
+
+    //Pools with tokens that have no code or invalid decimals are left unpopulated by the batch request
+    let (amms, invalid_tokens): (Vec<AMM>, Vec<AMM>) = amms.into_iter().partition(|amm| {
+        amm.tokens().iter().all(|token| !token.is_zero())
+    });
+    rejected.extend(invalid_tokens.iter().map(|amm| amm.address()));
+
+    Ok((amms, rejected))
+}
+
+//Checks that the factory returns `pair` for the pair tokens, calls that fail on chain mark the pair as invalid while transport errors are returned
+#[cfg(feature = "uniswap-v2")]
+async fn is_pair_from_factory<M: Middleware>(
+    pair: H160,
+    factory: H160,
+    middleware: Arc<M>,
+) -> Result<bool, AMMError<M>> {
+    let v2_pair = IUniswapV2Pair::new(pair, middleware.clone());
+    let v2_factory = IUniswapV2Factory::new(factory, middleware);
+
+    let token_0 = match v2_pair.token_0().call().await {
+        Ok(token_0) => token_0,
+        Err(e) if call_failed(&e) => return Ok(false),
+        Err(e) => return Err(AMMError::ContractError("sync_and_validate", pair, e)),
+    };
+
+    let token_1 = match v2_pair.token_1().call().await {
+        Ok(token_1) => token_1,
+        Err(e) if call_failed(&e) => return Ok(false),
+        Err(e) => return Err(AMMError::ContractError("sync_and_validate", pair, e)),
+    };
+
+    match v2_factory.get_pair(token_0, token_1).call().await {
+        Ok(factory_pair) => Ok(factory_pair == pair),
+        Err(e) if call_failed(&e) => Ok(false),
+        Err(e) => Err(AMMError::ContractError("sync_and_validate", factory, e)),
+    }
+}
+
+//Returns true if the node executed the call and it failed, rather than the request failing to reach the node
+#[cfg(feature = "uniswap-v2")]
+fn call_failed<M: Middleware>(error: &ContractError<M>) -> bool {
+    match error {
+        ContractError::MiddlewareError { e } => e.as_error_response().is_some(),
+        ContractError::ProviderError { e } => e.as_error_response().is_some(),
+        _ => true,
+    }
+}
+
+/// Returns the addresses of the AMMs in `amms` that emitted any of their sync events between `from_block` and `to_block` (inclusive).
+/// Only the returned AMMs need to be refreshed to bring the set up to date with `to_block`.
+pub async fn changed_pools<M: Middleware>(
+    amms: &[AMM],
+    from_block: u64,
+    to_block: u64,
+    middleware: Arc<M>,
+) -> Result<Vec<H160>, AMMError<M>> {
+    //An empty topic filter would match every log of the addresses
+    if amms.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut event_signatures = amms
+        .iter()
+        .flat_map(|amm| amm.sync_on_event_signatures())
+        .collect::<Vec<H256>>();
+    event_signatures.sort();
+    event_signatures.dedup();
+
+    let mut active_addresses = HashSet::new();
+    for amm_chunk in amms.chunks(CHANGED_POOLS_ADDRESS_STEP) {
+        let addresses = amm_chunk
+            .iter()
+            .map(|amm| amm.address())
+            .collect::<Vec<H160>>();
+
+        let mut from = from_block;
+        while from <= to_block {
+            let target_block = (from + CHANGED_POOLS_BLOCK_STEP - 1).min(to_block);
+
+            let logs = middleware
+                .get_logs(
+                    &Filter::new()
+                        .topic0(event_signatures.clone())
+                        .address(addresses.clone())
+                        .from_block(from)
+                        .to_block(target_block),
+                )
+                .await
+                .map_err(AMMError::MiddlewareError)?;
+
+            active_addresses.extend(logs.into_iter().map(|log| log.address));
+
+            from = target_block + 1;
+        }
+    }
+
+    Ok(amms
+        .iter()
+        .map(|amm| amm.address())
+        .filter(|address| active_addresses.contains(address))
+        .collect())
+}
+
 pub fn amms_are_congruent(amms: &[AMM]) -> bool {
     let expected_amm = &amms[0];
 
@@ -111,23 +382,28 @@ pub async fn populate_amms<M: Middleware>(
 ) -> Result<(), AMMError<M>> {
     if amms_are_congruent(amms) {
         match amms[0] {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(_) => {
                 let step = 109; //Max batch size for call
                 for amm_chunk in amms.chunks_mut(step) {
                     uniswap_v2::batch_request::get_amm_data_batch_request(
                         amm_chunk,
+                        DecimalsPolicy::default(),
+                        &CallOptions::new().block(block_number),
                         middleware.clone(),
                     )
                     .await?;
                 }
             }
 
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(_) => {
                 let step = 76; //Max batch size for call
                 for amm_chunk in amms.chunks_mut(step) {
                     uniswap_v3::batch_request::get_amm_data_batch_request(
                         amm_chunk,
                         block_number,
+                        &CallOptions::default(),
                         middleware.clone(),
                     )
                     .await?;
@@ -154,11 +430,13 @@ pub fn remove_empty_amms(amms: Vec<AMM>) -> Vec<AMM> {
 
     for amm in amms.into_iter() {
         match amm {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(ref uniswap_v2_pool) => {
                 if !uniswap_v2_pool.token_a.is_zero() && !uniswap_v2_pool.token_b.is_zero() {
                     cleaned_amms.push(amm)
                 }
             }
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(ref uniswap_v3_pool) => {
                 if !uniswap_v3_pool.token_a.is_zero() && !uniswap_v3_pool.token_b.is_zero() {
                     cleaned_amms.push(amm)
@@ -175,6 +453,27 @@ pub fn remove_empty_amms(amms: Vec<AMM>) -> Vec<AMM> {
     cleaned_amms
 }
 
+/// Returns true if the AMM has the same token more than once, ie. a pool created with `token_a == token_b` by a misconfigured fork.
+/// Prices and swaps of such pools are meaningless, and routing through them loops on the same token.
+pub fn is_degenerate(amm: &AMM) -> bool {
+    let tokens = amm.tokens();
+
+    tokens
+        .iter()
+        .enumerate()
+        .any(|(i, token)| tokens[i + 1..].contains(token))
+}
+
+/// Splits `amms` into the valid AMMs and the degenerate ones, see `is_degenerate`.
+pub fn partition_degenerate_amms(amms: Vec<AMM>) -> (Vec<AMM>, Vec<AMM>) {
+    amms.into_iter().partition(|amm| !is_degenerate(amm))
+}
+
+pub fn remove_degenerate_amms(amms: Vec<AMM>) -> Vec<AMM> {
+    partition_degenerate_amms(amms).0
+}
+
+#[cfg_attr(not(feature = "uniswap-v2"), allow(unused_variables))]
 pub async fn remove_outdated_amms<M: Middleware>(
     amms: Vec<AMM>, 
     min_block: u64, 
@@ -193,11 +492,13 @@ pub async fn remove_outdated_amms<M: Middleware>(
 
     for amm in amms.into_iter() {
         match amm {
+            #[cfg(feature = "uniswap-v2")]
             AMM::UniswapV2Pool(ref uniswap_v2_pool) => {
                 if uniswap_v2_pool.last_active_at_block >= min_block || uniswap_v2_pool.last_active_at >= min_block_ts {
                     cleaned_amms.push(amm)
                 }
             }
+            #[cfg(feature = "uniswap-v3")]
             AMM::UniswapV3Pool(ref uniswap_v3_pool) => {
                 if uniswap_v3_pool.last_active_at_block.unwrap_or_default() >= min_block {
                     cleaned_amms.push(amm)
@@ -213,3 +514,134 @@ pub async fn remove_outdated_amms<M: Middleware>(
 
     Ok(cleaned_amms)
 }
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::H160,
+    };
+
+    use crate::amm::{
+        factory::{AutomatedMarketMakerFactory, Factory},
+        uniswap_v2::{factory::UniswapV2Factory, UniswapV2Pool},
+        AutomatedMarketMaker, AMM,
+    };
+
+    use super::{
+        changed_pools, exclude_factories, is_degenerate, partition_degenerate_amms, sync_amms,
+    };
+
+    #[test]
+    fn test_partition_degenerate_amms() {
+        let token = H160::from_low_u64_be;
+
+        let valid_pool = AMM::UniswapV2Pool(UniswapV2Pool {
+            address: token(10),
+            token_a: token(1),
+            token_b: token(2),
+            ..Default::default()
+        });
+        let degenerate_pool = AMM::UniswapV2Pool(UniswapV2Pool {
+            address: token(11),
+            token_a: token(1),
+            token_b: token(1),
+            ..Default::default()
+        });
+
+        assert!(!is_degenerate(&valid_pool));
+        assert!(is_degenerate(&degenerate_pool));
+
+        let (valid, degenerate) =
+            partition_degenerate_amms(vec![valid_pool.clone(), degenerate_pool.clone()]);
+        assert_eq!(valid.len(), 1);
+        assert_eq!(valid[0].address(), valid_pool.address());
+        assert_eq!(degenerate.len(), 1);
+        assert_eq!(degenerate[0].address(), degenerate_pool.address());
+    }
+
+    #[test]
+    fn test_exclude_factories() {
+        let factory = |address| {
+            Factory::UniswapV2Factory(UniswapV2Factory::new(
+                H160::from_low_u64_be(address),
+                0,
+                300,
+            ))
+        };
+
+        let factories = exclude_factories(
+            vec![factory(1), factory(2), factory(3)],
+            &[H160::from_low_u64_be(2), H160::from_low_u64_be(4)],
+        );
+        assert_eq!(
+            factories
+                .iter()
+                .map(|factory| factory.address())
+                .collect::<Vec<H160>>(),
+            vec![H160::from_low_u64_be(1), H160::from_low_u64_be(3)]
+        );
+
+        assert_eq!(exclude_factories(vec![factory(1)], &[]).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_sync_amms_is_ordered() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let factories = vec![Factory::UniswapV2Factory(UniswapV2Factory::new(
+            H160::from_str("0x115934131916C8b277DD010Ee02de363c09d037c")?,
+            12771526,
+            300,
+        ))];
+
+        let (amms, _) =
+            sync_amms(factories.clone(), middleware.clone(), None, 10000, 10000000).await?;
+        let (resynced_amms, _) = sync_amms(factories, middleware, None, 10000, 10000000).await?;
+
+        let addresses = amms.iter().map(|amm| amm.address()).collect::<Vec<H160>>();
+        let resynced_addresses = resynced_amms
+            .iter()
+            .map(|amm| amm.address())
+            .collect::<Vec<H160>>();
+
+        assert!(addresses.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(addresses, resynced_addresses);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_changed_pools() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //USDC/WETH pair, which is swapped against in virtually every block
+        let active_pool = H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?;
+        //Not a pool, never emits a sync event
+        let inactive_pool = H160::from_str("0x0000000000000000000000000000000000000001")?;
+
+        let amms = vec![
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: inactive_pool,
+                ..Default::default()
+            }),
+            AMM::UniswapV2Pool(UniswapV2Pool {
+                address: active_pool,
+                ..Default::default()
+            }),
+        ];
+
+        let changed = changed_pools(&amms, 17000000, 17000100, middleware.clone()).await?;
+        assert_eq!(changed, vec![active_pool]);
+
+        assert!(changed_pools(&[], 17000000, 17000100, middleware)
+            .await?
+            .is_empty());
+
+        Ok(())
+    }
+}
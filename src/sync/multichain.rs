@@ -0,0 +1,274 @@
+use std::{collections::HashMap, sync::Arc};
+
+use ethers::providers::Middleware;
+use futures::{
+    future,
+    stream::{self, StreamExt},
+};
+
+#[cfg(feature = "uniswap-v2")]
+use crate::{
+    amm::{factory::AutomatedMarketMakerFactory, uniswap_v2::factory::UniswapV2Factory},
+    sync::{populate_amms, remove_degenerate_amms, remove_empty_amms},
+};
+use crate::{
+    amm::{factory::TASK_LIMIT, AutomatedMarketMaker, AMM},
+    errors::AMMError,
+};
+
+/// A set of AMMs spanning multiple chains, grouped by chain id. Each group is synced with the middleware of its chain.
+#[derive(Debug)]
+pub struct MultiChainSyncer<M: Middleware> {
+    middlewares: HashMap<u64, Arc<M>>,
+    amms: HashMap<u64, Vec<AMM>>,
+}
+
+impl<M: Middleware> Default for MultiChainSyncer<M> {
+    fn default() -> Self {
+        Self {
+            middlewares: HashMap::new(),
+            amms: HashMap::new(),
+        }
+    }
+}
+
+impl<M: Middleware> MultiChainSyncer<M> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the middleware used to sync the AMMs of `chain_id`.
+    pub fn add_chain(&mut self, chain_id: u64, middleware: Arc<M>) {
+        self.middlewares.insert(chain_id, middleware);
+    }
+
+    /// Adds `middleware` under the chain id it reports, returning the chain id.
+    pub async fn add_middleware(&mut self, middleware: Arc<M>) -> Result<u64, AMMError<M>> {
+        let chain_id = middleware
+            .get_chainid()
+            .await
+            .map_err(AMMError::MiddlewareError)?
+            .as_u64();
+
+        self.add_chain(chain_id, middleware);
+
+        Ok(chain_id)
+    }
+
+    pub fn add_amms(&mut self, chain_id: u64, amms: Vec<AMM>) {
+        self.amms.entry(chain_id).or_default().extend(amms);
+    }
+
+    pub fn amms(&self, chain_id: u64) -> &[AMM] {
+        self.amms
+            .get(&chain_id)
+            .map(|amms| amms.as_slice())
+            .unwrap_or_default()
+    }
+
+    pub fn all_amms(&self) -> &HashMap<u64, Vec<AMM>> {
+        &self.amms
+    }
+
+    pub fn chain_ids(&self) -> Vec<u64> {
+        self.middlewares.keys().copied().collect()
+    }
+
+    /// Syncs the AMMs of every chain with the middleware of that chain. Chains are synced concurrently.
+    /// Returns `AMMError::ChainNotFound` without syncing anything if AMMs were added for a chain with no middleware.
+    pub async fn sync(&mut self) -> Result<(), AMMError<M>> {
+        if let Some(chain_id) = self
+            .amms
+            .keys()
+            .find(|chain_id| !self.middlewares.contains_key(chain_id))
+        {
+            return Err(AMMError::ChainNotFound(*chain_id));
+        }
+
+        let middlewares = &self.middlewares;
+        future::try_join_all(self.amms.iter_mut().map(|(chain_id, amms)| {
+            sync_chain(amms, middlewares[chain_id].clone())
+        }))
+        .await?;
+
+        Ok(())
+    }
+
+    /// Syncs the AMMs of `chain_id` only.
+    pub async fn sync_chain(&mut self, chain_id: u64) -> Result<(), AMMError<M>> {
+        let middleware = self
+            .middlewares
+            .get(&chain_id)
+            .ok_or(AMMError::ChainNotFound(chain_id))?
+            .clone();
+
+        if let Some(amms) = self.amms.get_mut(&chain_id) {
+            sync_chain(amms, middleware).await?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Discovers and populates the pools of the Uniswap V2 factories of each chain with the middleware of that chain, returning the pools grouped by chain id.
+/// Chains are synced concurrently. Empty and degenerate pools are dropped, as in `sync_amms`.
+/// Returns `AMMError::ChainNotFound` without syncing anything if a chain has factories but no middleware.
+#[cfg(feature = "uniswap-v2")]
+pub async fn sync_multichain<M: 'static + Middleware>(
+    factories: HashMap<u64, Vec<UniswapV2Factory>>,
+    providers: HashMap<u64, Arc<M>>,
+    step: u64,
+) -> Result<HashMap<u64, Vec<AMM>>, AMMError<M>> {
+    if let Some(chain_id) = factories
+        .keys()
+        .find(|chain_id| !providers.contains_key(chain_id))
+    {
+        return Err(AMMError::ChainNotFound(*chain_id));
+    }
+
+    let chains = future::try_join_all(factories.into_iter().map(|(chain_id, factories)| {
+        let middleware = providers[&chain_id].clone();
+        async move {
+            let amms = discover_chain(factories, middleware, step).await?;
+            Ok::<_, AMMError<M>>((chain_id, amms))
+        }
+    }))
+    .await?;
+
+    Ok(chains.into_iter().collect())
+}
+
+#[cfg(feature = "uniswap-v2")]
+async fn discover_chain<M: 'static + Middleware>(
+    factories: Vec<UniswapV2Factory>,
+    middleware: Arc<M>,
+    step: u64,
+) -> Result<Vec<AMM>, AMMError<M>> {
+    let current_block = middleware
+        .get_block_number()
+        .await
+        .map_err(AMMError::MiddlewareError)?
+        .as_u64();
+
+    let mut chain_amms = vec![];
+    for factory in factories {
+        let mut amms = factory
+            .get_all_amms(Some(current_block), middleware.clone(), step)
+            .await?;
+        populate_amms(&mut amms, current_block, middleware.clone()).await?;
+
+        for amm in amms.iter_mut() {
+            if let AMM::UniswapV2Pool(ref mut pool) = amm {
+                pool.fee = factory.fee;
+            }
+        }
+
+        chain_amms.extend(remove_degenerate_amms(remove_empty_amms(amms)));
+    }
+
+    //Sort by address so that the output does not depend on the order of the factories
+    chain_amms.sort_by_key(|amm| amm.address());
+
+    Ok(chain_amms)
+}
+
+async fn sync_chain<M: Middleware>(amms: &mut [AMM], middleware: Arc<M>) -> Result<(), AMMError<M>> {
+    let results = stream::iter(amms.iter_mut())
+        .map(|amm| amm.sync(middleware.clone()))
+        .buffer_unordered(TASK_LIMIT)
+        .collect::<Vec<_>>()
+        .await;
+
+    results.into_iter().collect()
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use std::{collections::HashMap, str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::H160,
+    };
+
+    use crate::{
+        amm::{
+            uniswap_v2::{factory::UniswapV2Factory, UniswapV2Pool},
+            AMM,
+        },
+        errors::AMMError,
+    };
+
+    use super::{sync_multichain, MultiChainSyncer};
+
+    #[tokio::test]
+    async fn test_sync_without_middleware_fails() {
+        let mut syncer = MultiChainSyncer::<Provider<Http>>::new();
+        syncer.add_amms(1, vec![AMM::UniswapV2Pool(UniswapV2Pool::default())]);
+
+        assert!(matches!(syncer.sync().await, Err(AMMError::ChainNotFound(1))));
+        assert!(matches!(
+            syncer.sync_chain(1).await,
+            Err(AMMError::ChainNotFound(1))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_sync_multichain() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let factory = UniswapV2Factory::new(
+            H160::from_str("0x115934131916C8b277DD010Ee02de363c09d037c")?,
+            12771526,
+            300,
+        );
+
+        let amms = sync_multichain(
+            HashMap::from([(1, vec![factory.clone()])]),
+            HashMap::from([(1, middleware.clone())]),
+            10000,
+        )
+        .await?;
+        assert!(!amms[&1].is_empty());
+
+        //Factories of a chain without a middleware
+        assert!(matches!(
+            sync_multichain(
+                HashMap::from([(10, vec![factory])]),
+                HashMap::from([(1, middleware)]),
+                10000,
+            )
+            .await,
+            Err(AMMError::ChainNotFound(10))
+        ));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_multichain_sync() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let mut syncer = MultiChainSyncer::new();
+        let chain_id = syncer.add_middleware(middleware).await?;
+
+        //USDC/WETH pair
+        let pool = UniswapV2Pool {
+            address: H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?,
+            fee: 300,
+            ..Default::default()
+        };
+        syncer.add_amms(chain_id, vec![AMM::UniswapV2Pool(pool)]);
+
+        syncer.sync().await?;
+
+        match &syncer.amms(chain_id)[0] {
+            AMM::UniswapV2Pool(pool) => assert!(pool.reserve_0 > 0 && pool.reserve_1 > 0),
+            _ => panic!("AMM should be a Uniswap V2 pool"),
+        }
+
+        Ok(())
+    }
+}
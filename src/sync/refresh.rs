@@ -0,0 +1,110 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use ethers::{providers::Middleware, types::H160};
+use tokio::{
+    sync::{
+        mpsc::{self, Receiver},
+        RwLock,
+    },
+    task::JoinHandle,
+    time::MissedTickBehavior,
+};
+
+use crate::{
+    amm::{AutomatedMarketMaker, AMM},
+    errors::AMMError,
+};
+
+// Number of errors held by the channel returned from `spawn_refresher` before new errors are dropped
+pub const REFRESH_ERROR_BUFFER: usize = 100;
+
+/// Spawns a task that syncs every AMM of `amms` every `interval`, until the returned handle is aborted.
+/// AMMs are synced from a copy taken under the read lock and written back under the write lock, so readers are only blocked while the refreshed state is swapped in.
+/// Errors of individual AMMs do not stop the task, they are sent on the returned receiver and dropped when it is full or closed.
+pub fn spawn_refresher<M: 'static + Middleware>(
+    amms: Arc<RwLock<Vec<AMM>>>,
+    interval: Duration,
+    middleware: Arc<M>,
+) -> (JoinHandle<()>, Receiver<AMMError<M>>) {
+    let (error_tx, error_rx) = mpsc::channel(REFRESH_ERROR_BUFFER);
+
+    let handle = tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let snapshot = amms.read().await.clone();
+
+            let mut refreshed: HashMap<H160, AMM> = HashMap::new();
+            for mut amm in snapshot {
+                match amm.sync(middleware.clone()).await {
+                    Ok(()) => {
+                        refreshed.insert(amm.address(), amm);
+                    }
+                    Err(e) => {
+                        let _ = error_tx.try_send(e);
+                    }
+                }
+            }
+
+            //AMMs added or removed since the snapshot are left as they are
+            let mut state = amms.write().await;
+            for amm in state.iter_mut() {
+                if let Some(refreshed_amm) = refreshed.remove(&amm.address()) {
+                    *amm = refreshed_amm;
+                }
+            }
+        }
+    });
+
+    (handle, error_rx)
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use std::{str::FromStr, sync::Arc, time::Duration};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::H160,
+    };
+    use tokio::sync::RwLock;
+
+    use crate::amm::{uniswap_v2::UniswapV2Pool, AMM};
+
+    use super::spawn_refresher;
+
+    #[tokio::test]
+    async fn test_spawn_refresher() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        //USDC/WETH pair with stale reserves
+        let amms = Arc::new(RwLock::new(vec![AMM::UniswapV2Pool(UniswapV2Pool {
+            address: H160::from_str("0xB4e16d0168e52d35CaCD2c6185b44281Ec28C9Dc")?,
+            token_a: H160::from_str("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48")?,
+            token_a_decimals: 6,
+            token_b: H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2")?,
+            token_b_decimals: 18,
+            fee: 300,
+            ..Default::default()
+        })]));
+
+        let (handle, mut errors) =
+            spawn_refresher(amms.clone(), Duration::from_millis(100), middleware);
+
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        handle.abort();
+
+        let AMM::UniswapV2Pool(pool) = &amms.read().await[0] else {
+            panic!("Expected a Uniswap V2 pool");
+        };
+        assert_ne!(pool.reserve_0, 0);
+        assert_ne!(pool.reserve_1, 0);
+        assert!(errors.try_recv().is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+
+use ethers::{providers::Middleware, types::H160};
+use futures::stream::{self, Stream, StreamExt};
+
+use crate::{
+    amm::{
+        factory::{AutomatedMarketMakerFactory, Factory},
+        AMM,
+    },
+    errors::AMMError,
+};
+
+use super::remove_empty_amms;
+
+/// AMMs created by a factory within a block window, as yielded by `sync_amms_stream`.
+#[derive(Debug, Clone)]
+pub struct SyncWindow {
+    pub factory: H160,
+    /// First block of the window
+    pub from_block: u64,
+    /// Last block of the window, inclusive
+    pub to_block: u64,
+    /// AMMs created within the window, populated as of the last block of the sync
+    pub amms: Vec<AMM>,
+}
+
+/// Returns a stream of the AMMs created by each factory from its creation block up to `to_block`, one window of `step` blocks at a time.
+/// Windows are only fetched and populated when the stream is polled, one at a time, so a slow consumer pauses the sync and memory stays bounded to a single window.
+/// The block range of each window is returned along with its AMMs, so that a sync can be resumed from the last window consumed.
+pub fn sync_amms_stream<M: 'static + Middleware>(
+    factories: Vec<Factory>,
+    to_block: u64,
+    step: u64,
+    middleware: Arc<M>,
+) -> impl Stream<Item = Result<SyncWindow, AMMError<M>>> {
+    let step = step.max(1);
+
+    let windows = factories.into_iter().flat_map(move |factory| {
+        (factory.creation_block()..=to_block)
+            .step_by(step as usize)
+            .map(move |from_block| {
+                (
+                    factory.clone(),
+                    from_block,
+                    (from_block + step - 1).min(to_block),
+                )
+            })
+    });
+
+    stream::iter(windows).then(move |(factory, from_block, window_end)| {
+        let middleware = middleware.clone();
+
+        async move {
+            //Fetch the window in a single request so that the last window does not overshoot `to_block`
+            let window_size = window_end - from_block + 1;
+            let mut amms = factory
+                .get_all_pools_from_logs(
+                    from_block,
+                    window_end + 1,
+                    window_size,
+                    middleware.clone(),
+                )
+                .await?;
+
+            if !amms.is_empty() {
+                factory
+                    .populate_amm_data(&mut amms, Some(to_block), middleware)
+                    .await?;
+            }
+
+            Ok(SyncWindow {
+                factory: factory.address(),
+                from_block,
+                to_block: window_end,
+                amms: remove_empty_amms(amms),
+            })
+        }
+    })
+}
+
+#[cfg(all(test, feature = "uniswap-v2"))]
+mod tests {
+    use std::{str::FromStr, sync::Arc};
+
+    use ethers::{
+        providers::{Http, Provider},
+        types::H160,
+    };
+    use futures::StreamExt;
+
+    use crate::amm::{factory::Factory, uniswap_v2::factory::UniswapV2Factory};
+
+    use super::sync_amms_stream;
+
+    #[tokio::test]
+    async fn test_sync_amms_stream() -> eyre::Result<()> {
+        let rpc_endpoint = std::env::var("ETHEREUM_RPC_ENDPOINT")?;
+        let middleware = Arc::new(Provider::<Http>::try_from(rpc_endpoint)?);
+
+        let factory = Factory::UniswapV2Factory(UniswapV2Factory::new(
+            H160::from_str("0x5C69bEe701ef814a2B6a3EDD4B1652CB9cc5aA6f")?,
+            10000835,
+            300,
+        ));
+
+        let to_block = 10010834;
+        let windows = sync_amms_stream(vec![factory], to_block, 2000, middleware)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+
+        //Windows cover the range without gaps or overlaps
+        assert_eq!(windows.len(), 5);
+        assert_eq!(windows[0].from_block, 10000835);
+        for pair in windows.windows(2) {
+            assert_eq!(pair[1].from_block, pair[0].to_block + 1);
+        }
+        assert_eq!(windows[4].to_block, to_block);
+
+        assert!(windows.iter().any(|window| !window.amms.is_empty()));
+
+        Ok(())
+    }
+}